@@ -0,0 +1,71 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+use core_ledger::{
+    verify_transactions, Blockchain, ConsensusConfig, MerkleTree, Transaction, Workload,
+};
+
+const ACCOUNTS: usize = 1_000;
+const BLOCK_SIZE: usize = 1_000;
+const ZIPF_EXPONENT: f64 = 1.1;
+
+fn signing(c: &mut Criterion) {
+    let mut workload = Workload::new(ACCOUNTS, ZIPF_EXPONENT, 1);
+    let tx = workload.transfers(1, false).unwrap().remove(0);
+    let private_key = workload.accounts()[0].0;
+    c.bench_function("sign transaction", |b| {
+        b.iter_batched(
+            || tx.clone(),
+            |mut tx| tx.sign(&private_key).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+
+    let signed = workload.transfers(1, true).unwrap().remove(0);
+    c.bench_function("verify transaction", |b| {
+        b.iter(|| black_box(&signed).verify().unwrap())
+    });
+}
+
+fn blocks(c: &mut Criterion) {
+    let mut workload = Workload::new(ACCOUNTS, ZIPF_EXPONENT, 2);
+    let transfers = workload.transfers(BLOCK_SIZE, true).unwrap();
+
+    c.bench_function("build block", |b| {
+        b.iter_batched(
+            || (workload.blockchain().unwrap(), transfers.clone()),
+            |(mut blockchain, transfers)| {
+                for tx in transfers {
+                    blockchain.add_transaction(tx).unwrap();
+                }
+                blockchain.finalize_and_mint_pending_block().unwrap();
+                blockchain
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    let mut blockchain = workload.blockchain().unwrap();
+    for tx in transfers.iter().cloned() {
+        blockchain.add_transaction(tx).unwrap();
+    }
+    blockchain.finalize_and_mint_pending_block().unwrap();
+    let chain = blockchain.blocks().unwrap();
+    c.bench_function("import block", |b| {
+        b.iter_batched(
+            || chain.clone(),
+            |chain| Blockchain::from_blocks(chain, ConsensusConfig::default()).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function("verify block transactions", |b| {
+        b.iter(|| verify_transactions(black_box(&transfers)).unwrap())
+    });
+
+    c.bench_function("merkle root", |b| {
+        b.iter(|| MerkleTree::<Transaction>::new(black_box(&transfers)).root())
+    });
+}
+
+criterion_group!(benches, signing, blocks);
+criterion_main!(benches);
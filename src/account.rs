@@ -1,29 +1,43 @@
-use crate::crypto::PublicKey;
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
 
-#[derive(Debug, Clone)]
+use crate::address::Address;
+use crate::error::TxError;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Account {
-    address: PublicKey,
+    address: Address,
     balance: u64,
     nonce: u64,
 }
 
-impl From<PublicKey> for Account {
-    fn from(address: PublicKey) -> Self {
+impl From<Address> for Account {
+    fn from(address: Address) -> Self {
         Account::new(&address)
     }
 }
 
 impl Account {
-    pub fn new(address: &PublicKey) -> Account {
+    pub fn new(address: &Address) -> Account {
         Account {
-            address: address.clone(),
+            address: *address,
             balance: 0,
             nonce: 0,
         }
     }
 
-    pub fn address(&self) -> PublicKey {
-        self.address.clone()
+    /// account as of a snapshot, bypassing the transactions that led there
+    #[cfg(any(test, feature = "test-utils"))]
+    pub(crate) fn from_parts(address: &Address, balance: u64, nonce: u64) -> Account {
+        Account {
+            address: *address,
+            balance,
+            nonce,
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
     }
 
     pub fn balance(&self) -> u64 {
@@ -38,11 +52,19 @@ impl Account {
         self.nonce += 1;
     }
 
-    pub fn transfer(&mut self, amount: u64) {
-        self.balance -= amount;
+    pub fn transfer(&mut self, amount: u64) -> Result<(), TxError> {
+        self.balance = self
+            .balance
+            .checked_sub(amount)
+            .ok_or(TxError::InsufficientFunds {
+                balance: self.balance,
+                required: amount,
+            })?;
+        Ok(())
     }
 
-    pub fn deposit(&mut self, amount: u64) {
-        self.balance += amount;
+    pub fn deposit(&mut self, amount: u64) -> Result<(), TxError> {
+        self.balance = self.balance.checked_add(amount).ok_or(TxError::Overflow)?;
+        Ok(())
     }
 }
@@ -0,0 +1,226 @@
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::consensus::Network;
+use crate::crypto::{CryptoError, Hash, PublicKey};
+
+pub(crate) const ADDRESS_LEN: usize = 20;
+const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32_CHECKSUM_LEN: usize = 6;
+
+/// Short identifier of an account, the first 20 bytes of the hash of its
+/// public key. Shown as bech32 (BIP-173) under the prefix of a `Network` so
+/// a mistyped address is caught by its checksum, and one of another network
+/// by its prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Address([u8; ADDRESS_LEN]);
+
+impl Address {
+    pub fn from_slice(bytes: &[u8]) -> Result<Address, CryptoError> {
+        let bytes = bytes.try_into().map_err(|_| CryptoError::InvalidLength {
+            expected: ADDRESS_LEN,
+            got: bytes.len(),
+        })?;
+        Ok(Address(bytes))
+    }
+
+    /// bech32 form under the prefix of `network`
+    pub fn encode(&self, network: Network) -> String {
+        bech32_encode(network.hrp(), &self.0)
+    }
+
+    /// Parse the bech32 form, any case but not mixed. An address of another
+    /// network is rejected even with a valid checksum.
+    pub fn decode(s: &str, network: Network) -> Result<Address, CryptoError> {
+        let (hrp, data) = bech32_decode(s.trim())?;
+        if hrp != network.hrp() {
+            let error = match Network::ALL.iter().find(|other| other.hrp() == hrp) {
+                Some(other) => format!("Address of {:?}, expected {:?}", other, network),
+                None => format!("Unexpected address prefix {}", hrp),
+            };
+            return Err(CryptoError::InvalidEncoding(error));
+        }
+        Address::from_slice(&data)
+    }
+}
+
+impl From<&PublicKey> for Address {
+    fn from(public_key: &PublicKey) -> Self {
+        let hash = Hash::from(public_key.as_ref());
+        let mut bytes = [0; ADDRESS_LEN];
+        bytes.copy_from_slice(&hash.as_ref()[..ADDRESS_LEN]);
+        Address(bytes)
+    }
+}
+
+impl PublicKey {
+    pub fn address(&self) -> Address {
+        Address::from(self)
+    }
+}
+
+impl AsRef<[u8]> for Address {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::LowerHex for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut checksum = 1u32;
+    for value in values {
+        let top = checksum >> 25;
+        checksum = (checksum & 0x1ffffff) << 5 ^ *value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    let bytes = hrp.as_bytes();
+    let high = bytes.iter().map(|c| c >> 5);
+    let low = bytes.iter().map(|c| c & 31);
+    high.chain([0]).chain(low).collect()
+}
+
+/// regroup `data` from `from` bits to `to` bits per value, padding the last
+/// value when encoding and rejecting non zero padding when decoding
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Result<Vec<u8>, CryptoError> {
+    let invalid = || CryptoError::InvalidEncoding("Invalid bech32 padding".to_string());
+    let max = (1u32 << to) - 1;
+    let (mut accumulator, mut bits) = (0u32, 0u32);
+    let mut out = Vec::new();
+    for value in data {
+        accumulator = (accumulator << from) | *value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            out.push(((accumulator >> bits) & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            out.push(((accumulator << (to - bits)) & max) as u8);
+        }
+    } else if bits >= from || (accumulator << (to - bits)) & max != 0 {
+        return Err(invalid());
+    }
+    Ok(out)
+}
+
+fn bech32_encode(hrp: &str, data: &[u8]) -> String {
+    // regrouping into fewer bits per value cannot fail
+    let mut values = convert_bits(data, 8, 5, true).unwrap_or_default();
+    let mut checked = hrp_expand(hrp);
+    checked.extend_from_slice(&values);
+    checked.extend_from_slice(&[0; BECH32_CHECKSUM_LEN]);
+    let polymod = bech32_polymod(&checked) ^ 1;
+    values.extend((0..BECH32_CHECKSUM_LEN).map(|i| ((polymod >> (5 * (5 - i))) & 31) as u8));
+    let data: String = values
+        .iter()
+        .map(|value| BECH32_CHARSET[*value as usize] as char)
+        .collect();
+    format!("{}1{}", hrp, data)
+}
+
+fn bech32_decode(s: &str) -> Result<(String, Vec<u8>), CryptoError> {
+    let invalid = |e: &str| CryptoError::InvalidEncoding(e.to_string());
+    if s.chars().any(|c| c.is_ascii_lowercase()) && s.chars().any(|c| c.is_ascii_uppercase()) {
+        return Err(invalid("Mixed case bech32"));
+    }
+    let s = s.to_ascii_lowercase();
+    let (hrp, data) = s
+        .rsplit_once('1')
+        .ok_or_else(|| invalid("Missing bech32 separator"))?;
+    if hrp.is_empty() || data.len() < BECH32_CHECKSUM_LEN {
+        return Err(invalid("Bech32 string too short"));
+    }
+    let values = data
+        .bytes()
+        .map(|c| {
+            BECH32_CHARSET
+                .iter()
+                .position(|x| *x == c)
+                .map(|value| value as u8)
+                .ok_or_else(|| invalid("Invalid bech32 character"))
+        })
+        .collect::<Result<Vec<u8>, CryptoError>>()?;
+    let mut checked = hrp_expand(hrp);
+    checked.extend_from_slice(&values);
+    if bech32_polymod(&checked) != 1 {
+        return Err(invalid("Invalid bech32 checksum"));
+    }
+    let data = convert_bits(&values[..values.len() - BECH32_CHECKSUM_LEN], 5, 8, false)?;
+    Ok((hrp.to_string(), data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keypair;
+
+    #[test]
+    fn test_bech32_vectors() {
+        // valid checksums from BIP-173
+        assert_eq!(
+            bech32_decode("A12UEL5L").unwrap(),
+            ("a".to_string(), vec![])
+        );
+        assert_eq!(bech32_encode("a", &[]), "a12uel5l");
+        assert!(bech32_decode("abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxw").is_ok());
+        assert!(bech32_decode("a12uel5m").is_err());
+        assert!(bech32_decode("A12uel5l").is_err());
+    }
+
+    #[test]
+    fn test_address_round_trip() {
+        let (_, public_key) = generate_keypair();
+        let address = public_key.address();
+        let text = address.encode(Network::Mainnet);
+        assert!(text.starts_with("cl1"));
+        assert_eq!(Address::decode(&text, Network::Mainnet), Ok(address));
+        assert_eq!(
+            Address::decode(&text.to_uppercase(), Network::Mainnet),
+            Ok(address)
+        );
+        assert_eq!(format!("{:x}", address).len(), 2 * ADDRESS_LEN);
+
+        let mut typo = text.clone().into_bytes();
+        let last = typo.len() - 1;
+        typo[last] = if typo[last] == b'q' { b'p' } else { b'q' };
+        let typo = String::from_utf8(typo).unwrap();
+        assert!(Address::decode(&typo, Network::Mainnet).is_err());
+        let unknown = bech32_encode("xx", address.as_ref());
+        assert!(Address::decode(&unknown, Network::Mainnet).is_err());
+    }
+
+    #[test]
+    fn test_address_network_prefix() {
+        let (_, public_key) = generate_keypair();
+        let address = public_key.address();
+        let testnet = address.encode(Network::Testnet);
+        assert!(testnet.starts_with("tcl1"));
+        assert_eq!(Address::decode(&testnet, Network::Testnet), Ok(address));
+        // valid checksum, wrong network
+        assert_eq!(
+            Address::decode(&testnet, Network::Mainnet),
+            Err(CryptoError::InvalidEncoding(
+                "Address of Testnet, expected Mainnet".to_string()
+            ))
+        );
+        assert!(Address::decode(&address.encode(Network::Mainnet), Network::Testnet).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+use crate::commitment::MerkleStateCommitment;
+use crate::consensus::ConsensusConfig;
+use crate::crypto::Hash;
+use crate::encoding;
+use crate::error::{BlockError, LedgerError, StateError, TxError};
+use crate::state::StateSnapshot;
+use crate::transaction::Transaction;
+
+const BACKUP_MAGIC: &[u8; 4] = b"CLBK";
+const BACKUP_VERSION: u8 = 1;
+
+#[derive(Debug)]
+pub enum BackupError {
+    Io(io::Error),
+    NotABackup,
+    UnsupportedVersion(u8),
+    ChecksumMismatch,
+    Encoding(String),
+    Ledger(LedgerError),
+    /// the pending block of the backup can not be rebuilt
+    Block(BlockError),
+    Transaction(TxError),
+    /// the replayed chain does not lead to the state of the backup
+    StateMismatch(StateError),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackupError::Io(e) => write!(f, "{}", e),
+            BackupError::NotABackup => write!(f, "Not a backup file"),
+            BackupError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported backup version {}", version)
+            }
+            BackupError::ChecksumMismatch => write!(f, "Backup checksum mismatch"),
+            BackupError::Encoding(e) => write!(f, "Encoding error: {}", e),
+            BackupError::Ledger(e) => write!(f, "{}", e),
+            BackupError::Block(e) => write!(f, "{}", e),
+            BackupError::Transaction(e) => write!(f, "{}", e),
+            BackupError::StateMismatch(e) => {
+                write!(f, "Backup state does not match the replayed chain: {}", e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+impl From<io::Error> for BackupError {
+    fn from(e: io::Error) -> Self {
+        BackupError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for BackupError {
+    fn from(e: bincode::Error) -> Self {
+        BackupError::Encoding(e.to_string())
+    }
+}
+
+impl From<LedgerError> for BackupError {
+    fn from(e: LedgerError) -> Self {
+        BackupError::Ledger(e)
+    }
+}
+
+/// Indices are not stored, they are rebuilt when the chain is replayed
+#[derive(Deserialize)]
+struct Backup {
+    blocks: Vec<Block>,
+    pending_transactions: Vec<Transaction>,
+    pending_extra_data: Vec<u8>,
+    state: StateSnapshot,
+}
+
+/// borrowed form of `Backup` with the same encoding, so writing a backup
+/// does not copy the pending block
+#[derive(Serialize)]
+struct BackupRef<'a> {
+    blocks: &'a [Block],
+    pending_transactions: &'a [Transaction],
+    pending_extra_data: &'a [u8],
+    state: StateSnapshot,
+}
+
+/// Write `data` to `path`: next to it first, then renamed once synced, so
+/// `path` never holds a partial file
+pub(crate) fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)
+}
+
+impl Blockchain {
+    /// Write a snapshot of the blocks, pending block and state to `path`:
+    /// magic, version, body and the hash of the body as checksum. The file is
+    /// written atomically.
+    pub fn backup_to(&self, path: impl AsRef<Path>) -> Result<(), BackupError> {
+        let data = self.to_backup_bytes()?;
+        Ok(write_atomically(path.as_ref(), &data)?)
+    }
+
+    /// in memory form of the file written by `backup_to`
+    pub fn to_backup_bytes(&self) -> Result<Vec<u8>, BackupError> {
+        let blocks = self.blocks()?;
+        let backup = BackupRef {
+            blocks: &blocks,
+            pending_transactions: self.pending_block().transactions(),
+            pending_extra_data: self.pending_block().extra_data(),
+            state: self.state_snapshot(),
+        };
+        let body = encoding::encode(&backup)?;
+        let checksum = Hash::from(body.as_ref());
+        Ok([
+            BACKUP_MAGIC.as_ref(),
+            &[BACKUP_VERSION],
+            &body,
+            checksum.as_ref(),
+        ]
+        .concat())
+    }
+
+    /// Load a snapshot written by `backup_to`. The checksum is verified, the
+    /// chain is replayed from genesis and the resulting state must match the
+    /// snapshot state before it is adopted. The blocks are checked under
+    /// `consensus`, the rules they were produced under.
+    pub fn restore_from(
+        path: impl AsRef<Path>,
+        consensus: ConsensusConfig,
+    ) -> Result<Blockchain, BackupError> {
+        let data = fs::read(path)?;
+        Blockchain::from_backup_bytes(&data, consensus)
+    }
+
+    /// counterpart of `to_backup_bytes`, with the checks of `restore_from`
+    pub fn from_backup_bytes(
+        data: &[u8],
+        consensus: ConsensusConfig,
+    ) -> Result<Blockchain, BackupError> {
+        let header_len = BACKUP_MAGIC.len() + 1;
+        if data.len() < header_len + 32 || &data[..4] != BACKUP_MAGIC {
+            return Err(BackupError::NotABackup);
+        }
+        if data[4] != BACKUP_VERSION {
+            return Err(BackupError::UnsupportedVersion(data[4]));
+        }
+        let (body, checksum) = data[header_len..].split_at(data.len() - header_len - 32);
+        if Hash::from(body).as_ref() != checksum {
+            return Err(BackupError::ChecksumMismatch);
+        }
+        let backup: Backup = encoding::decode(body)?;
+
+        let commitment = Box::new(MerkleStateCommitment::new());
+        let mut blockchain =
+            Blockchain::replay(backup.blocks, consensus, commitment, Some(&backup.state))?;
+        blockchain
+            .set_extra_data(&backup.pending_extra_data)
+            .map_err(BackupError::Block)?;
+        for tx in backup.pending_transactions {
+            blockchain
+                .add_transaction(tx)
+                .map_err(BackupError::Transaction)?;
+        }
+        blockchain
+            .state_snapshot()
+            .check_matches(&backup.state)
+            .map_err(BackupError::StateMismatch)?;
+        Ok(blockchain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keypair_from_secret;
+
+    #[test]
+    fn test_backup_and_restore() {
+        let (private_key, public_key) = generate_keypair_from_secret(b"backup");
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let genesis =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 1000, 0, &private_key)
+                .unwrap();
+        let mut blockchain = Blockchain::new(genesis, 0).unwrap();
+        let tx =
+            Transaction::new_and_sign(&public_key, &other.address(), 10, 0, &private_key).unwrap();
+        blockchain.add_transaction(tx).unwrap();
+        blockchain.finalize_and_mint_pending_block().unwrap();
+        let tx =
+            Transaction::new_and_sign(&public_key, &other.address(), 20, 1, &private_key).unwrap();
+        blockchain.add_transaction(tx).unwrap();
+
+        let path = std::env::temp_dir().join(format!("core-ledger-{}.bak", std::process::id()));
+        blockchain.backup_to(&path).unwrap();
+        let restored = Blockchain::restore_from(&path, ConsensusConfig::default()).unwrap();
+        assert_eq!(restored.blocks(), blockchain.blocks());
+        assert_eq!(
+            restored.get_account(&other.address()).unwrap().balance(),
+            30
+        );
+        assert_eq!(restored.pending_block().transactions().len(), 1);
+
+        let mut data = fs::read(&path).unwrap();
+        data[10] ^= 1;
+        fs::write(&path, data).unwrap();
+        assert!(matches!(
+            Blockchain::restore_from(&path, ConsensusConfig::default()),
+            Err(BackupError::ChecksumMismatch)
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,124 @@
+//! Workload generators shared by the benchmarks in `benches/`
+
+use secp256k1::rand::rngs::StdRng;
+use secp256k1::rand::{Rng, SeedableRng};
+
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+use crate::consensus::ConsensusConfig;
+use crate::crypto::{generate_keypair_from_secret, CryptoError, PrivateKey, PublicKey};
+use crate::error::{LedgerError, TxError};
+use crate::transaction::Transaction;
+
+/// balance given to every account at genesis
+pub const WORKLOAD_BALANCE: u64 = 1_000_000_000;
+
+/// Funded accounts exchanging transfers, recipients follow a Zipf
+/// distribution so a few accounts receive most of the traffic
+pub struct Workload {
+    keys: Vec<(PrivateKey, PublicKey)>,
+    nonces: Vec<u64>,
+    /// cumulative Zipf weights of the recipients, ranked by account index
+    cumulative_weights: Vec<f64>,
+    rng: StdRng,
+}
+
+impl Workload {
+    pub fn new(accounts: usize, zipf_exponent: f64, seed: u64) -> Workload {
+        let accounts = accounts.max(1);
+        let keys = (0..accounts)
+            .map(|i| generate_keypair_from_secret(format!("bench-{}", i).as_bytes()))
+            .collect();
+        let mut total = 0.0;
+        let cumulative_weights = (1..=accounts)
+            .map(|rank| {
+                total += 1.0 / (rank as f64).powf(zipf_exponent);
+                total
+            })
+            .collect();
+        Workload {
+            keys,
+            nonces: vec![0; accounts],
+            cumulative_weights,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn accounts(&self) -> &[(PrivateKey, PublicKey)] {
+        &self.keys
+    }
+
+    /// genesis block funding every account with `WORKLOAD_BALANCE`
+    pub fn genesis(&self) -> Result<Block, LedgerError> {
+        let transactions = self
+            .keys
+            .iter()
+            .map(|(private_key, public_key)| {
+                Transaction::new_and_sign(
+                    public_key,
+                    &public_key.address(),
+                    WORKLOAD_BALANCE,
+                    0,
+                    private_key,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(TxError::from)?;
+        Ok(Block::new_genesis(transactions, 0)?)
+    }
+
+    pub fn blockchain(&self) -> Result<Blockchain, LedgerError> {
+        Blockchain::from_blocks(vec![self.genesis()?], ConsensusConfig::default())
+    }
+
+    fn zipf_recipient(&mut self) -> usize {
+        let total = self.cumulative_weights.last().copied().unwrap_or(0.0);
+        let sample = self.rng.gen_range(0.0..total);
+        self.cumulative_weights
+            .partition_point(|weight| *weight <= sample)
+            .min(self.keys.len() - 1)
+    }
+
+    /// `signed` transfers are valid in order on top of `genesis`, unsigned
+    /// ones are left for signing benchmarks
+    pub fn transfers(
+        &mut self,
+        count: usize,
+        signed: bool,
+    ) -> Result<Vec<Transaction>, CryptoError> {
+        (0..count)
+            .map(|_| {
+                let sender = self.rng.gen_range(0..self.keys.len());
+                let recipient = self.zipf_recipient();
+                let (private_key, from) = self.keys[sender];
+                let to = self.keys[recipient].1.address();
+                let amount = self.rng.gen_range(1..=10);
+                let nonce = self.nonces[sender];
+                self.nonces[sender] += 1;
+                let mut tx = Transaction::new(&from, &to, amount, nonce);
+                if signed {
+                    tx.sign(&private_key)?;
+                }
+                Ok(tx)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workload_is_valid_and_skewed() {
+        let mut workload = Workload::new(20, 1.2, 7);
+        let mut blockchain = workload.blockchain().unwrap();
+        let transfers = workload.transfers(200, true).unwrap();
+        let top = workload.accounts()[0].1.address();
+        let to_top = transfers.iter().filter(|tx| tx.to() == Some(top)).count();
+        assert!(to_top > 200 / 20);
+        for tx in transfers {
+            blockchain.add_transaction(tx).unwrap();
+        }
+    }
+}
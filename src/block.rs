@@ -1,16 +1,70 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
 
-use crate::crypto::Hash;
-use crate::merkle;
-use crate::transaction::Transaction;
+use crate::clock;
+use crate::crypto::{Hash, PublicKey};
+use crate::error::BlockError;
+use crate::merkle::{IncrementalMerkleTree, MerkleProof, MerkleTree};
+use crate::transaction::{verify_transactions, Transaction, TxId};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq)]
+/// maximum size of the producer-settable `extra_data` header field
+pub const MAX_EXTRA_DATA_SIZE: usize = 32;
+
+/// maximum number of transactions in a block
+pub const MAX_BLOCK_TRANSACTIONS: usize = 4096;
+
+/// maximum number of uncles a block references
+pub const MAX_UNCLES: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Block {
     index: u64,
     timestamp: u64,
     previous_hash: Hash,
     transactions_root: Hash,
+    /// MMR root over the hashes of every previous block
+    history_root: Hash,
+    /// state commitment root after executing the transactions, default for
+    /// the genesis block
+    state_root: Hash,
+    extra_data: Vec<u8>,
+    /// proof of work target the hash meets, 0 on chains without proof of
+    /// work
+    difficulty: u64,
+    nonce: u64,
     transactions: Vec<Transaction>,
+    /// hash over the hashes of the uncle headers, default without uncles
+    uncles_root: Hash,
+    uncles: Vec<Uncle>,
+    hash: Hash,
+}
+
+/// Header of a stale block, mined off the chain of the block referencing
+/// it, and the coinbase of the stale block proven part of it, crediting
+/// its producer with the uncle reward
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Uncle {
+    header: BlockHeader,
+    coinbase: Transaction,
+    coinbase_proof: MerkleProof,
+}
+
+/// Block without its transactions, what a light client keeps of it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockHeader {
+    index: u64,
+    timestamp: u64,
+    previous_hash: Hash,
+    transactions_root: Hash,
+    /// number of leaves under `transactions_root`, needed to check a
+    /// transaction proof
+    transaction_count: u64,
+    history_root: Hash,
+    state_root: Hash,
+    extra_data: Vec<u8>,
+    difficulty: u64,
+    nonce: u64,
+    uncles_root: Hash,
     hash: Hash,
 }
 
@@ -18,104 +72,383 @@ pub struct Block {
 pub struct BlockBuilder {
     index: u64,
     previous_hash: Hash,
+    history_root: Hash,
+    state_root: Hash,
+    extra_data: Vec<u8>,
+    difficulty: u64,
+    nonce: u64,
     transactions: Vec<Transaction>,
+    /// kept up to date as transactions are added
+    transactions_tree: IncrementalMerkleTree<Transaction>,
+    uncles: Vec<Uncle>,
+    /// kept up to date as uncles are added
+    uncles_root: Hash,
+}
+
+/// header fields covered by the block hash, nonce and transactions root
+/// aside
+struct Header<'a> {
+    index: u64,
+    timestamp: u64,
+    previous_hash: &'a Hash,
+    transaction_count: u64,
+    history_root: &'a Hash,
+    state_root: &'a Hash,
+    extra_data: &'a [u8],
+    difficulty: u64,
+    uncles_root: &'a Hash,
+}
+
+fn header_hash(header: &Header, transactions_root: &Hash, nonce: u64) -> Hash {
+    let timestamp_bytes = header.timestamp.to_be_bytes();
+    let index_bytes = header.index.to_be_bytes();
+    let count_bytes = header.transaction_count.to_be_bytes();
+    let difficulty_bytes = header.difficulty.to_be_bytes();
+    let nonce_bytes = nonce.to_be_bytes();
+    Hash::from_parts(&[
+        &index_bytes,
+        &timestamp_bytes,
+        header.previous_hash.as_ref(),
+        transactions_root.as_ref(),
+        &count_bytes,
+        header.history_root.as_ref(),
+        header.state_root.as_ref(),
+        header.extra_data,
+        &difficulty_bytes,
+        &nonce_bytes,
+        header.uncles_root.as_ref(),
+    ])
+}
+
+fn uncles_root(uncles: &[Uncle]) -> Hash {
+    if uncles.is_empty() {
+        return Hash::default();
+    }
+    let hashes: Vec<Hash> = uncles.iter().map(|uncle| uncle.header.hash).collect();
+    let parts: Vec<&[u8]> = hashes.iter().map(|hash| hash.as_ref()).collect();
+    Hash::from_parts(&parts)
+}
+
+/// merkle root of the transaction ids, hashed in parallel when the
+/// `parallel` feature is enabled
+fn transactions_root(transactions: &[Transaction]) -> Hash {
+    #[cfg(feature = "parallel")]
+    {
+        MerkleTree::<Transaction>::new_parallel(transactions).root_parallel()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        MerkleTree::<Transaction>::new(transactions).into_root()
+    }
+}
+
+fn check_extra_data(extra_data: &[u8]) -> Result<(), BlockError> {
+    if extra_data.len() > MAX_EXTRA_DATA_SIZE {
+        return Err(BlockError::ExtraDataTooLarge {
+            size: extra_data.len(),
+            max: MAX_EXTRA_DATA_SIZE,
+        });
+    }
+    Ok(())
 }
 
 impl BlockBuilder {
     pub fn new(index: u64, previous_hash: &Hash) -> BlockBuilder {
         BlockBuilder {
             index,
-            previous_hash: previous_hash.clone(),
+            previous_hash: *previous_hash,
+            history_root: Hash::default(),
+            state_root: Hash::default(),
+            extra_data: vec![],
+            difficulty: 0,
+            nonce: 0,
             transactions: vec![],
+            transactions_tree: IncrementalMerkleTree::new(),
+            uncles: vec![],
+            uncles_root: Hash::default(),
+        }
+    }
+
+    pub fn add_transaction(&mut self, transaction: Transaction) -> Result<(), BlockError> {
+        if self.is_full() {
+            return Err(BlockError::Full);
         }
+        self.transactions_tree.push(&transaction);
+        self.transactions.push(transaction);
+        Ok(())
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.transactions.len() >= MAX_BLOCK_TRANSACTIONS
+    }
+
+    /// reference a stale block, checked against the chain when the block
+    /// is executed
+    pub fn add_uncle(&mut self, uncle: Uncle) -> Result<(), BlockError> {
+        if self.uncles.len() >= MAX_UNCLES {
+            return Err(BlockError::TooManyUncles {
+                count: self.uncles.len() + 1,
+                max: MAX_UNCLES,
+            });
+        }
+        self.uncles.push(uncle);
+        self.uncles_root = uncles_root(&self.uncles);
+        Ok(())
+    }
+
+    pub fn uncles(&self) -> &[Uncle] {
+        &self.uncles
+    }
+
+    /// set the free-form header data, e.g. producer identification
+    pub fn set_extra_data(&mut self, extra_data: &[u8]) -> Result<(), BlockError> {
+        check_extra_data(extra_data)?;
+        self.extra_data = extra_data.to_vec();
+        Ok(())
+    }
+
+    /// commit to the history of the chain this block extends
+    pub fn set_history_root(&mut self, history_root: &Hash) {
+        self.history_root = *history_root;
+    }
+
+    /// commit to the state reached by executing the transactions, set once
+    /// the last one is added
+    pub fn set_state_root(&mut self, state_root: &Hash) {
+        self.state_root = *state_root;
     }
 
-    pub fn add_transaction(&mut self, transaction: &Transaction) {
-        self.transactions.push(transaction.clone());
+    /// difficulty committed in the header and nonce meeting it, see
+    /// `Miner::mine_block`
+    pub fn set_work(&mut self, difficulty: u64, nonce: u64) {
+        self.difficulty = difficulty;
+        self.nonce = nonce;
     }
 
     pub fn hash(&self, timestamp: u64) -> Hash {
-        let timestamp_bytes = timestamp.to_be_bytes();
-        let index_bytes = self.index.to_be_bytes();
-        let transactions_root = self.transactions_root();
-        let data = [
-            &index_bytes[..],
-            &timestamp_bytes[..],
-            self.previous_hash.as_ref(),
-            transactions_root.as_ref(),
-        ];
-        Hash::from(data.concat().as_ref())
+        self.hash_with_nonce(timestamp, self.nonce)
+    }
+
+    /// Hash of the block built at `timestamp` with `nonce`. Mining calls it
+    /// for every nonce tried, only the nonce bytes change between calls.
+    pub fn hash_with_nonce(&self, timestamp: u64, nonce: u64) -> Hash {
+        header_hash(&self.header(timestamp), &self.current_root(), nonce)
+    }
+
+    fn header(&self, timestamp: u64) -> Header<'_> {
+        Header {
+            index: self.index,
+            timestamp,
+            previous_hash: &self.previous_hash,
+            transaction_count: self.transactions.len() as u64,
+            history_root: &self.history_root,
+            state_root: &self.state_root,
+            extra_data: &self.extra_data,
+            difficulty: self.difficulty,
+            uncles_root: &self.uncles_root,
+        }
+    }
+
+    /// Builder for the block following `block`, keeping the extra data. The
+    /// transaction list is sized after `block`, blocks tend to be of similar
+    /// size.
+    pub fn next(&self, block: &Block, history_root: &Hash) -> BlockBuilder {
+        BlockBuilder {
+            index: block.index + 1,
+            previous_hash: block.hash,
+            history_root: *history_root,
+            state_root: Hash::default(),
+            extra_data: self.extra_data.clone(),
+            difficulty: 0,
+            nonce: 0,
+            transactions: Vec::with_capacity(block.transactions.len()),
+            transactions_tree: IncrementalMerkleTree::with_capacity(block.transactions.len()),
+            uncles: vec![],
+            uncles_root: Hash::default(),
+        }
     }
 
     pub fn build(self) -> Block {
         Block::from(self)
     }
 
-    pub fn transactions(&self) -> &Vec<Transaction> {
+    /// build the block and leave an empty builder with the same header
+    /// fields, without cloning the transactions
+    pub(crate) fn take(&mut self, timestamp: u64) -> Block {
+        let empty = BlockBuilder {
+            index: self.index,
+            previous_hash: self.previous_hash,
+            history_root: self.history_root,
+            state_root: Hash::default(),
+            extra_data: self.extra_data.clone(),
+            difficulty: 0,
+            nonce: 0,
+            transactions: vec![],
+            transactions_tree: IncrementalMerkleTree::new(),
+            uncles: vec![],
+            uncles_root: Hash::default(),
+        };
+        std::mem::replace(self, empty).build_at(timestamp)
+    }
+
+    pub fn build_at(self, timestamp: u64) -> Block {
+        let transactions_root = self.current_root();
+        let hash = header_hash(&self.header(timestamp), &transactions_root, self.nonce);
+        Block {
+            index: self.index,
+            timestamp,
+            previous_hash: self.previous_hash,
+            transactions_root,
+            history_root: self.history_root,
+            state_root: self.state_root,
+            extra_data: self.extra_data,
+            difficulty: self.difficulty,
+            nonce: self.nonce,
+            transactions: self.transactions,
+            uncles_root: self.uncles_root,
+            uncles: self.uncles,
+            hash,
+        }
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
         &self.transactions
     }
 
+    pub fn into_transactions(self) -> Vec<Transaction> {
+        self.transactions
+    }
+
     pub fn index(&self) -> u64 {
         self.index
     }
 
     pub fn previous_hash(&self) -> Hash {
-        self.previous_hash.clone()
+        self.previous_hash
     }
 
-    pub fn transactions_root(&self) -> Hash {
-        merkle::root_hash(self.transactions.iter().map(|tx| tx.hash()).collect())
+    pub fn extra_data(&self) -> &[u8] {
+        &self.extra_data
+    }
+
+    pub fn history_root(&self) -> Hash {
+        self.history_root
+    }
+
+    pub fn state_root(&self) -> Hash {
+        self.state_root
+    }
+
+    /// Proof that transaction `id` is part of the block, verified with
+    /// `MerkleProof::verify` against the transaction, `transactions_root` and
+    /// the transaction count
+    pub fn transaction_proof(&self, id: &TxId) -> Option<MerkleProof> {
+        let index = self.transactions.iter().position(|tx| tx.id() == *id)?;
+        MerkleTree::<Transaction>::new(&self.transactions).proof(index)
+    }
+
+    /// root of the transactions added so far, cheap enough to poll
+    pub fn current_root(&self) -> Hash {
+        self.transactions_tree.root()
     }
 }
 
 impl From<BlockBuilder> for Block {
     fn from(builder: BlockBuilder) -> Self {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("error getting system time")
-            .as_secs();
-        let hash = builder.hash(timestamp);
-        Self {
-            index: builder.index,
-            timestamp,
-            previous_hash: builder.previous_hash,
-            transactions_root: merkle::root_hash(
-                builder.transactions.iter().map(|tx| tx.hash()).collect(),
-            ),
-            transactions: builder.transactions,
-            hash,
-        }
+        builder.build_at(clock::now())
     }
 }
 
 impl Block {
-    pub fn new_genesis(transactions: Vec<Transaction>, timestamp: u64) -> Result<Block, String> {
+    pub fn new_genesis(
+        transactions: Vec<Transaction>,
+        timestamp: u64,
+    ) -> Result<Block, BlockError> {
         if transactions.is_empty() {
-            return Err("Genesis block must have at least one transaction".to_string());
+            return Err(BlockError::EmptyGenesis);
         }
-        transactions.iter().try_for_each(|tx| tx.verify())?;
-        let genesis_block = BlockBuilder {
-            index: 0,
-            previous_hash: Hash::default(),
-            transactions: transactions.clone(),
-        };
+        verify_transactions(&transactions).map_err(|e| BlockError::InvalidTransaction(e.into()))?;
+        let mut genesis_block = BlockBuilder::new(0, &Hash::default());
+        for tx in transactions {
+            genesis_block.add_transaction(tx)?;
+        }
+        Ok(genesis_block.build_at(timestamp))
+    }
 
-        let hash = genesis_block.hash(timestamp);
-        Ok(Block {
-            index: 0,
-            timestamp,
-            previous_hash: Hash::default(),
-            transactions_root: genesis_block.transactions_root(),
-            transactions,
-            hash,
-        })
+    /// Check the block is self consistent: header limits, transactions root
+    /// and hash. Linkage to the chain and execution are not checked here.
+    pub fn verify(&self) -> Result<(), BlockError> {
+        check_extra_data(&self.extra_data)?;
+        if self.transactions.len() > MAX_BLOCK_TRANSACTIONS {
+            return Err(BlockError::TooManyTransactions {
+                count: self.transactions.len(),
+                max: MAX_BLOCK_TRANSACTIONS,
+            });
+        }
+        let transactions_root = transactions_root(&self.transactions);
+        if transactions_root != self.transactions_root {
+            return Err(BlockError::InvalidTransactionsRoot);
+        }
+        if self.uncles.len() > MAX_UNCLES {
+            return Err(BlockError::TooManyUncles {
+                count: self.uncles.len(),
+                max: MAX_UNCLES,
+            });
+        }
+        for uncle in &self.uncles {
+            uncle.verify()?;
+        }
+        if uncles_root(&self.uncles) != self.uncles_root {
+            return Err(BlockError::InvalidUnclesRoot);
+        }
+        if header_hash(&self.header_fields(), &self.transactions_root, self.nonce) != self.hash {
+            return Err(BlockError::InvalidHash);
+        }
+        Ok(())
+    }
+
+    fn header_fields(&self) -> Header<'_> {
+        Header {
+            index: self.index,
+            timestamp: self.timestamp,
+            previous_hash: &self.previous_hash,
+            transaction_count: self.transactions.len() as u64,
+            history_root: &self.history_root,
+            state_root: &self.state_root,
+            extra_data: &self.extra_data,
+            difficulty: self.difficulty,
+            uncles_root: &self.uncles_root,
+        }
     }
 
     pub fn hash(&self) -> Hash {
-        self.hash.clone()
+        self.hash
     }
 
-    pub fn transactions(&self) -> &Vec<Transaction> {
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            index: self.index,
+            timestamp: self.timestamp,
+            previous_hash: self.previous_hash,
+            transactions_root: self.transactions_root,
+            transaction_count: self.transactions.len() as u64,
+            history_root: self.history_root,
+            state_root: self.state_root,
+            extra_data: self.extra_data.clone(),
+            difficulty: self.difficulty,
+            nonce: self.nonce,
+            uncles_root: self.uncles_root,
+            hash: self.hash,
+        }
+    }
+
+    /// alter the header so the block no longer matches its hash
+    #[cfg(any(test, feature = "fault-injection"))]
+    pub(crate) fn corrupt(&mut self) {
+        self.timestamp ^= 1;
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
         &self.transactions
     }
 
@@ -124,14 +457,204 @@ impl Block {
     }
 
     pub fn previous_hash(&self) -> Hash {
-        self.previous_hash.clone()
+        self.previous_hash
     }
 
     pub fn transactions_root(&self) -> Hash {
-        self.transactions_root.clone()
+        self.transactions_root
+    }
+
+    pub fn history_root(&self) -> Hash {
+        self.history_root
+    }
+
+    pub fn state_root(&self) -> Hash {
+        self.state_root
+    }
+
+    /// Proof that transaction `id` is part of the block, verified with
+    /// `MerkleProof::verify` against the transaction, `transactions_root` and
+    /// the transaction count
+    pub fn transaction_proof(&self, id: &TxId) -> Option<MerkleProof> {
+        let index = self.transactions.iter().position(|tx| tx.id() == *id)?;
+        MerkleTree::<Transaction>::new(&self.transactions).proof(index)
+    }
+
+    pub fn extra_data(&self) -> &[u8] {
+        &self.extra_data
     }
 
     pub fn timestamp(&self) -> u64 {
         self.timestamp
     }
+
+    pub fn difficulty(&self) -> u64 {
+        self.difficulty
+    }
+
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    pub fn uncles(&self) -> &[Uncle] {
+        &self.uncles
+    }
+}
+
+impl Uncle {
+    /// reference `block`, `None` when it has no coinbase to credit
+    pub fn new(block: &Block) -> Option<Uncle> {
+        let coinbase = block.transactions.last().filter(|tx| tx.is_coinbase())?;
+        let coinbase_proof = MerkleTree::<Transaction>::new(&block.transactions)
+            .proof(block.transactions.len() - 1)?;
+        Some(Uncle {
+            header: block.header(),
+            coinbase: coinbase.clone(),
+            coinbase_proof,
+        })
+    }
+
+    /// check the header hash and that the coinbase closes the stale block
+    pub fn verify(&self) -> Result<(), BlockError> {
+        self.header.verify().map_err(|_| BlockError::InvalidUncle)?;
+        let count = self.header.transaction_count;
+        if !self.coinbase.is_coinbase()
+            || self.coinbase.nonce() != self.header.index
+            || self.coinbase_proof.leaf_index.checked_add(1) != Some(count)
+        {
+            return Err(BlockError::InvalidUncle);
+        }
+        self.coinbase_proof
+            .verify(&self.coinbase, &self.header.transactions_root, count)
+            .map_err(|_| BlockError::InvalidUncle)
+    }
+
+    pub fn header(&self) -> &BlockHeader {
+        &self.header
+    }
+
+    /// producer of the stale block, credited by its coinbase
+    pub fn beneficiary(&self) -> PublicKey {
+        self.coinbase.from()
+    }
+
+    pub fn hash(&self) -> Hash {
+        self.header.hash
+    }
+}
+
+impl BlockHeader {
+    /// check the header limits and that the hash covers the fields
+    pub fn verify(&self) -> Result<(), BlockError> {
+        check_extra_data(&self.extra_data)?;
+        let header = Header {
+            index: self.index,
+            timestamp: self.timestamp,
+            previous_hash: &self.previous_hash,
+            transaction_count: self.transaction_count,
+            history_root: &self.history_root,
+            state_root: &self.state_root,
+            extra_data: &self.extra_data,
+            difficulty: self.difficulty,
+            uncles_root: &self.uncles_root,
+        };
+        if header_hash(&header, &self.transactions_root, self.nonce) != self.hash {
+            return Err(BlockError::InvalidHash);
+        }
+        Ok(())
+    }
+
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn previous_hash(&self) -> Hash {
+        self.previous_hash
+    }
+
+    pub fn transactions_root(&self) -> Hash {
+        self.transactions_root
+    }
+
+    pub fn transaction_count(&self) -> u64 {
+        self.transaction_count
+    }
+
+    pub fn history_root(&self) -> Hash {
+        self.history_root
+    }
+
+    pub fn state_root(&self) -> Hash {
+        self.state_root
+    }
+
+    pub fn extra_data(&self) -> &[u8] {
+        &self.extra_data
+    }
+
+    pub fn difficulty(&self) -> u64 {
+        self.difficulty
+    }
+
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    pub fn uncles_root(&self) -> Hash {
+        self.uncles_root
+    }
+
+    pub fn hash(&self) -> Hash {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extra_data() {
+        let mut builder = BlockBuilder::new(1, &Hash::default());
+        assert_eq!(
+            builder.set_extra_data(&[0; MAX_EXTRA_DATA_SIZE + 1]),
+            Err(BlockError::ExtraDataTooLarge {
+                size: MAX_EXTRA_DATA_SIZE + 1,
+                max: MAX_EXTRA_DATA_SIZE
+            })
+        );
+        let plain = builder.clone().build_at(1);
+        builder.set_extra_data(&[1; MAX_EXTRA_DATA_SIZE]).unwrap();
+        let block = builder.build_at(1);
+        block.verify().unwrap();
+        assert_eq!(block.extra_data(), &[1; MAX_EXTRA_DATA_SIZE]);
+        assert_ne!(block.hash(), plain.hash());
+
+        // covered by the hash, and bounded even when the hash matches
+        let mut tampered = block.clone();
+        tampered.extra_data = vec![2; MAX_EXTRA_DATA_SIZE];
+        assert_eq!(tampered.verify(), Err(BlockError::InvalidHash));
+        assert_eq!(tampered.header().verify(), Err(BlockError::InvalidHash));
+        tampered.extra_data = vec![1; MAX_EXTRA_DATA_SIZE + 1];
+        tampered.hash = header_hash(
+            &Header {
+                extra_data: &tampered.extra_data,
+                ..block.header_fields()
+            },
+            &tampered.transactions_root,
+            tampered.nonce,
+        );
+        assert!(matches!(
+            tampered.verify(),
+            Err(BlockError::ExtraDataTooLarge { .. })
+        ));
+        assert!(matches!(
+            tampered.header().verify(),
+            Err(BlockError::ExtraDataTooLarge { .. })
+        ));
+    }
 }
@@ -1,144 +1,2905 @@
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
 use crate::account::Account;
-use crate::block::{Block, BlockBuilder};
+use crate::address::Address;
+use crate::block::{Block, BlockBuilder, Uncle, MAX_BLOCK_TRANSACTIONS, MAX_UNCLES};
+use crate::clock::{NetworkTime, TimestampPolicy};
+use crate::commitment::{MerkleStateCommitment, StateCommitment};
+use crate::consensus::{ConsensusConfig, MAX_UNCLE_DEPTH};
 use crate::crypto::{Hash, PublicKey};
-use crate::transaction::Transaction;
-use std::collections::HashMap;
+use crate::difficulty::{meets_difficulty, next_difficulty, work};
+use crate::error::{BlockError, LedgerError, StateError, TxError, UncleError};
+use crate::grant::FeeGrant;
+use crate::memory::{self, MemoryUsage};
+use crate::mempool::{Mempool, MempoolConfig};
+use crate::miner::{BlockTemplate, CancelToken, Miner};
+use crate::mmr::{Mmr, MmrProof};
+use crate::notifier::{ChainEvent, Notifier};
+use crate::policy::{AdmissionError, FeePolicy, TransactionPolicy};
+use crate::scoring::{order_by_score, AccountView, ArrivalOrder, TransactionScorer};
+use crate::state::{AccountDiff, StateSnapshot};
+use crate::storage::{MemoryStorage, Storage, StorageBatch};
+use crate::transaction::{verify_transactions, Transaction, TransactionKind, TxId};
+use crate::validation::Validation;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// number of blocks that can be rolled back to switch to a fork
+pub const MAX_REORG_DEPTH: usize = 100;
+
+/// known blocks kept off the canonical chain, the lowest are dropped first
+pub const MAX_SIDE_BLOCKS: usize = 256;
+
+/// Transfer built by the node for an external signer: the signature over
+/// `signing_hash` is set with `Transaction::set_signature` and the result
+/// submitted as is
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsignedTransfer {
+    pub transaction: Transaction,
+    pub signing_hash: Hash,
+    /// canonical encoding of the unsigned transaction
+    pub payload: Vec<u8>,
+}
+
+/// state as it was before the changes made since the tip, by the pending
+/// block or a block being imported, so they can be rolled back
+#[derive(Default)]
+struct UndoLog {
+    accounts: HashMap<Address, Option<Account>>,
+    burned: HashMap<PublicKey, Option<u64>>,
+    spent_nonces: Vec<(PublicKey, u64)>,
+    total_supply: Option<u64>,
+    fees: Option<u64>,
+    fee_grants: HashMap<(PublicKey, PublicKey), Option<FeeGrant>>,
+    validations: HashMap<PublicKey, Option<Validation>>,
+}
 
-struct Blockchain {
-    blocks: Vec<Block>,
+pub struct Blockchain {
+    storage: Box<dyn Storage>,
+    /// last block, also in the storage
+    tip: Block,
     pending_block: BlockBuilder,
-    accounts: HashMap<PublicKey, Account>,
+    /// transactions waiting for the next block to be assembled
+    mempool: Mempool,
+    accounts: BTreeMap<Address, Account>,
+    total_supply: u64,
+    burned: BTreeMap<PublicKey, u64>,
+    /// fees paid by the transactions executed since the tip, collected by
+    /// the coinbase of the next block
+    fees: u64,
+    /// fee grants by (granter, grantee)
+    fee_grants: BTreeMap<(PublicKey, PublicKey), FeeGrant>,
+    /// conditions accounts put on their transactions
+    validations: BTreeMap<PublicKey, Validation>,
+    consensus: ConsensusConfig,
+    /// credited with the coinbase of the blocks produced here, without one
+    /// the fees are burned
+    beneficiary: Option<PublicKey>,
+    /// block index and position of every confirmed transaction
+    transaction_index: HashMap<TxId, (u64, usize)>,
+    /// transaction consuming each (sender, nonce), pending or confirmed in
+    /// the last `MAX_REORG_DEPTH` blocks
+    spent_nonces: HashMap<(PublicKey, u64), TxId>,
+    undo: UndoLog,
+    /// undo logs of the last blocks, newest last, at most `MAX_REORG_DEPTH`
+    block_undo: VecDeque<UndoLog>,
+    /// known blocks off the canonical chain, candidates for fork choice
+    side_blocks: HashMap<Hash, Block>,
+    /// the pending block holds its coinbase and uncles, waiting for the
+    /// template returned by `seal_pending_block`
+    sealed: bool,
+    /// uncles referenced by the last blocks, with the index of the block
+    /// referencing them
+    included_uncles: HashMap<Hash, u64>,
+    timestamp_policy: TimestampPolicy,
+    network_time: NetworkTime,
+    /// MMR over the hashes of every block
+    history: Mmr,
+    /// timestamp and difficulty of every block, input of the retarget
+    work_history: Vec<(u64, u64)>,
+    /// work of the chain up to every block, genesis included, what fork
+    /// choice compares
+    chain_work: Vec<u128>,
+    state_commitment: Box<dyn StateCommitment>,
+    /// local admission rules, never applied to blocks being replayed
+    policies: Vec<Box<dyn TransactionPolicy>>,
+    scorer: Box<dyn TransactionScorer>,
+    notifier: Notifier,
+}
+
+/// next nonce of the account of `sender`
+fn nonce_of(accounts: &BTreeMap<Address, Account>, sender: &PublicKey) -> u64 {
+    accounts.get(&sender.address()).map_or(0, |a| a.nonce())
+}
+
+/// accounts whose state `tx` changes
+fn touched_accounts(tx: &Transaction) -> impl Iterator<Item = Address> {
+    std::iter::once(tx.from().address())
+        .chain(tx.to())
+        .chain(tx.fee_granter().map(|granter| granter.address()))
+}
+
+/// accounts whose state `block` changes, those of its transactions and the
+/// producers of its uncles
+fn block_touched_accounts(block: &Block) -> impl Iterator<Item = Address> + '_ {
+    block
+        .transactions()
+        .iter()
+        .flat_map(touched_accounts)
+        .chain(
+            block
+                .uncles()
+                .iter()
+                .map(|uncle| uncle.beneficiary().address()),
+        )
+}
+
+/// add `block` and the accounts its transactions touched, as of now, to
+/// `batch`
+fn put_block_and_accounts<'a>(
+    batch: &mut StorageBatch<'a>,
+    accounts: &'a BTreeMap<Address, Account>,
+    block: &'a Block,
+) {
+    batch.put_block(block);
+    for address in block_touched_accounts(block) {
+        if let Some(account) = accounts.get(&address) {
+            batch.put_account(account);
+        }
+    }
+}
+
+/// write `block` and the accounts its transactions touched, as of now, and
+/// move the storage head to it, all at once
+fn store_block(
+    storage: &mut dyn Storage,
+    accounts: &BTreeMap<Address, Account>,
+    block: &Block,
+) -> Result<(), LedgerError> {
+    let mut batch = StorageBatch::new();
+    put_block_and_accounts(&mut batch, accounts, block);
+    batch.set_head(block.index());
+    storage.commit(&batch).map_err(LedgerError::Storage)
+}
+
+/// fill in the first differing account of a state root mismatch, `diff`
+/// only runs for one
+fn with_state_diff(error: LedgerError, diff: impl FnOnce() -> Option<AccountDiff>) -> LedgerError {
+    match error {
+        LedgerError::InvalidStateRoot {
+            index,
+            expected,
+            computed,
+            first_diff: None,
+        } => LedgerError::InvalidStateRoot {
+            index,
+            expected,
+            computed,
+            first_diff: diff().map(Box::new),
+        },
+        error => error,
+    }
 }
 
 impl Blockchain {
-    pub fn new(transaction: Transaction, timestamp: u64) -> Result<Blockchain, String> {
+    pub fn new(transaction: Transaction, timestamp: u64) -> Result<Blockchain, LedgerError> {
         let genesis_block = Block::new_genesis(vec![transaction], timestamp)?;
+        let mut blockchain = Blockchain::from_genesis(
+            genesis_block,
+            Box::new(MemoryStorage::new()),
+            ConsensusConfig::default(),
+            Box::new(MerkleStateCommitment::new()),
+        )?;
+        blockchain.store_tip()?;
+        Ok(blockchain)
+    }
+
+    /// Resume the chain kept in `storage`, produced under `consensus`.
+    /// Blocks are replayed from genesis to rebuild the indexes, the result
+    /// must match the stored accounts.
+    pub fn open(
+        storage: Box<dyn Storage>,
+        consensus: ConsensusConfig,
+    ) -> Result<Blockchain, LedgerError> {
+        Blockchain::open_with_commitment(storage, consensus, Box::new(MerkleStateCommitment::new()))
+    }
+
+    /// `open` for a chain committing to its state with `state_commitment`,
+    /// given without accounts
+    pub fn open_with_commitment(
+        storage: Box<dyn Storage>,
+        consensus: ConsensusConfig,
+        state_commitment: Box<dyn StateCommitment>,
+    ) -> Result<Blockchain, LedgerError> {
+        let head = storage
+            .head()
+            .map_err(LedgerError::Storage)?
+            .ok_or(LedgerError::EmptyStorage)?;
+        let genesis_block = storage
+            .block_by_index(0)
+            .map_err(LedgerError::Storage)?
+            .ok_or(LedgerError::MissingBlock(0))?;
+        let mut blockchain =
+            Blockchain::from_genesis(genesis_block, storage, consensus, state_commitment)?;
+        for index in 1..=head {
+            let block = blockchain
+                .storage
+                .block_by_index(index)
+                .map_err(LedgerError::Storage)?
+                .ok_or(LedgerError::MissingBlock(index))?;
+            if let Err(error) = blockchain.apply_block(block) {
+                // the stored accounts are the state as of the head
+                if index != head {
+                    return Err(error);
+                }
+                let diff = blockchain.stored_state_diff(blockchain.storage.as_ref())?;
+                return Err(with_state_diff(error, || diff));
+            }
+        }
+        blockchain.check_stored_state(blockchain.storage.as_ref())?;
+        Ok(blockchain)
+    }
+
+    fn check_stored_state(&self, storage: &dyn Storage) -> Result<(), LedgerError> {
+        match self.stored_state_diff(storage)? {
+            Some(diff) => Err(LedgerError::StateMismatch(Box::new(diff))),
+            None => Ok(()),
+        }
+    }
+
+    /// first account of the state whose stored version differs
+    fn stored_state_diff(&self, storage: &dyn Storage) -> Result<Option<AccountDiff>, LedgerError> {
+        for account in self.accounts.values() {
+            let stored = storage
+                .account(&account.address())
+                .map_err(LedgerError::Storage)?;
+            if stored.as_ref() != Some(account) {
+                return Ok(Some(AccountDiff {
+                    address: account.address(),
+                    left: Some(account.clone()),
+                    right: stored,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    fn from_genesis(
+        genesis_block: Block,
+        storage: Box<dyn Storage>,
+        consensus: ConsensusConfig,
+        state_commitment: Box<dyn StateCommitment>,
+    ) -> Result<Blockchain, LedgerError> {
+        if genesis_block.index() != 0
+            || genesis_block.previous_hash() != Hash::default()
+            || genesis_block.history_root() != Hash::default()
+            || genesis_block.state_root() != Hash::default()
+        {
+            return Err(LedgerError::InvalidGenesis);
+        }
+        if genesis_block.transactions().is_empty() {
+            return Err(BlockError::EmptyGenesis.into());
+        }
+        genesis_block.verify()?;
         let hash = genesis_block.hash();
+        let mut history = Mmr::new();
+        history.push(&hash);
+        let mut pending_block = BlockBuilder::new(1, &hash);
+        pending_block.set_history_root(&history.root());
 
         let mut blockchain = Blockchain {
-            blocks: vec![genesis_block.clone()],
-            pending_block: BlockBuilder::new(1, &hash),
-            accounts: HashMap::new(),
+            storage,
+            tip: genesis_block,
+            pending_block,
+            mempool: Mempool::new(),
+            accounts: BTreeMap::new(),
+            total_supply: 0,
+            burned: BTreeMap::new(),
+            fees: 0,
+            fee_grants: BTreeMap::new(),
+            validations: BTreeMap::new(),
+            consensus,
+            beneficiary: None,
+            transaction_index: HashMap::new(),
+            spent_nonces: HashMap::new(),
+            undo: UndoLog::default(),
+            block_undo: VecDeque::new(),
+            side_blocks: HashMap::new(),
+            sealed: false,
+            included_uncles: HashMap::new(),
+            timestamp_policy: TimestampPolicy::default(),
+            network_time: NetworkTime::default(),
+            history,
+            work_history: vec![],
+            chain_work: vec![],
+            state_commitment,
+            policies: vec![],
+            scorer: Box::new(ArrivalOrder),
+            notifier: Notifier::new(),
         };
 
+        let genesis_block = blockchain.tip.clone();
         for tx in genesis_block.transactions() {
-            blockchain.execute_transaction_genesis(tx)?;
+            blockchain
+                .execute_transaction_genesis(tx)
+                .map_err(|error| LedgerError::BlockTransaction { index: 0, error })?;
+        }
+        blockchain.index_transactions(&genesis_block);
+        blockchain.push_work(&genesis_block);
+        Ok(blockchain)
+    }
+
+    /// Rebuild a chain produced under `consensus` from its blocks, checking
+    /// every link and re-executing every transaction from genesis
+    pub fn from_blocks(
+        blocks: Vec<Block>,
+        consensus: ConsensusConfig,
+    ) -> Result<Blockchain, LedgerError> {
+        Blockchain::from_blocks_with_commitment(
+            blocks,
+            consensus,
+            Box::new(MerkleStateCommitment::new()),
+        )
+    }
+
+    /// `from_blocks` for a chain committing to its state with
+    /// `state_commitment`, given without accounts
+    pub fn from_blocks_with_commitment(
+        blocks: Vec<Block>,
+        consensus: ConsensusConfig,
+        state_commitment: Box<dyn StateCommitment>,
+    ) -> Result<Blockchain, LedgerError> {
+        Blockchain::replay(blocks, consensus, state_commitment, None)
+    }
+
+    /// `from_blocks`, with the state expected after the last block to point
+    /// at the first differing account if its state root does not match
+    pub(crate) fn replay(
+        blocks: Vec<Block>,
+        consensus: ConsensusConfig,
+        state_commitment: Box<dyn StateCommitment>,
+        expected: Option<&StateSnapshot>,
+    ) -> Result<Blockchain, LedgerError> {
+        let mut blocks = blocks.into_iter().peekable();
+        let genesis_block = blocks.next().ok_or(LedgerError::MissingBlock(0))?;
+        let mut blockchain = Blockchain::from_genesis(
+            genesis_block,
+            Box::new(MemoryStorage::new()),
+            consensus,
+            state_commitment,
+        )?;
+        blockchain.store_tip()?;
+        while let Some(block) = blocks.next() {
+            if let Err(error) = blockchain.execute_block(&block) {
+                return Err(match expected {
+                    Some(expected) if blocks.peek().is_none() => with_state_diff(error, || {
+                        match blockchain.state_snapshot().check_matches(expected) {
+                            Err(StateError::AccountsDiffer { first, .. }) => Some(*first),
+                            _ => None,
+                        }
+                    }),
+                    _ => error,
+                });
+            }
+            store_block(blockchain.storage.as_mut(), &blockchain.accounts, &block)?;
+            blockchain.append_block(block);
         }
         Ok(blockchain)
     }
 
-    fn is_existing_account(&self, address: &PublicKey) -> bool {
-        self.accounts.contains_key(address)
+    /// check and execute `block` then make it the tip, the storage is left
+    /// to the caller
+    fn apply_block(&mut self, block: Block) -> Result<(), LedgerError> {
+        self.execute_block(&block)?;
+        self.append_block(block);
+        Ok(())
+    }
+
+    /// Check `block` against the tip and execute its transactions. The
+    /// changes are recorded in the undo log, a failing block is rolled back
+    /// by the caller.
+    fn execute_block(&mut self, block: &Block) -> Result<(), LedgerError> {
+        block.verify()?;
+        let previous = &self.tip;
+        if block.index() != previous.index() + 1 {
+            return Err(LedgerError::UnexpectedIndex(block.index()));
+        }
+        if block.previous_hash() != previous.hash() {
+            return Err(LedgerError::UnlinkedBlock(block.index()));
+        }
+        if block.timestamp() < previous.timestamp() {
+            return Err(LedgerError::OlderThanParent(block.index()));
+        }
+        if block.history_root() != self.history.root() {
+            return Err(LedgerError::InvalidHistoryRoot(block.index()));
+        }
+        if let Some(expected) = self.next_difficulty() {
+            if block.difficulty() != expected {
+                return Err(LedgerError::InvalidDifficulty {
+                    index: block.index(),
+                    expected,
+                    got: block.difficulty(),
+                });
+            }
+            if !meets_difficulty(block.hash().as_ref(), expected) {
+                return Err(LedgerError::InsufficientWork(block.index()));
+            }
+        }
+        // every signature at once rather than one per executed transaction
+        verify_transactions(block.transactions()).map_err(|e| LedgerError::BlockTransaction {
+            index: block.index(),
+            error: e.into(),
+        })?;
+        let last = block.transactions().len().saturating_sub(1);
+        for (position, tx) in block.transactions().iter().enumerate() {
+            let result = if position == last && tx.is_coinbase() {
+                self.execute_coinbase(tx, block.index())
+            } else {
+                self.execute_verified(tx)
+            };
+            result.map_err(|error| LedgerError::BlockTransaction {
+                index: block.index(),
+                error,
+            })?;
+        }
+        self.execute_uncles(block.index(), block.uncles())?;
+        if block.state_root() != self.state_root() {
+            return Err(LedgerError::InvalidStateRoot {
+                index: block.index(),
+                expected: block.state_root(),
+                computed: self.state_root(),
+                first_diff: None,
+            });
+        }
+        Ok(())
+    }
+
+    /// make `block`, already checked and executed, the new tip, its changes
+    /// can still be reverted by `revert_tip`
+    fn append_block(&mut self, block: Block) {
+        let supply_before = self.undo.total_supply.unwrap_or(self.total_supply);
+        self.push_tip(block);
+        self.prune_history();
+        self.notifier
+            .block_added(&self.tip, supply_before, self.total_supply);
+    }
+
+    /// `append_block` without the notifications, for the blocks of a branch
+    /// that may still be reverted before the switch completes
+    fn push_tip(&mut self, block: Block) {
+        self.block_undo.push_back(std::mem::take(&mut self.undo));
+        self.index_transactions(&block);
+        for uncle in block.uncles() {
+            self.included_uncles.insert(uncle.hash(), block.index());
+        }
+        self.history.push(&block.hash());
+        self.push_work(&block);
+        self.pending_block = self.pending_block.next(&block, &self.history.root());
+        // fees left without a coinbase stay burned
+        self.fees = 0;
+        self.tip = block;
+    }
+
+    /// forget the undo logs, with the nonces they spend, and side blocks
+    /// deeper than `MAX_REORG_DEPTH`
+    fn prune_history(&mut self) {
+        while self.block_undo.len() > MAX_REORG_DEPTH {
+            let Some(undo) = self.block_undo.pop_front() else {
+                break;
+            };
+            // deeper blocks are never reverted, the nonce check alone
+            // rejects what conflicts with them
+            for key in undo.spent_nonces {
+                self.spent_nonces.remove(&key);
+            }
+        }
+        let index = self.tip.index();
+        self.side_blocks
+            .retain(|_, side| side.index() + MAX_REORG_DEPTH as u64 > index);
+        // an uncle is only referenced again within its window, also after a
+        // switch to a fork
+        self.included_uncles
+            .retain(|_, including| *including + MAX_UNCLE_DEPTH + MAX_REORG_DEPTH as u64 > index);
+    }
+
+    /// difficulty the next block must meet, `None` without proof of work
+    pub fn next_difficulty(&self) -> Option<u64> {
+        let config = self.consensus.proof_of_work.as_ref()?;
+        Some(next_difficulty(config, &self.work_history))
+    }
+
+    /// Rules of the next blocks, produced and imported alike, those of a
+    /// new chain until its first block is produced
+    pub fn set_consensus(&mut self, consensus: ConsensusConfig) {
+        self.consensus = consensus;
+    }
+
+    pub fn consensus(&self) -> &ConsensusConfig {
+        &self.consensus
+    }
+
+    /// account collecting the fees and the reward of the blocks produced
+    /// here, `None` produces blocks without a coinbase
+    pub fn set_beneficiary(&mut self, beneficiary: Option<PublicKey>) {
+        self.beneficiary = beneficiary;
+    }
+
+    /// fees paid by the pending block so far
+    pub fn pending_fees(&self) -> u64 {
+        self.fees
+    }
+
+    /// undo the tip, `parent` becoming the tip again, and return it with
+    /// the log bringing its state back for `restore_tip`
+    fn revert_tip(&mut self, parent: Block) -> (Block, UndoLog) {
+        let undo = self.block_undo.pop_back().unwrap_or_default();
+        let redo = self.inverse(&undo);
+        self.revert(undo);
+        for tx in self.tip.transactions() {
+            self.transaction_index.remove(&tx.id());
+        }
+        self.included_uncles
+            .retain(|_, including| *including <= parent.index());
+        self.history.truncate(parent.index() + 1);
+        self.work_history.truncate(parent.index() as usize + 1);
+        self.chain_work.truncate(parent.index() as usize + 1);
+        self.pending_block = self.pending_block.next(&parent, &self.history.root());
+        (std::mem::replace(&mut self.tip, parent), redo)
+    }
+
+    /// make `block`, undone by `revert_tip`, the tip again without executing
+    /// it, `redo` holds its state
+    fn restore_tip(&mut self, block: Block, redo: UndoLog) {
+        self.undo = self.inverse(&redo);
+        self.revert(redo);
+        for tx in block.transactions().iter().filter(|tx| !tx.is_coinbase()) {
+            self.undo.spent_nonces.push((tx.from(), tx.nonce()));
+            self.spent_nonces.insert((tx.from(), tx.nonce()), tx.id());
+        }
+        self.push_tip(block);
+    }
+
+    /// log restoring the current value of everything `log` restores, the
+    /// spent nonces aside
+    fn inverse(&self, log: &UndoLog) -> UndoLog {
+        UndoLog {
+            accounts: log
+                .accounts
+                .keys()
+                .map(|address| (*address, self.accounts.get(address).cloned()))
+                .collect(),
+            burned: log
+                .burned
+                .keys()
+                .map(|address| (*address, self.burned.get(address).copied()))
+                .collect(),
+            spent_nonces: vec![],
+            total_supply: log.total_supply.map(|_| self.total_supply),
+            fees: log.fees.map(|_| self.fees),
+            fee_grants: log
+                .fee_grants
+                .keys()
+                .map(|key| (*key, self.fee_grants.get(key).copied()))
+                .collect(),
+            validations: log
+                .validations
+                .keys()
+                .map(|address| (*address, self.validations.get(address).cloned()))
+                .collect(),
+        }
+    }
+
+    /// work fork choice sums, every block weighs the same without proof of
+    /// work so the longest chain wins
+    fn block_work(&self, difficulty: u64) -> u128 {
+        match self.consensus.proof_of_work {
+            Some(_) => work(difficulty),
+            None => 1,
+        }
+    }
+
+    fn push_work(&mut self, block: &Block) {
+        let total = self.chain_work.last().copied().unwrap_or(0);
+        self.chain_work
+            .push(total.saturating_add(self.block_work(block.difficulty())));
+        self.work_history
+            .push((block.timestamp(), block.difficulty()));
+    }
+
+    /// work of the chain up to and including the tip
+    pub fn chain_work(&self) -> u128 {
+        self.chain_work.last().copied().unwrap_or(0)
+    }
+
+    /// work of the chain up to and including the block at `index`
+    pub fn work_at(&self, index: u64) -> Option<u128> {
+        self.chain_work.get(index as usize).copied()
+    }
+
+    /// known blocks off the canonical chain
+    pub fn side_blocks(&self) -> impl Iterator<Item = &Block> {
+        self.side_blocks.values()
+    }
+
+    /// Accept a block produced elsewhere, no further ahead of the network
+    /// adjusted time than the timestamp policy allows. One on top of the tip
+    /// is checked and re-executed like a replayed block, one failing leaves
+    /// the chain as it was. A block extending a fork is kept aside, the chain switches to
+    /// the fork once it has more work, see `import_side_block`. The pending
+    /// transactions are executed again on top of the new tip, those no
+    /// longer valid are dropped and returned.
+    pub fn import_block(&mut self, block: Block) -> Result<Vec<Transaction>, LedgerError> {
+        if block.previous_hash() != self.tip.hash() {
+            return self.import_side_block(block);
+        }
+        self.check_block_timestamp(&block)?;
+        self.unseal();
+        self.rollback();
+        let empty = self.pending_block.next(&self.tip, &self.history.root());
+        let pending = std::mem::replace(&mut self.pending_block, empty).into_transactions();
+        // persisted before it becomes the tip, a failing write leaves the
+        // chain as it was
+        let result = self
+            .execute_block(&block)
+            .and_then(|()| store_block(self.storage.as_mut(), &self.accounts, &block));
+        if let Err(e) = result {
+            self.rollback();
+            self.restore_pending(pending);
+            return Err(e);
+        }
+        self.append_block(block);
+        let dropped = self.restore_pending(pending);
+        self.prune_mempool();
+        Ok(dropped)
+    }
+
+    /// Keep a block forking off the canonical chain, and switch to its
+    /// branch when it has strictly more work than the canonical blocks
+    /// since the fork. Only the header is checked until then.
+    fn import_side_block(&mut self, block: Block) -> Result<Vec<Transaction>, LedgerError> {
+        block.verify()?;
+        let hash = block.hash();
+        if self.side_blocks.contains_key(&hash) || self.canonical_block(&hash)?.is_some() {
+            return Err(LedgerError::KnownBlock(block.index()));
+        }
+        if self.consensus.proof_of_work.is_some()
+            && !meets_difficulty(hash.as_ref(), block.difficulty())
+        {
+            return Err(LedgerError::InsufficientWork(block.index()));
+        }
+
+        // walk back through the side blocks to the canonical chain
+        let mut branch = vec![];
+        let mut previous_hash = block.previous_hash();
+        let fork = loop {
+            match self.side_blocks.get(&previous_hash).cloned() {
+                Some(parent) => {
+                    previous_hash = parent.previous_hash();
+                    branch.push(parent);
+                }
+                None => match self.canonical_block(&previous_hash)? {
+                    Some(fork) => break fork,
+                    None => return Err(LedgerError::UnlinkedBlock(block.index())),
+                },
+            }
+        };
+        branch.reverse();
+        let parent = branch.last().unwrap_or(&fork);
+        if block.index() != parent.index() + 1 {
+            return Err(LedgerError::UnexpectedIndex(block.index()));
+        }
+        self.timestamp_policy
+            .check(&block, parent, self.network_time.now())?;
+        if self.tip.index() - fork.index() > self.block_undo.len() as u64 {
+            return Err(LedgerError::ForkTooDeep(block.index()));
+        }
+
+        let branch_work: u128 = branch
+            .iter()
+            .chain([&block])
+            .map(|b| self.block_work(b.difficulty()))
+            .sum();
+        let canonical_work = self.chain_work() - self.chain_work[fork.index() as usize];
+        if branch_work <= canonical_work {
+            self.keep_side_block(block);
+            return Ok(vec![]);
+        }
+        branch.push(block);
+        self.reorganize(fork, branch)
+    }
+
+    fn keep_side_block(&mut self, block: Block) {
+        self.side_blocks.insert(block.hash(), block);
+        while self.side_blocks.len() > MAX_SIDE_BLOCKS {
+            let lowest = self
+                .side_blocks
+                .iter()
+                .min_by_key(|&(hash, side)| (side.index(), hash.as_ref()))
+                .map(|(hash, _)| *hash);
+            match lowest {
+                Some(hash) => self.side_blocks.remove(&hash),
+                None => break,
+            };
+        }
+    }
+
+    /// block `hash` if it is part of the canonical chain
+    fn canonical_block(&self, hash: &Hash) -> Result<Option<Block>, LedgerError> {
+        self.storage
+            .block_by_hash(hash)
+            .map_err(LedgerError::Storage)
+    }
+
+    /// Roll the canonical chain back to `fork` and apply `branch` on top of
+    /// it, then write the switch to the storage in one batch. A branch block
+    /// failing, or the write, restores the previous chain from the undo
+    /// logs, a failing block and its descendants are dropped. The
+    /// transactions of the abandoned blocks go back to the mempool.
+    fn reorganize(
+        &mut self,
+        fork: Block,
+        branch: Vec<Block>,
+    ) -> Result<Vec<Transaction>, LedgerError> {
+        // read everything needed before touching the state
+        let mut parents = vec![];
+        for index in fork.index()..self.tip.index() {
+            let parent = self
+                .storage
+                .block_by_index(index)
+                .map_err(LedgerError::Storage)?
+                .ok_or(LedgerError::MissingBlock(index))?;
+            parents.push(parent);
+        }
+
+        self.unseal();
+        self.rollback();
+        let empty = self.pending_block.next(&self.tip, &self.history.root());
+        let pending = std::mem::replace(&mut self.pending_block, empty).into_transactions();
+        let old_tip = self.tip.hash();
+        let mut abandoned = vec![];
+        while let Some(parent) = parents.pop() {
+            abandoned.push(self.revert_tip(parent));
+        }
+        abandoned.reverse();
+
+        // supply before and after every branch block, for the notifications
+        let mut supplies = vec![];
+        let mut failure = None;
+        for (applied, block) in branch.iter().enumerate() {
+            let supply_before = self.total_supply;
+            if let Err(e) = self.execute_block(block) {
+                failure = Some((applied, e));
+                break;
+            }
+            self.push_tip(block.clone());
+            supplies.push((supply_before, self.total_supply));
+        }
+        if failure.is_none() {
+            if let Err(e) = self.store_branch(&fork, &branch, &abandoned) {
+                failure = Some((branch.len(), e));
+            }
+        }
+        if let Some((applied, e)) = failure {
+            self.rollback();
+            let parents = branch[..applied].iter().rev().skip(1).chain([&fork]);
+            for parent in parents.take(applied) {
+                self.revert_tip(parent.clone());
+            }
+            for (block, redo) in abandoned {
+                self.restore_tip(block, redo);
+            }
+            for block in &branch[applied..] {
+                self.side_blocks.remove(&block.hash());
+            }
+            self.restore_pending(pending);
+            return Err(e);
+        }
+
+        for block in &branch {
+            self.side_blocks.remove(&block.hash());
+        }
+        let depth = abandoned.len() as u64;
+        for (block, _) in abandoned {
+            for tx in block.transactions().iter().filter(|tx| !tx.is_coinbase()) {
+                self.requeue(tx.clone());
+            }
+            self.keep_side_block(block);
+        }
+        self.prune_history();
+        self.notifier.notify(&ChainEvent::Reorg {
+            depth,
+            old_tip,
+            new_tip: self.tip.hash(),
+        });
+        for (block, (before, after)) in branch.iter().zip(supplies) {
+            self.notifier.block_added(block, before, after);
+        }
+        let dropped = self.restore_pending(pending);
+        self.prune_mempool();
+        Ok(dropped)
+    }
+
+    /// Write `branch`, now canonical, in place of the blocks after `fork`,
+    /// with the accounts as of the new tip. Those only the abandoned blocks
+    /// created are deleted.
+    fn store_branch(
+        &mut self,
+        fork: &Block,
+        branch: &[Block],
+        abandoned: &[(Block, UndoLog)],
+    ) -> Result<(), LedgerError> {
+        let mut batch = StorageBatch::new();
+        batch.truncate_blocks(fork.index() + 1);
+        for block in branch {
+            put_block_and_accounts(&mut batch, &self.accounts, block);
+        }
+        for address in abandoned
+            .iter()
+            .flat_map(|(block, _)| block_touched_accounts(block))
+        {
+            match self.accounts.get(&address) {
+                Some(account) => batch.put_account(account),
+                None => batch.remove_account(address),
+            }
+        }
+        batch.set_head(self.tip.index());
+        self.storage.commit(&batch).map_err(LedgerError::Storage)
     }
 
-    fn add_account(&mut self, address: &PublicKey) -> Result<&Account, String> {
-        if self.is_existing_account(&address) {
-            return Err("Account already exists".to_string());
+    /// Audit the whole chain: every stored block is checked and re-executed
+    /// from genesis, the result must match the stored state
+    pub fn validate_chain(&self) -> Result<(), LedgerError> {
+        let replayed = Blockchain::from_blocks_with_commitment(
+            self.blocks()?,
+            self.consensus,
+            self.state_commitment.empty(),
+        )?;
+        if replayed.history_root() != self.history_root() {
+            return Err(LedgerError::HistoryMismatch);
         }
-        let account = Account::new(address);
-        self.accounts.insert(address.clone(), account);
-        Ok(self.accounts.get(&address).unwrap())
+        replayed.check_stored_state(self.storage.as_ref())
     }
 
-    pub fn get_account(&self, address: &PublicKey) -> Option<&Account> {
+    /// reopen the pending block closed by `seal_pending_block`, its coinbase
+    /// and uncles undone
+    fn unseal(&mut self) {
+        if !std::mem::take(&mut self.sealed) {
+            return;
+        }
+        self.rollback();
+        let empty = self.pending_block.next(&self.tip, &self.history.root());
+        let pending = std::mem::replace(&mut self.pending_block, empty).into_transactions();
+        let transactions = pending.into_iter().filter(|tx| !tx.is_coinbase()).collect();
+        for tx in self.restore_pending(transactions) {
+            self.requeue(tx);
+        }
+    }
+
+    fn restore_pending(&mut self, transactions: Vec<Transaction>) -> Vec<Transaction> {
+        let mut dropped = vec![];
+        for tx in transactions {
+            if self.pending_block_full() || self.execute_transaction(&tx).is_err() {
+                dropped.push(tx);
+            } else {
+                // cannot fail, the block is not full
+                let _ = self.pending_block.add_transaction(tx);
+            }
+        }
+        dropped
+    }
+
+    /// record the state `tx` is about to change
+    fn record_undo(&mut self, tx: &Transaction) {
+        for address in touched_accounts(tx) {
+            self.record_account_undo(address);
+        }
+        self.undo
+            .burned
+            .entry(tx.from())
+            .or_insert_with(|| self.burned.get(&tx.from()).copied());
+        self.undo.spent_nonces.push((tx.from(), tx.nonce()));
+        self.undo.total_supply.get_or_insert(self.total_supply);
+        self.undo.fees.get_or_insert(self.fees);
+    }
+
+    fn record_grant_undo(&mut self, key: (PublicKey, PublicKey)) {
+        self.undo
+            .fee_grants
+            .entry(key)
+            .or_insert_with(|| self.fee_grants.get(&key).copied());
+    }
+
+    fn record_validation_undo(&mut self, address: PublicKey) {
+        self.undo
+            .validations
+            .entry(address)
+            .or_insert_with(|| self.validations.get(&address).cloned());
+    }
+
+    fn record_account_undo(&mut self, address: Address) {
+        self.undo
+            .accounts
+            .entry(address)
+            .or_insert_with(|| self.accounts.get(&address).cloned());
+    }
+
+    /// undo every change made since the tip
+    fn rollback(&mut self) {
+        let undo = std::mem::take(&mut self.undo);
+        self.revert(undo);
+    }
+
+    fn revert(&mut self, undo: UndoLog) {
+        for (address, account) in undo.accounts {
+            match account {
+                Some(account) => {
+                    self.state_commitment.update(&account);
+                    self.accounts.insert(address, account);
+                }
+                None => {
+                    self.state_commitment.remove(&address);
+                    self.accounts.remove(&address);
+                }
+            }
+        }
+        for (address, burned) in undo.burned {
+            match burned {
+                Some(burned) => self.burned.insert(address, burned),
+                None => self.burned.remove(&address),
+            };
+        }
+        for key in undo.spent_nonces {
+            self.spent_nonces.remove(&key);
+        }
+        if let Some(total_supply) = undo.total_supply {
+            self.total_supply = total_supply;
+        }
+        if let Some(fees) = undo.fees {
+            self.fees = fees;
+        }
+        for (key, grant) in undo.fee_grants {
+            match grant {
+                Some(grant) => self.fee_grants.insert(key, grant),
+                None => self.fee_grants.remove(&key),
+            };
+        }
+        for (address, validation) in undo.validations {
+            match validation {
+                Some(validation) => self.validations.insert(address, validation),
+                None => self.validations.remove(&address),
+            };
+        }
+    }
+
+    /// write the tip and the accounts its transactions touched, and move
+    /// the storage head to it
+    fn store_tip(&mut self) -> Result<(), LedgerError> {
+        store_block(self.storage.as_mut(), &self.accounts, &self.tip)
+    }
+
+    /// Move the chain to another storage, e.g. from memory to disk. Blocks
+    /// and accounts are copied as of the last block, the pending block stays
+    /// in memory.
+    pub fn set_storage(&mut self, mut storage: Box<dyn Storage>) -> Result<(), LedgerError> {
+        let blocks = self.blocks()?;
+        let mut accounts = vec![];
+        for address in self.accounts.keys() {
+            if let Some(account) = self
+                .storage
+                .account(address)
+                .map_err(LedgerError::Storage)?
+            {
+                accounts.push(account);
+            }
+        }
+        let mut batch = StorageBatch::new();
+        for block in &blocks {
+            batch.put_block(block);
+        }
+        for account in &accounts {
+            batch.put_account(account);
+        }
+        batch.set_head(self.tip.index());
+        storage.commit(&batch).map_err(LedgerError::Storage)?;
+        self.storage = storage;
+        Ok(())
+    }
+
+    /// give the storage back, e.g. to reopen the chain later with `open`
+    pub fn into_storage(self) -> Box<dyn Storage> {
+        self.storage
+    }
+
+    /// every block from genesis, read from the storage
+    pub fn blocks(&self) -> Result<Vec<Block>, LedgerError> {
+        (0..=self.tip.index())
+            .map(|index| {
+                self.get_block_by_index(index)?
+                    .ok_or(LedgerError::MissingBlock(index))
+            })
+            .collect()
+    }
+
+    /// ordered by address, the same on every node
+    pub fn accounts(&self) -> impl Iterator<Item = &Account> {
+        self.accounts.values()
+    }
+
+    fn get_or_create_account(&mut self, address: &Address) -> &mut Account {
+        self.accounts
+            .entry(*address)
+            .or_insert_with(|| Account::new(address))
+    }
+
+    pub fn get_account(&self, address: &Address) -> Option<&Account> {
         self.accounts.get(address)
     }
 
-    fn get_account_mut(&mut self, address: &PublicKey) -> Option<&mut Account> {
-        self.accounts.get_mut(address)
+    pub fn account_view(&self) -> AccountView<'_> {
+        AccountView::new(&self.accounts)
+    }
+
+    /// Overwrite an account outside of any transaction, keeping the total
+    /// supply and the state commitment in line. The stored state no longer
+    /// matches a replay of the blocks afterwards.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub(crate) fn overwrite_account(&mut self, account: Account) -> Result<(), LedgerError> {
+        self.unseal();
+        if !self.pending_block.transactions().is_empty() {
+            return Err(LedgerError::PendingBlockNotEmpty);
+        }
+        let previous = self
+            .accounts
+            .get(&account.address())
+            .map_or(0, |a| a.balance());
+        self.total_supply = (self.total_supply - previous)
+            .checked_add(account.balance())
+            .ok_or(TxError::Overflow)?;
+        let mut batch = StorageBatch::new();
+        batch.put_account(&account);
+        self.storage.commit(&batch)?;
+        self.state_commitment.update(&account);
+        self.accounts.insert(account.address(), account);
+        Ok(())
     }
 
-    pub fn add_transaction(&mut self, tx: Transaction) -> Result<(), String> {
+    fn get_account_mut(&mut self, address: &Address) -> Result<&mut Account, TxError> {
+        self.accounts
+            .get_mut(address)
+            .ok_or(TxError::AccountNotFound)
+    }
+
+    fn commit_account(&mut self, address: &Address) {
+        if let Some(account) = self.accounts.get(address) {
+            self.state_commitment.update(account);
+        }
+    }
+
+    /// Replace the structure committing to the state, e.g. to compare
+    /// implementations, it is rebuilt from the current accounts. Its root
+    /// is committed in every block header, the nodes of a chain must all
+    /// use the same structure and replay it with `open_with_commitment` or
+    /// `from_blocks_with_commitment`.
+    pub fn set_state_commitment(&mut self, mut state_commitment: Box<dyn StateCommitment>) {
+        for account in self.accounts.values() {
+            state_commitment.update(account);
+        }
+        self.state_commitment = state_commitment;
+    }
+
+    /// root of the state commitment, covering the pending block
+    pub fn state_root(&self) -> Hash {
+        self.state_commitment.root()
+    }
+
+    /// one slot of the pending block is kept for its coinbase
+    fn pending_block_full(&self) -> bool {
+        self.pending_block.transactions().len() + 1 >= MAX_BLOCK_TRANSACTIONS
+    }
+
+    pub fn add_transaction(&mut self, tx: Transaction) -> Result<(), TxError> {
+        self.unseal();
+        if self.pending_block_full() {
+            return Err(TxError::PendingBlockFull);
+        }
         self.execute_transaction(&tx)?;
-        self.pending_block.add_transaction(&tx);
+        let id = tx.id();
+        self.pending_block
+            .add_transaction(tx)
+            .map_err(|_| TxError::PendingBlockFull)?;
+        self.notifier.transaction_added(id);
         Ok(())
     }
 
-    fn execute_transaction(&mut self, tx: &Transaction) -> Result<(), String> {
-        tx.verify()?;
+    pub fn add_policy(&mut self, policy: Box<dyn TransactionPolicy>) {
+        self.policies.push(policy);
+    }
 
-        let amount = tx.amount();
+    /// add a transaction to the pending block after checking the local
+    /// policies, `add_transaction` only enforces consensus validity
+    pub fn admit_transaction(&mut self, tx: Transaction) -> Result<(), AdmissionError> {
+        for policy in &self.policies {
+            policy.check(&tx, self)?;
+        }
+        self.add_transaction(tx).map_err(AdmissionError::Invalid)
+    }
+
+    /// Queue a transaction in the mempool, to be executed when the next
+    /// block is assembled. Only the signature, the amount and the local
+    /// policies are checked, the nonce may be ahead of the account within
+    /// the mempool bounds and a queued transaction with the same nonce is
+    /// replaced by one paying a higher fee.
+    pub fn submit_transaction(&mut self, tx: Transaction) -> Result<TxId, AdmissionError> {
+        tx.verify().map_err(|e| AdmissionError::Invalid(e.into()))?;
+        if tx.amount() == 0 {
+            return Err(AdmissionError::Invalid(TxError::InvalidAmount));
+        }
+        if tx.nonce() < self.account_view().nonce(&tx.from().address()) {
+            return Err(AdmissionError::Invalid(TxError::NonceAlreadyUsed));
+        }
+        for policy in &self.policies {
+            policy.check(&tx, self)?;
+        }
+        let id = tx.id();
+        let score = self.scorer.score(&tx, &self.account_view());
+        let nonce = self.account_view().nonce(&tx.from().address());
+        self.mempool
+            .insert(tx, score, nonce)
+            .map_err(AdmissionError::Mempool)?;
+        self.notifier.transaction_added(id);
+        Ok(id)
+    }
+
+    /// put back a transaction taken out of the mempool, dropped when it no
+    /// longer fits
+    fn requeue(&mut self, tx: Transaction) {
+        let score = self.scorer.score(&tx, &self.account_view());
+        let nonce = self.account_view().nonce(&tx.from().address());
+        let _ = self.mempool.insert(tx, score, nonce);
+    }
+
+    pub fn mempool(&self) -> &Mempool {
+        &self.mempool
+    }
+
+    pub fn set_mempool_config(&mut self, config: MempoolConfig) {
+        self.mempool.set_config(config);
+    }
+
+    /// transactions the next block would include: the pending block and the
+    /// ready part of the mempool
+    pub fn ready_transactions(&self) -> usize {
+        let accounts = &self.accounts;
+        self.pending_block.transactions().len()
+            + self
+                .mempool
+                .count_ready(|sender| nonce_of(accounts, sender))
+    }
+
+    /// Execute the ready mempool transactions into the pending block, in
+    /// score order. A failing transaction is dropped and the later nonces of
+    /// its sender stay in the mempool.
+    fn assemble_pending_block(&mut self) {
+        let accounts = &self.accounts;
+        let ready: Vec<Transaction> = self
+            .mempool
+            .ready(|sender| nonce_of(accounts, sender))
+            .into_iter()
+            .cloned()
+            .collect();
+        let ordered = order_by_score(ready, self.scorer.as_ref(), &self.account_view());
+        let mut blocked = vec![];
+        for tx in ordered {
+            if self.pending_block_full() || blocked.contains(&tx.from()) {
+                continue;
+            }
+            self.mempool.remove(&tx.from(), tx.nonce());
+            if self.execute_transaction(&tx).is_err() {
+                blocked.push(tx.from());
+            } else {
+                // cannot fail, the block is not full
+                let _ = self.pending_block.add_transaction(tx);
+            }
+        }
+    }
+
+    fn prune_mempool(&mut self) {
+        let accounts = &self.accounts;
+        self.mempool.prune(|sender| nonce_of(accounts, sender));
+    }
+
+    /// Admit a transaction given as the hex of its canonical encoding, as
+    /// built and signed outside the node. Only the signed bytes are trusted,
+    /// the id returned is the one the transaction is indexed under.
+    pub fn send_raw_transaction(&mut self, hex: &str) -> Result<TxId, AdmissionError> {
+        let tx = Transaction::from_hex(hex).map_err(|e| AdmissionError::Invalid(e.into()))?;
+        let id = tx.id();
+        self.admit_transaction(tx)?;
+        Ok(id)
+    }
+
+    /// Transfer from `from` using its next nonce, pending transactions
+    /// included, paying the fee chosen by `fee_policy`. The balance has to
+    /// cover both `amount` and the fee.
+    pub fn build_unsigned_transfer(
+        &self,
+        from: &PublicKey,
+        to: &Address,
+        amount: u64,
+        fee_policy: &FeePolicy,
+    ) -> Result<UnsignedTransfer, TxError> {
         if amount == 0 {
-            return Err("Invalid transaction amount".to_string());
+            return Err(TxError::InvalidAmount);
+        }
+        let account = self
+            .get_account(&from.address())
+            .ok_or(TxError::AccountNotFound)?;
+        let fee = fee_policy.fee(self);
+        let required = amount.checked_add(fee).ok_or(TxError::Overflow)?;
+        if account.balance() < required {
+            return Err(TxError::InsufficientFunds {
+                balance: account.balance(),
+                required,
+            });
         }
+        let mut transaction = Transaction::new(from, to, amount, account.nonce());
+        transaction.set_fee(fee);
+        Ok(UnsignedTransfer {
+            signing_hash: transaction.signing_hash(),
+            payload: transaction.to_bytes(),
+            transaction,
+        })
+    }
 
-        let from_account = self
-            .get_account(&tx.from())
-            .ok_or("From account not found")?;
+    /// prioritization used when several transactions are admitted at once
+    pub fn set_scorer(&mut self, scorer: Box<dyn TransactionScorer>) {
+        self.scorer = scorer;
+    }
 
-        if from_account.nonce() != tx.nonce() {
-            return Err("Invalid nonce".to_string());
+    /// Admit a batch of transactions in score order, the result of each is
+    /// returned in the order they were tried
+    pub fn admit_transactions(
+        &mut self,
+        transactions: Vec<Transaction>,
+    ) -> Vec<(TxId, Result<(), AdmissionError>)> {
+        let ordered = order_by_score(transactions, self.scorer.as_ref(), &self.account_view());
+        ordered
+            .into_iter()
+            .map(|tx| (tx.id(), self.admit_transaction(tx)))
+            .collect()
+    }
+
+    fn execute_transaction(&mut self, tx: &Transaction) -> Result<(), TxError> {
+        if !tx.is_coinbase() {
+            tx.verify()?;
         }
+        self.execute_verified(tx)
+    }
 
-        if from_account.balance() < amount {
-            return Err("Insufficient funds".to_string());
+    /// `execute_transaction` once the signature of `tx` is checked
+    fn execute_verified(&mut self, tx: &Transaction) -> Result<(), TxError> {
+        if tx.is_coinbase() {
+            return Err(TxError::UnexpectedCoinbase);
+        }
+        match self.validations.get(&tx.from()) {
+            Some(validation) => validation.check(
+                tx,
+                self.pending_block.index(),
+                self.consensus.predicate_gas_limit,
+            )?,
+            None if !tx.cosignatures().is_empty() => return Err(TxError::TooManyCosignatures),
+            None if !tx.witness().is_empty() => return Err(TxError::UnexpectedWitness),
+            None => {}
         }
 
-        let to_account = match self.get_account(&tx.to()) {
-            Some(account) => account,
-            None => self.add_account(&tx.to()).unwrap(),
+        let amount = tx.amount();
+        let valid_amount = match tx.kind() {
+            TransactionKind::SetValidation(_) => amount == 0,
+            _ => amount != 0,
+        };
+        if !valid_amount {
+            return Err(TxError::InvalidAmount);
+        }
+        let fee = tx.fee();
+        let fees = self.fees.checked_add(fee).ok_or(TxError::Overflow)?;
+        // a grant only sets an allowance, nothing is moved
+        let moved = match tx.kind() {
+            TransactionKind::GrantFees { .. } => 0,
+            _ => amount,
+        };
+        let grant = match tx.fee_granter() {
+            Some(granter) => Some(self.check_fee_grant(tx, granter)?),
+            None => None,
         };
+        let sender_fee = if grant.is_some() { 0 } else { fee };
+        let required = moved.checked_add(sender_fee).ok_or(TxError::Overflow)?;
 
-        if to_account.balance() + tx.amount() < to_account.balance() {
-            return Err("Overflow error".to_string());
+        let from = tx.from().address();
+        let from_account = self.get_account(&from).ok_or(TxError::AccountNotFound)?;
+
+        if from_account.nonce() != tx.nonce() {
+            return Err(TxError::InvalidNonce {
+                expected: from_account.nonce(),
+                got: tx.nonce(),
+            });
         }
 
-        {
-            self.get_account_mut(&tx.from()).unwrap().transfer(amount);
+        if from_account.balance() < required {
+            return Err(TxError::InsufficientFunds {
+                balance: from_account.balance(),
+                required,
+            });
         }
-        {
-            self.get_account_mut(&tx.to()).unwrap().deposit(amount);
+
+        // every check happens before the first mutation so a failing
+        // transaction leaves the state untouched
+        match tx.kind() {
+            TransactionKind::Transfer { to } => {
+                let to_balance = self.get_account(&to).map_or(0, |a| a.balance());
+                if to != from && to_balance.checked_add(amount).is_none() {
+                    return Err(TxError::Overflow);
+                }
+                // fees leave the supply until a coinbase collects them
+                let total_supply = self
+                    .total_supply
+                    .checked_sub(fee)
+                    .ok_or(TxError::Overflow)?;
+
+                self.record_undo(tx);
+                self.get_account_mut(&from)?.transfer(required)?;
+                self.get_or_create_account(&to).deposit(amount)?;
+                self.commit_account(&to);
+                self.total_supply = total_supply;
+            }
+            TransactionKind::Burn => {
+                let total_supply = amount
+                    .checked_add(fee)
+                    .and_then(|burned| self.total_supply.checked_sub(burned))
+                    .ok_or(TxError::BurnExceedsSupply)?;
+                let burned = self
+                    .burned_by(&tx.from())
+                    .checked_add(amount)
+                    .ok_or(TxError::Overflow)?;
+                self.record_undo(tx);
+                self.get_account_mut(&from)?.transfer(required)?;
+                self.total_supply = total_supply;
+                self.burned.insert(tx.from(), burned);
+            }
+            TransactionKind::Coinbase => return Err(TxError::UnexpectedCoinbase),
+            TransactionKind::GrantFees {
+                grantee,
+                expires_at,
+            } => {
+                if grantee == tx.from() {
+                    return Err(TxError::InvalidFeeGranter);
+                }
+                let total_supply = self
+                    .total_supply
+                    .checked_sub(fee)
+                    .ok_or(TxError::Overflow)?;
+                let key = (tx.from(), grantee);
+                self.record_undo(tx);
+                self.record_grant_undo(key);
+                self.get_account_mut(&from)?.transfer(required)?;
+                self.total_supply = total_supply;
+                let grant = FeeGrant {
+                    remaining: amount,
+                    expires_at,
+                };
+                if grant.is_expired(self.pending_block.index()) {
+                    self.fee_grants.remove(&key);
+                } else {
+                    self.fee_grants.insert(key, grant);
+                }
+            }
+            TransactionKind::SetValidation(validation) => {
+                if !validation.is_valid() {
+                    return Err(TxError::InvalidValidation);
+                }
+                let total_supply = self
+                    .total_supply
+                    .checked_sub(fee)
+                    .ok_or(TxError::Overflow)?;
+                self.record_undo(tx);
+                self.record_validation_undo(tx.from());
+                self.get_account_mut(&from)?.transfer(required)?;
+                self.total_supply = total_supply;
+                if validation.is_empty() {
+                    self.validations.remove(&tx.from());
+                } else {
+                    self.validations.insert(tx.from(), validation);
+                }
+            }
+        }
+        if let (Some(granter), Some(grant)) = (tx.fee_granter(), grant) {
+            let key = (granter, tx.from());
+            self.record_grant_undo(key);
+            self.get_account_mut(&granter.address())?.transfer(fee)?;
+            self.commit_account(&granter.address());
+            if grant.remaining == 0 {
+                self.fee_grants.remove(&key);
+            } else {
+                self.fee_grants.insert(key, grant);
+            }
+        }
+        self.fees = fees;
+        self.get_account_mut(&from)?.increment_nonce();
+        self.commit_account(&from);
+        self.spent_nonces.insert((tx.from(), tx.nonce()), tx.id());
+
+        Ok(())
+    }
+
+    /// The grant of `granter` to the sender of `tx` once it paid the fee,
+    /// the granter balance must cover it
+    fn check_fee_grant(&self, tx: &Transaction, granter: PublicKey) -> Result<FeeGrant, TxError> {
+        if granter == tx.from() {
+            return Err(TxError::InvalidFeeGranter);
+        }
+        let grant = self
+            .fee_grants
+            .get(&(granter, tx.from()))
+            .ok_or(TxError::NoFeeGrant)?
+            .spend(tx.fee(), self.pending_block.index())?;
+        let balance = self
+            .get_account(&granter.address())
+            .map_or(0, |a| a.balance());
+        if balance < tx.fee() {
+            return Err(TxError::InsufficientFunds {
+                balance,
+                required: tx.fee(),
+            });
+        }
+        Ok(grant)
+    }
+
+    /// conditions the transactions of `address` must meet
+    pub fn validation(&self, address: &PublicKey) -> Option<&Validation> {
+        self.validations.get(address)
+    }
+
+    /// fees `granter` still pays for `grantee`, expired grants included
+    pub fn fee_grant(&self, granter: &PublicKey, grantee: &PublicKey) -> Option<FeeGrant> {
+        self.fee_grants.get(&(*granter, *grantee)).copied()
+    }
+
+    /// Credit the coinbase of block `index`, minting the fees collected by
+    /// the transactions before it and the block reward
+    fn execute_coinbase(&mut self, tx: &Transaction, index: u64) -> Result<(), TxError> {
+        if tx.nonce() != index {
+            return Err(TxError::InvalidNonce {
+                expected: index,
+                got: tx.nonce(),
+            });
+        }
+        if tx.amount() == 0 {
+            return Err(TxError::InvalidAmount);
         }
+        let expected = self
+            .fees
+            .checked_add(self.consensus.block_reward)
+            .ok_or(TxError::Overflow)?;
+        if tx.amount() != expected {
+            return Err(TxError::InvalidCoinbaseAmount {
+                expected,
+                got: tx.amount(),
+            });
+        }
+        let total_supply = self
+            .total_supply
+            .checked_add(tx.amount())
+            .ok_or(TxError::Overflow)?;
+        let beneficiary = tx.from().address();
+        let balance = self.get_account(&beneficiary).map_or(0, |a| a.balance());
+        if balance.checked_add(tx.amount()).is_none() {
+            return Err(TxError::Overflow);
+        }
+        self.record_account_undo(beneficiary);
+        self.undo.total_supply.get_or_insert(self.total_supply);
+        self.undo.fees.get_or_insert(self.fees);
+        self.get_or_create_account(&beneficiary)
+            .deposit(tx.amount())?;
+        self.commit_account(&beneficiary);
+        self.total_supply = total_supply;
+        self.fees = 0;
+        Ok(())
+    }
+
+    /// Check `uncle` may be referenced by block `index` of this chain: a
+    /// block of the last `MAX_UNCLE_DEPTH` heights forking off the chain
+    /// right below it, not referenced yet and mined at the difficulty of its
+    /// height
+    fn check_uncle(&self, uncle: &Uncle, index: u64) -> Result<(), UncleError> {
+        let config = self
+            .consensus
+            .proof_of_work
+            .as_ref()
+            .ok_or(UncleError::WithoutProofOfWork)?;
+        let header = uncle.header();
+        let height = header.index();
+        if height == 0 || height >= index || index - height > MAX_UNCLE_DEPTH {
+            return Err(UncleError::OutOfWindow);
+        }
+        if self.history.leaf(height - 1) != Some(header.previous_hash())
+            || self.history.leaf(height) == Some(uncle.hash())
         {
-            self.get_account_mut(&tx.from()).unwrap().increment_nonce();
+            return Err(UncleError::NotStale);
+        }
+        if self.included_uncles.contains_key(&uncle.hash()) {
+            return Err(UncleError::AlreadyIncluded);
+        }
+        let history = self
+            .work_history
+            .get(..height as usize)
+            .ok_or(UncleError::OutOfWindow)?;
+        let expected = next_difficulty(config, history);
+        if header.difficulty() != expected {
+            return Err(UncleError::InvalidDifficulty {
+                expected,
+                got: header.difficulty(),
+            });
         }
+        if !meets_difficulty(uncle.hash().as_ref(), expected) {
+            return Err(UncleError::InsufficientWork);
+        }
+        Ok(())
+    }
 
+    /// check the uncles of block `index` and mint the reward of their
+    /// producers
+    fn execute_uncles(&mut self, index: u64, uncles: &[Uncle]) -> Result<(), LedgerError> {
+        for (position, uncle) in uncles.iter().enumerate() {
+            let result = if uncles[..position].iter().any(|u| u.hash() == uncle.hash()) {
+                Err(UncleError::AlreadyIncluded)
+            } else {
+                self.check_uncle(uncle, index)
+            };
+            result.map_err(|error| LedgerError::InvalidUncle {
+                index,
+                uncle: uncle.hash(),
+                error,
+            })?;
+            let reward = self.consensus.uncle_reward(index - uncle.header().index());
+            if reward == 0 {
+                continue;
+            }
+            let total_supply = self
+                .total_supply
+                .checked_add(reward)
+                .ok_or(TxError::Overflow)?;
+            let beneficiary = uncle.beneficiary().address();
+            let balance = self.get_account(&beneficiary).map_or(0, |a| a.balance());
+            if balance.checked_add(reward).is_none() {
+                return Err(TxError::Overflow.into());
+            }
+            self.record_account_undo(beneficiary);
+            self.undo.total_supply.get_or_insert(self.total_supply);
+            self.get_or_create_account(&beneficiary).deposit(reward)?;
+            self.commit_account(&beneficiary);
+            self.total_supply = total_supply;
+        }
         Ok(())
     }
 
+    /// known stale blocks the pending block can reference, the most recent
+    /// first
+    fn uncle_candidates(&self) -> Vec<Uncle> {
+        let index = self.pending_block.index();
+        let mut candidates: Vec<Uncle> = self
+            .side_blocks
+            .values()
+            .filter_map(Uncle::new)
+            .filter(|uncle| self.check_uncle(uncle, index).is_ok())
+            .collect();
+        candidates.sort_by(|a, b| {
+            (b.header().index(), a.hash().as_ref()).cmp(&(a.header().index(), b.hash().as_ref()))
+        });
+        candidates.truncate(MAX_UNCLES);
+        candidates
+    }
+
+    /// coinbase closing the pending block, `None` without a beneficiary or
+    /// nothing to collect
+    fn pending_coinbase(&self) -> Option<Transaction> {
+        let beneficiary = self.beneficiary?;
+        let amount = self.fees.checked_add(self.consensus.block_reward)?;
+        (amount > 0)
+            .then(|| Transaction::new_coinbase(&beneficiary, amount, self.pending_block.index()))
+    }
+
     /// ignore the nonce check and from account balance check
-    fn execute_transaction_genesis(&mut self, tx: &Transaction) -> Result<(), String> {
+    fn execute_transaction_genesis(&mut self, tx: &Transaction) -> Result<(), TxError> {
         tx.verify()?;
         let amount = tx.amount();
         if amount == 0 {
-            return Err("Invalid transaction amount".to_string());
+            return Err(TxError::InvalidAmount);
         }
-        let to_acount = match self.get_account(&tx.to()) {
-            Some(account) => account,
-            None => self.add_account(&tx.to()).unwrap(),
-        };
-        if to_acount.balance() + amount < to_acount.balance() {
-            return Err("Overflow error".to_string());
-        }
-        self.get_account_mut(&tx.to()).unwrap().deposit(amount);
+        let to = tx.to().ok_or(TxError::GenesisNotTransfer)?;
+        let total_supply = self
+            .total_supply
+            .checked_add(amount)
+            .ok_or(TxError::Overflow)?;
+        self.get_or_create_account(&to).deposit(amount)?;
+        self.commit_account(&to);
+        self.total_supply = total_supply;
         Ok(())
     }
 
+    /// Transaction of the pending block or of the last `MAX_REORG_DEPTH`
+    /// blocks already consuming the nonce of `tx`, `None` when the nonce is
+    /// free, consumed by `tx` itself or by an older block
+    pub fn conflicts_with(&self, tx: &Transaction) -> Option<TxId> {
+        self.spent_nonces
+            .get(&(tx.from(), tx.nonce()))
+            .copied()
+            .filter(|id| *id != tx.id())
+    }
+
     pub fn last_block(&self) -> Option<&Block> {
-        self.blocks.last()
+        Some(&self.tip)
     }
 
     pub fn pending_block(&self) -> &BlockBuilder {
         &self.pending_block
     }
 
-    pub fn finalize_and_mint_pending_block(&mut self) {
-        self.blocks.push(Block::from(self.pending_block.clone()));
-        self.pending_block = BlockBuilder::new(
-            self.last_block().unwrap().index() + 1,
-            &self.last_block().unwrap().hash(),
-        );
+    fn index_transactions(&mut self, block: &Block) {
+        for (position, tx) in block.transactions().iter().enumerate() {
+            self.transaction_index
+                .insert(tx.id(), (block.index(), position));
+        }
+    }
+
+    /// producer data committed in the header of the pending block and the
+    /// following ones
+    pub fn set_extra_data(&mut self, extra_data: &[u8]) -> Result<(), BlockError> {
+        self.pending_block.set_extra_data(extra_data)
+    }
+
+    /// Assemble the pending block with the ready mempool transactions and
+    /// append it to the chain
+    pub fn finalize_and_mint_pending_block(&mut self) -> Result<(), LedgerError> {
+        let mut template = self.seal_pending_block()?;
+        if !template.mine(&Miner::with_available_parallelism(), &CancelToken::new()) {
+            return Err(LedgerError::InsufficientWork(template.index()));
+        }
+        self.mint_sealed_block(template)
+    }
+
+    /// Close the pending block with the ready mempool transactions, its
+    /// coinbase and uncles, and return it to be mined, e.g. by a thread not
+    /// holding the chain. A transaction added or a block imported meanwhile
+    /// reopens the pending block and makes the template stale.
+    pub fn seal_pending_block(&mut self) -> Result<BlockTemplate, LedgerError> {
+        self.unseal();
+        self.assemble_pending_block();
+        self.sealed = true;
+        if let Some(coinbase) = self.pending_coinbase() {
+            self.execute_coinbase(&coinbase, coinbase.nonce())?;
+            self.pending_block.add_transaction(coinbase)?;
+        }
+        for uncle in self.uncle_candidates() {
+            self.pending_block.add_uncle(uncle)?;
+        }
+        let uncles = self.pending_block.uncles().to_vec();
+        self.execute_uncles(self.pending_block.index(), &uncles)?;
+        let state_root = self.state_root();
+        self.pending_block.set_state_root(&state_root);
+        let timestamp = self
+            .network_time
+            .now()
+            .max(self.last_block().map_or(0, |b| b.timestamp()));
+        let difficulty = self.next_difficulty();
+        self.pending_block.set_work(difficulty.unwrap_or(0), 0);
+        Ok(BlockTemplate {
+            builder: self.pending_block.clone(),
+            difficulty,
+            timestamp,
+        })
+    }
+
+    /// Append the block of `template`, mined since `seal_pending_block`
+    /// returned it, unless the chain moved on in the meantime
+    pub fn mint_sealed_block(&mut self, template: BlockTemplate) -> Result<(), LedgerError> {
+        let mut unmined = template.builder.clone();
+        unmined.set_work(template.difficulty.unwrap_or(0), 0);
+        if !self.sealed || unmined != self.pending_block {
+            return Err(LedgerError::StaleTemplate);
+        }
+        if let Some(difficulty) = template.difficulty {
+            let hash = template.builder.hash(template.timestamp);
+            if !meets_difficulty(hash.as_ref(), difficulty) {
+                return Err(LedgerError::InsufficientWork(template.index()));
+            }
+        }
+        self.sealed = false;
+        self.pending_block = template.builder;
+        let block = self.pending_block.take(template.timestamp);
+        // persisted before it becomes the tip, on failure the block is
+        // dropped and its transactions go back to the mempool
+        if let Err(e) = store_block(self.storage.as_mut(), &self.accounts, &block) {
+            self.rollback();
+            for tx in block.transactions().iter().filter(|tx| !tx.is_coinbase()) {
+                self.requeue(tx.clone());
+            }
+            return Err(e);
+        }
+        self.append_block(block);
+        self.prune_mempool();
+        Ok(())
+    }
+
+    pub fn set_timestamp_policy(&mut self, policy: TimestampPolicy) {
+        self.timestamp_policy = policy;
+    }
+
+    /// approximate heap usage of the in-memory structures, blocks other than
+    /// the tip are left to the storage
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            accounts: memory::btree_bytes(&self.accounts),
+            blocks: memory::block_bytes(&self.tip)
+                + self
+                    .side_blocks
+                    .values()
+                    .map(memory::block_bytes)
+                    .sum::<usize>(),
+            indexes: memory::map_bytes(&self.transaction_index)
+                + memory::map_bytes(&self.spent_nonces)
+                + memory::map_bytes(&self.included_uncles)
+                + memory::btree_bytes(&self.burned)
+                + memory::btree_bytes(&self.fee_grants)
+                + memory::btree_bytes(&self.validations)
+                + self.work_history.capacity() * std::mem::size_of::<(u64, u64)>()
+                + self.chain_work.capacity() * std::mem::size_of::<u128>(),
+            pending_block: memory::pending_block_bytes(&self.pending_block),
+        }
+    }
+
+    /// hooks called on chain events
+    pub fn notifier_mut(&mut self) -> &mut Notifier {
+        &mut self.notifier
+    }
+
+    /// peer clock offsets, fed by the networking layer
+    pub fn network_time_mut(&mut self) -> &mut NetworkTime {
+        &mut self.network_time
+    }
+
+    /// check the timestamp of a block extending the current tip against the
+    /// network adjusted time
+    pub fn check_block_timestamp(&self, block: &Block) -> Result<(), LedgerError> {
+        self.timestamp_policy
+            .check(block, &self.tip, self.network_time.now())
+    }
+
+    /// MMR root over every block, committed in the header of the next block
+    pub fn history_root(&self) -> Hash {
+        self.history.root()
+    }
+
+    /// Proof that block `index` is part of the history committed in the
+    /// header of block `at`, verified with `MmrProof::verify` against the
+    /// block hash and that header's `history_root`
+    pub fn history_proof(&self, index: u64, at: u64) -> Option<MmrProof> {
+        self.history.proof(index, at)
     }
 
     pub fn last_block_hash(&self) -> Option<Hash> {
         self.last_block().map(|b| b.hash())
     }
 
-    pub fn get_block_by_index(&self, index: u64) -> Option<&Block> {
-        self.blocks.get(index as usize)
+    pub fn get_block_by_index(&self, index: u64) -> Result<Option<Block>, LedgerError> {
+        if index == self.tip.index() {
+            return Ok(Some(self.tip.clone()));
+        }
+        self.storage
+            .block_by_index(index)
+            .map_err(LedgerError::Storage)
+    }
+
+    pub fn get_block_by_hash(&self, hash: &Hash) -> Result<Option<Block>, LedgerError> {
+        if *hash == self.tip.hash() {
+            return Ok(Some(self.tip.clone()));
+        }
+        self.storage
+            .block_by_hash(hash)
+            .map_err(LedgerError::Storage)
+    }
+
+    /// confirmed transaction and the block including it
+    pub fn get_transaction(&self, id: &TxId) -> Result<Option<(Block, Transaction)>, LedgerError> {
+        let Some((index, position)) = self.transaction_index.get(id) else {
+            return Ok(None);
+        };
+        let block = self
+            .get_block_by_index(*index)?
+            .ok_or(LedgerError::MissingBlock(*index))?;
+        let tx = block
+            .transactions()
+            .get(*position)
+            .cloned()
+            .ok_or(LedgerError::HistoryMismatch)?;
+        Ok(Some((block, tx)))
+    }
+
+    /// amount in circulation: minted at genesis and by block and uncle
+    /// rewards, minus everything burned and the fees no coinbase collected yet
+    pub fn total_supply(&self) -> u64 {
+        self.total_supply
+    }
+
+    /// everything burned since genesis, saturating at `u64::MAX`
+    pub fn total_burned(&self) -> u64 {
+        self.burned
+            .values()
+            .fold(0, |total, burned| total.saturating_add(*burned))
+    }
+
+    /// total amount destroyed by burn transactions sent from `address`
+    pub fn burned_by(&self, address: &PublicKey) -> u64 {
+        self.burned.get(address).copied().unwrap_or(0)
+    }
+
+    /// burn transactions from `address` together with the index of the block
+    /// including them, usable as proof of burn
+    pub fn burns_by(&self, address: &PublicKey) -> Result<Vec<(u64, Transaction)>, LedgerError> {
+        let mut burns = vec![];
+        for block in self.blocks()? {
+            for tx in block.transactions() {
+                if tx.is_burn() && tx.from() == *address {
+                    burns.push((block.index(), tx.clone()));
+                }
+            }
+        }
+        Ok(burns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{generate_keypair_from_secret, sign_hash};
+    use crate::difficulty::DifficultyConfig;
+    use crate::fault::FaultyStorage;
+    use crate::fixture::{test_chain, test_genesis};
+    use crate::mempool::MempoolError;
+    use crate::script::{Op, ScriptError, CHECK_SIG_GAS};
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_burn_reduces_supply() {
+        let (private_key, public_key, mut blockchain) = test_chain();
+
+        let mut burn = Transaction::new_burn(&public_key, 300, 0);
+        burn.sign(&private_key).unwrap();
+        blockchain.add_transaction(burn).unwrap();
+        blockchain.finalize_and_mint_pending_block().unwrap();
+
+        assert_eq!(blockchain.total_supply(), 700);
+        assert_eq!(blockchain.burned_by(&public_key), 300);
+        assert_eq!(
+            blockchain
+                .get_account(&public_key.address())
+                .unwrap()
+                .balance(),
+            700
+        );
+        assert_eq!(blockchain.burns_by(&public_key).unwrap().len(), 1);
+        assert_eq!(blockchain.burns_by(&public_key).unwrap()[0].0, 1);
+    }
+
+    #[test]
+    fn test_fees_and_coinbase() {
+        let (private_key, public_key, genesis) = test_genesis();
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let (_, miner) = generate_keypair_from_secret(b"miner");
+        let mut producer = Blockchain::new(genesis.clone(), 0).unwrap();
+        let mut node = Blockchain::new(genesis, 0).unwrap();
+        let consensus = ConsensusConfig {
+            block_reward: 50,
+            ..ConsensusConfig::default()
+        };
+        producer.set_consensus(consensus);
+        producer.set_beneficiary(Some(miner));
+        node.set_consensus(consensus);
+
+        let mut tx = Transaction::new(&public_key, &other.address(), 100, 0);
+        tx.set_fee(1000);
+        tx.sign(&private_key).unwrap();
+        assert_eq!(
+            producer.add_transaction(tx),
+            Err(TxError::InsufficientFunds {
+                balance: 1000,
+                required: 1100
+            })
+        );
+        let mut tx = Transaction::new(&public_key, &other.address(), 100, 0);
+        tx.set_fee(10);
+        tx.sign(&private_key).unwrap();
+        producer.add_transaction(tx).unwrap();
+        assert_eq!(producer.pending_fees(), 10);
+        let coinbase = Transaction::new_coinbase(&miner, 60, 1);
+        assert_eq!(
+            producer.add_transaction(coinbase),
+            Err(TxError::UnexpectedCoinbase)
+        );
+        producer.finalize_and_mint_pending_block().unwrap();
+
+        let block = producer.last_block().unwrap().clone();
+        assert_eq!(block.transactions().last().unwrap().amount(), 60);
+        assert_eq!(
+            producer
+                .get_account(&public_key.address())
+                .unwrap()
+                .balance(),
+            890
+        );
+        assert_eq!(
+            producer.get_account(&miner.address()).unwrap().balance(),
+            60
+        );
+        assert_eq!(producer.total_supply(), 1050);
+        assert_eq!(producer.pending_fees(), 0);
+        node.import_block(block).unwrap();
+        assert_eq!(node.state_root(), producer.state_root());
+        assert_eq!(
+            Blockchain::from_blocks(producer.blocks().unwrap(), ConsensusConfig::default()).err(),
+            Some(LedgerError::BlockTransaction {
+                index: 1,
+                error: TxError::InvalidCoinbaseAmount {
+                    expected: 10,
+                    got: 60
+                }
+            })
+        );
+
+        let mut builder = producer.pending_block().clone();
+        builder
+            .add_transaction(Transaction::new_coinbase(&miner, 100, 2))
+            .unwrap();
+        let timestamp = producer.last_block().unwrap().timestamp();
+        assert_eq!(
+            node.import_block(builder.build_at(timestamp)),
+            Err(LedgerError::BlockTransaction {
+                index: 2,
+                error: TxError::InvalidCoinbaseAmount {
+                    expected: 50,
+                    got: 100
+                }
+            })
+        );
+        producer.validate_chain().unwrap();
+    }
+
+    #[test]
+    fn test_multisig_account() {
+        let (private_key, public_key, mut blockchain) = test_chain();
+        let cosigners: Vec<_> = [b"a", b"b", b"c"]
+            .iter()
+            .map(|secret| generate_keypair_from_secret(&secret[..]))
+            .collect();
+        let signed = |kind, amount, nonce, cosigned: &[usize]| {
+            let mut tx = Transaction::with_kind(&public_key, kind, amount, nonce);
+            tx.sign(&private_key).unwrap();
+            for i in cosigned {
+                tx.cosign(&cosigners[*i].0).unwrap();
+            }
+            tx
+        };
+        let transfer = TransactionKind::Transfer {
+            to: public_key.address(),
+        };
+
+        let mut validation = Validation {
+            cosigners: cosigners
+                .iter()
+                .map(|(_, public_key)| *public_key)
+                .collect(),
+            threshold: 4,
+            spend_limit: Some(100),
+            predicate: None,
+        };
+        let kind = TransactionKind::SetValidation(validation.clone());
+        assert_eq!(
+            blockchain.add_transaction(signed(kind, 0, 0, &[])),
+            Err(TxError::InvalidValidation)
+        );
+        validation.threshold = 2;
+        let kind = TransactionKind::SetValidation(validation.clone());
+        assert_eq!(
+            blockchain.add_transaction(signed(kind.clone(), 1, 0, &[])),
+            Err(TxError::InvalidAmount)
+        );
+        blockchain.add_transaction(signed(kind, 0, 0, &[])).unwrap();
+        assert_eq!(blockchain.validation(&public_key), Some(&validation));
+        assert_eq!(
+            blockchain.add_transaction(signed(transfer.clone(), 10, 1, &[1])),
+            Err(TxError::NotEnoughCosignatures {
+                threshold: 2,
+                signed: 1
+            })
+        );
+        assert_eq!(
+            blockchain.add_transaction(signed(transfer.clone(), 101, 1, &[0, 1])),
+            Err(TxError::SpendLimitExceeded {
+                limit: 100,
+                spent: 101
+            })
+        );
+        blockchain
+            .add_transaction(signed(transfer.clone(), 10, 1, &[2, 0]))
+            .unwrap();
+        blockchain.finalize_and_mint_pending_block().unwrap();
+
+        let kind = TransactionKind::SetValidation(Validation::default());
+        blockchain
+            .add_transaction(signed(kind, 0, 2, &[0, 1]))
+            .unwrap();
+        assert_eq!(blockchain.validation(&public_key), None);
+        assert_eq!(
+            blockchain.add_transaction(signed(transfer.clone(), 500, 3, &[0])),
+            Err(TxError::TooManyCosignatures)
+        );
+        blockchain
+            .add_transaction(signed(transfer, 500, 3, &[]))
+            .unwrap();
+        blockchain.finalize_and_mint_pending_block().unwrap();
+        blockchain.validate_chain().unwrap();
+    }
+
+    #[test]
+    fn test_predicate_account() {
+        let (private_key, public_key, mut blockchain) = test_chain();
+        let (second_key, second) = generate_keypair_from_secret(b"second");
+        let signed = |kind, amount, nonce, cosigned: bool| {
+            let mut tx = Transaction::with_kind(&public_key, kind, amount, nonce);
+            tx.sign(&private_key).unwrap();
+            if cosigned {
+                tx.push_witness_signature(&second_key).unwrap();
+            }
+            tx
+        };
+        let transfer = TransactionKind::Transfer {
+            to: second.address(),
+        };
+
+        // up to 50 with the account key, the second key cosigning above
+        let validation = Validation {
+            predicate: Some(vec![
+                Op::PushSpent,
+                Op::PushNumber(50),
+                Op::LessOrEqual,
+                Op::If,
+                Op::PushNumber(1),
+                Op::Else,
+                Op::Push(second.as_ref().to_vec()),
+                Op::CheckSig,
+                Op::EndIf,
+            ]),
+            ..Validation::default()
+        };
+        let mut witnessed = signed(transfer.clone(), 5, 0, false);
+        witnessed.push_witness(vec![1]);
+        assert_eq!(
+            blockchain.add_transaction(witnessed),
+            Err(TxError::UnexpectedWitness)
+        );
+        let kind = TransactionKind::SetValidation(validation.clone());
+        blockchain
+            .add_transaction(signed(kind, 0, 0, false))
+            .unwrap();
+        assert_eq!(blockchain.validation(&public_key), Some(&validation));
+        blockchain
+            .add_transaction(signed(transfer.clone(), 50, 1, false))
+            .unwrap();
+        assert_eq!(
+            blockchain.add_transaction(signed(transfer.clone(), 51, 2, false)),
+            Err(TxError::PredicateFailed(ScriptError::StackUnderflow))
+        );
+        blockchain
+            .add_transaction(signed(transfer.clone(), 51, 2, true))
+            .unwrap();
+
+        // a hash lock, or the second key once the preimage leaked, picked
+        // by the top value of the witness
+        let preimage = b"order 7".to_vec();
+        let hash_lock = Validation {
+            predicate: Some(vec![
+                Op::If,
+                Op::Sha256,
+                Op::Push(Hash::from(preimage.as_slice()).as_ref().to_vec()),
+                Op::Equal,
+                Op::Else,
+                Op::Push(second.as_ref().to_vec()),
+                Op::CheckSig,
+                Op::EndIf,
+            ]),
+            ..Validation::default()
+        };
+        let kind = TransactionKind::SetValidation(hash_lock);
+        blockchain
+            .add_transaction(signed(kind, 0, 3, false))
+            .unwrap();
+        let revealing = |nonce, revealed: &[u8]| {
+            let mut tx = signed(transfer.clone(), 5, nonce, false);
+            tx.push_witness(revealed.to_vec());
+            tx.push_witness(vec![1]);
+            tx
+        };
+        assert_eq!(
+            blockchain.add_transaction(revealing(4, b"guess")),
+            Err(TxError::PredicateFailed(ScriptError::Rejected))
+        );
+        blockchain.add_transaction(revealing(4, &preimage)).unwrap();
+        let mut by_key = signed(transfer, 5, 5, true);
+        by_key.push_witness(vec![0]);
+        blockchain.add_transaction(by_key).unwrap();
+        blockchain.finalize_and_mint_pending_block().unwrap();
+        blockchain.validate_chain().unwrap();
+
+        // the gas limit is a consensus rule, a chain allowing less rejects
+        // the blocks the predicate accepted
+        let consensus = ConsensusConfig {
+            predicate_gas_limit: CHECK_SIG_GAS,
+            ..ConsensusConfig::default()
+        };
+        assert_eq!(
+            Blockchain::from_blocks(blockchain.blocks().unwrap(), consensus).err(),
+            Some(LedgerError::BlockTransaction {
+                index: 1,
+                error: TxError::PredicateFailed(ScriptError::OutOfGas)
+            })
+        );
+    }
+
+    #[test]
+    fn test_fee_grant() {
+        let (private_key, public_key, genesis) = test_genesis();
+        let (other_key, other) = generate_keypair_from_secret(b"other");
+        let mut blockchain = Blockchain::new(genesis.clone(), 0).unwrap();
+        let mut node = Blockchain::new(genesis, 0).unwrap();
+        let granted = |fee, nonce, granter| {
+            let mut tx = Transaction::new(&other, &public_key.address(), 5, nonce);
+            tx.set_fee(fee);
+            tx.set_fee_granter(Some(granter));
+            tx.sign(&other_key).unwrap();
+            tx
+        };
+
+        let grant = TransactionKind::GrantFees {
+            grantee: other,
+            expires_at: 3,
+        };
+        let mut tx = Transaction::with_kind(&public_key, grant, 20, 0);
+        tx.sign(&private_key).unwrap();
+        blockchain.add_transaction(tx).unwrap();
+        let tx =
+            Transaction::new_and_sign(&public_key, &other.address(), 5, 1, &private_key).unwrap();
+        blockchain.add_transaction(tx).unwrap();
+        assert_eq!(
+            blockchain.add_transaction(granted(15, 0, other)),
+            Err(TxError::InvalidFeeGranter)
+        );
+        blockchain
+            .add_transaction(granted(15, 0, public_key))
+            .unwrap();
+        assert_eq!(
+            blockchain.add_transaction(granted(10, 1, public_key)),
+            Err(TxError::FeeGrantExceeded {
+                remaining: 5,
+                required: 10
+            })
+        );
+        blockchain.finalize_and_mint_pending_block().unwrap();
+        assert_eq!(
+            blockchain
+                .get_account(&public_key.address())
+                .unwrap()
+                .balance(),
+            985
+        );
+        assert_eq!(
+            blockchain.get_account(&other.address()).unwrap().balance(),
+            0
+        );
+        assert_eq!(
+            blockchain.fee_grant(&public_key, &other),
+            Some(FeeGrant {
+                remaining: 5,
+                expires_at: 3
+            })
+        );
+        node.import_block(blockchain.last_block().unwrap().clone())
+            .unwrap();
+        assert_eq!(
+            node.fee_grant(&public_key, &other),
+            blockchain.fee_grant(&public_key, &other)
+        );
+
+        blockchain.finalize_and_mint_pending_block().unwrap();
+        assert_eq!(
+            blockchain.add_transaction(granted(1, 1, public_key)),
+            Err(TxError::FeeGrantExpired)
+        );
+        let (_, stranger) = generate_keypair_from_secret(b"stranger");
+        assert_eq!(
+            blockchain.add_transaction(granted(1, 1, stranger)),
+            Err(TxError::NoFeeGrant)
+        );
+        blockchain.validate_chain().unwrap();
+    }
+
+    #[test]
+    fn test_transaction_index() {
+        let (private_key, public_key, genesis) = test_genesis();
+        let genesis_id = genesis.id();
+        let mut blockchain = Blockchain::new(genesis, 0).unwrap();
+
+        let tx = Transaction::new_and_sign(&public_key, &public_key.address(), 10, 0, &private_key)
+            .unwrap();
+        let id = tx.id();
+        assert_ne!(id.hash(), tx.signing_hash());
+        blockchain.add_transaction(tx.clone()).unwrap();
+        assert!(blockchain.get_transaction(&id).unwrap().is_none());
+
+        blockchain.finalize_and_mint_pending_block().unwrap();
+        let (block, found) = blockchain.get_transaction(&id).unwrap().unwrap();
+        assert_eq!(block.index(), 1);
+        assert_eq!(found, tx);
+        assert_eq!(
+            blockchain
+                .get_transaction(&genesis_id)
+                .unwrap()
+                .unwrap()
+                .0
+                .index(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_conflicting_transactions() {
+        let (private_key, public_key, mut blockchain) = test_chain();
+        let (_, other) = generate_keypair_from_secret(b"other");
+
+        let tx =
+            Transaction::new_and_sign(&public_key, &other.address(), 10, 0, &private_key).unwrap();
+        let double_spend =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 10, 0, &private_key)
+                .unwrap();
+        assert_eq!(blockchain.conflicts_with(&double_spend), None);
+
+        blockchain.add_transaction(tx.clone()).unwrap();
+        assert_eq!(blockchain.conflicts_with(&tx), None);
+        assert_eq!(blockchain.conflicts_with(&double_spend), Some(tx.id()));
+
+        blockchain.finalize_and_mint_pending_block().unwrap();
+        assert_eq!(blockchain.conflicts_with(&double_spend), Some(tx.id()));
+
+        // forgotten once the block can no longer be reverted
+        for _ in 1..MAX_REORG_DEPTH {
+            blockchain.finalize_and_mint_pending_block().unwrap();
+        }
+        assert_eq!(blockchain.conflicts_with(&double_spend), Some(tx.id()));
+        blockchain.finalize_and_mint_pending_block().unwrap();
+        assert_eq!(blockchain.conflicts_with(&double_spend), None);
+        assert!(blockchain.spent_nonces.is_empty());
+        assert!(matches!(
+            blockchain.add_transaction(double_spend),
+            Err(TxError::InvalidNonce { .. })
+        ));
     }
 
-    pub fn get_block_by_hash(&self, hash: &Hash) -> Option<&Block> {
-        self.blocks.iter().find(|b| b.hash() == *hash)
+    #[test]
+    fn test_send_raw_transaction() {
+        let (private_key, public_key, mut blockchain) = test_chain();
+        let (_, other) = generate_keypair_from_secret(b"other");
+
+        let tx =
+            Transaction::new_and_sign(&public_key, &other.address(), 10, 0, &private_key).unwrap();
+        let mut tampered = tx.to_bytes();
+        let last = tampered.len() - 9;
+        tampered[last] ^= 1;
+        assert!(matches!(
+            blockchain.send_raw_transaction(&hex::encode(tampered)),
+            Err(AdmissionError::Invalid(_))
+        ));
+        assert!(blockchain.send_raw_transaction("zz").is_err());
+
+        let id = blockchain.send_raw_transaction(&tx.to_hex()).unwrap();
+        assert_eq!(id, tx.id());
+        assert_eq!(blockchain.conflicts_with(&tx), None);
+    }
+
+    #[test]
+    fn test_build_unsigned_transfer() {
+        let (private_key, public_key, mut blockchain) = test_chain();
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let fee_policy = FeePolicy::Fixed(2);
+        assert_eq!(
+            blockchain
+                .build_unsigned_transfer(&other, &public_key.address(), 1, &fee_policy)
+                .err(),
+            Some(TxError::AccountNotFound)
+        );
+        assert_eq!(
+            blockchain
+                .build_unsigned_transfer(&public_key, &other.address(), 999, &fee_policy)
+                .err(),
+            Some(TxError::InsufficientFunds {
+                balance: 1000,
+                required: 1001
+            })
+        );
+
+        for nonce in 0..2 {
+            let unsigned = blockchain
+                .build_unsigned_transfer(&public_key, &other.address(), 10, &fee_policy)
+                .unwrap();
+            assert_eq!(unsigned.transaction.nonce(), nonce);
+            assert_eq!(unsigned.transaction.fee(), 2);
+            assert_eq!(unsigned.payload, unsigned.transaction.to_bytes());
+            let signature = sign_hash(&unsigned.signing_hash, &private_key).unwrap();
+            let mut tx = unsigned.transaction;
+            tx.set_signature(&signature);
+            blockchain.add_transaction(tx).unwrap();
+        }
+        assert_eq!(FeePolicy::Priority { minimum: 1 }.fee(&blockchain), 2);
+        assert_eq!(FeePolicy::Priority { minimum: 5 }.fee(&blockchain), 5);
+    }
+
+    #[test]
+    fn test_import_block() {
+        let (private_key, public_key, genesis) = test_genesis();
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let mut producer = Blockchain::new(genesis.clone(), 0).unwrap();
+        let mut node = Blockchain::new(genesis, 0).unwrap();
+
+        let tx =
+            Transaction::new_and_sign(&public_key, &other.address(), 10, 0, &private_key).unwrap();
+        producer.add_transaction(tx).unwrap();
+        producer.finalize_and_mint_pending_block().unwrap();
+        let double_spend =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 10, 0, &private_key)
+                .unwrap();
+        node.add_transaction(double_spend.clone()).unwrap();
+
+        let block = producer.last_block().unwrap().clone();
+        assert_eq!(
+            node.import_block(block.clone()).unwrap(),
+            vec![double_spend]
+        );
+        assert_eq!(node.last_block_hash(), Some(block.hash()));
+        assert_eq!(node.state_root(), producer.state_root());
+        assert!(node.import_block(block).is_err());
+
+        // a block passing the header checks but failing execution
+        let pending =
+            Transaction::new_and_sign(&public_key, &other.address(), 5, 1, &private_key).unwrap();
+        node.add_transaction(pending.clone()).unwrap();
+        let state_root = node.state_root();
+        let mut builder = producer.pending_block().clone();
+        builder.add_transaction(pending).unwrap();
+        let replayed_nonce =
+            Transaction::new_and_sign(&public_key, &other.address(), 10, 0, &private_key).unwrap();
+        builder.add_transaction(replayed_nonce).unwrap();
+        let timestamp = producer.last_block().unwrap().timestamp();
+        assert_eq!(
+            node.import_block(builder.build_at(timestamp)),
+            Err(LedgerError::BlockTransaction {
+                index: 2,
+                error: TxError::InvalidNonce {
+                    expected: 2,
+                    got: 0
+                }
+            })
+        );
+        assert_eq!(node.state_root(), state_root);
+        assert_eq!(node.pending_block().transactions().len(), 1);
+        assert_eq!(node.last_block_hash(), producer.last_block_hash());
+
+        let future = producer
+            .pending_block()
+            .clone()
+            .build_at(crate::clock::now() + 3600);
+        assert!(matches!(
+            node.import_block(future),
+            Err(LedgerError::FutureTimestamp { index: 2, .. })
+        ));
+
+        node.finalize_and_mint_pending_block().unwrap();
+        node.validate_chain().unwrap();
+    }
+
+    #[test]
+    fn test_reject_invalid_state_root() {
+        let (private_key, public_key, genesis) = test_genesis();
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let mut producer = Blockchain::new(genesis.clone(), 0).unwrap();
+        let mut node = Blockchain::new(genesis, 0).unwrap();
+
+        let tx =
+            Transaction::new_and_sign(&public_key, &other.address(), 10, 0, &private_key).unwrap();
+        let mut builder = producer.pending_block().clone();
+        builder.add_transaction(tx.clone()).unwrap();
+        let state_root = node.state_root();
+        let forged = builder.build_at(0);
+        assert!(matches!(
+            node.import_block(forged.clone()),
+            Err(LedgerError::InvalidStateRoot {
+                index: 1,
+                expected,
+                computed,
+                first_diff: None,
+            }) if expected == forged.state_root() && computed != expected
+        ));
+        assert_eq!(node.state_root(), state_root);
+
+        // replayed against the state the header commits to, the error points
+        // at the first account the block should not have changed
+        let mut blocks = producer.blocks().unwrap();
+        blocks.push(forged);
+        let expected = producer.state_snapshot();
+        let commitment = Box::new(MerkleStateCommitment::new());
+        match Blockchain::replay(
+            blocks,
+            ConsensusConfig::default(),
+            commitment,
+            Some(&expected),
+        ) {
+            Err(LedgerError::InvalidStateRoot {
+                first_diff: Some(diff),
+                ..
+            }) => {
+                assert_eq!(diff.address, public_key.address());
+                assert_eq!(diff.left.map(|account| account.balance()), Some(1000));
+                assert_eq!(diff.right.map(|account| account.balance()), Some(990));
+            }
+            result => panic!("unexpected {:?}", result.err()),
+        }
+
+        producer.add_transaction(tx).unwrap();
+        producer.finalize_and_mint_pending_block().unwrap();
+        let block = producer.last_block().unwrap().clone();
+        assert_eq!(block.state_root(), producer.state_root());
+        node.import_block(block).unwrap();
+    }
+
+    #[test]
+    fn test_switch_to_heavier_fork() {
+        let (private_key, public_key, genesis) = test_genesis();
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let mut node = Blockchain::new(genesis.clone(), 0).unwrap();
+        let mut fork = Blockchain::new(genesis, 0).unwrap();
+
+        let tx =
+            Transaction::new_and_sign(&public_key, &other.address(), 10, 0, &private_key).unwrap();
+        node.add_transaction(tx.clone()).unwrap();
+        node.finalize_and_mint_pending_block().unwrap();
+        let abandoned = node.last_block().unwrap().clone();
+        let replacing =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 20, 0, &private_key)
+                .unwrap();
+        fork.add_transaction(replacing).unwrap();
+        fork.finalize_and_mint_pending_block().unwrap();
+        fork.finalize_and_mint_pending_block().unwrap();
+        let fork_blocks = fork.blocks().unwrap();
+
+        // same work as the canonical chain, kept aside
+        assert_eq!(node.import_block(fork_blocks[1].clone()), Ok(vec![]));
+        assert_eq!(node.chain_work(), 2);
+        assert_eq!(node.last_block_hash(), Some(abandoned.hash()));
+        assert_eq!(
+            node.import_block(fork_blocks[1].clone()),
+            Err(LedgerError::KnownBlock(1))
+        );
+
+        // the switch fails to reach the storage
+        let storage = FaultyStorage::new(MemoryStorage::new());
+        let injector = storage.injector();
+        node.set_storage(Box::new(storage)).unwrap();
+        let state_root = node.state_root();
+        injector.fail_write(3);
+        assert!(node.import_block(fork_blocks[2].clone()).is_err());
+        assert_eq!(node.last_block_hash(), Some(abandoned.hash()));
+        assert_eq!(node.state_root(), state_root);
+        assert!(node.get_transaction(&tx.id()).unwrap().is_some());
+        node.validate_chain().unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        node.notifier_mut().register(Box::new(sender));
+        node.import_block(fork_blocks[2].clone()).unwrap();
+        assert_eq!(node.blocks().unwrap(), fork_blocks);
+        assert_eq!(node.state_root(), fork.state_root());
+        assert_eq!(node.chain_work(), fork.chain_work());
+        assert_eq!(node.work_at(1), Some(2));
+        assert!(node.get_transaction(&tx.id()).unwrap().is_none());
+        assert_eq!(node.get_account(&other.address()), None);
+        assert_eq!(node.storage.account(&other.address()), Ok(None));
+        assert_eq!(node.side_blocks().collect::<Vec<_>>(), vec![&abandoned]);
+        node.validate_chain().unwrap();
+        let events: Vec<ChainEvent> = receiver.try_iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                ChainEvent::Reorg {
+                    depth: 1,
+                    old_tip: abandoned.hash(),
+                    new_tip: fork_blocks[2].hash()
+                },
+                ChainEvent::NewBlock {
+                    index: 1,
+                    hash: fork_blocks[1].hash()
+                },
+                ChainEvent::NewBlock {
+                    index: 2,
+                    hash: fork_blocks[2].hash()
+                },
+            ]
+        );
+
+        // a heavier branch failing to execute leaves the chain as it was
+        let timestamp = abandoned.timestamp();
+        let invalid = BlockBuilder::new(2, &abandoned.hash()).build_at(timestamp);
+        let child = BlockBuilder::new(3, &invalid.hash()).build_at(timestamp);
+        assert_eq!(node.import_block(invalid), Ok(vec![]));
+        assert_eq!(
+            node.import_block(child),
+            Err(LedgerError::InvalidHistoryRoot(2))
+        );
+        assert_eq!(node.blocks().unwrap(), fork_blocks);
+        assert_eq!(node.state_root(), fork.state_root());
+        assert_eq!(node.chain_work(), fork.chain_work());
+        assert_eq!(node.work_at(1), Some(2));
+        assert_eq!(node.side_blocks().count(), 1);
+        assert_eq!(receiver.try_iter().count(), 0);
+        node.validate_chain().unwrap();
+
+        let reopened = Blockchain::open(node.into_storage(), ConsensusConfig::default()).unwrap();
+        assert_eq!(reopened.state_root(), fork.state_root());
+    }
+
+    #[test]
+    fn test_side_blocks_are_capped() {
+        let (_, _, mut node) = test_chain();
+        let genesis_hash = node.last_block_hash().unwrap();
+        node.finalize_and_mint_pending_block().unwrap();
+
+        for timestamp in 0..=MAX_SIDE_BLOCKS as u64 {
+            let block = BlockBuilder::new(1, &genesis_hash).build_at(timestamp + 1);
+            assert_eq!(node.import_block(block), Ok(vec![]));
+        }
+        assert_eq!(node.side_blocks().count(), MAX_SIDE_BLOCKS);
+    }
+
+    #[test]
+    fn test_mempool_orders_and_replaces() {
+        let (private_key, public_key, mut blockchain) = test_chain();
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let transfer = |amount, nonce| {
+            Transaction::new_and_sign(&public_key, &other.address(), amount, nonce, &private_key)
+                .unwrap()
+        };
+
+        // submitted out of order, the future nonces wait for the gap
+        blockchain.submit_transaction(transfer(1, 2)).unwrap();
+        blockchain.submit_transaction(transfer(1, 1)).unwrap();
+        blockchain.submit_transaction(transfer(1, 4)).unwrap();
+        blockchain.submit_transaction(transfer(1, 0)).unwrap();
+        // a replacement must pay a higher fee
+        assert_eq!(
+            blockchain.submit_transaction(transfer(5, 1)),
+            Err(AdmissionError::Mempool(
+                MempoolError::ReplacementUnderpriced {
+                    fee: 0,
+                    required: 1
+                }
+            ))
+        );
+        let mut replacement = transfer(5, 1);
+        replacement.set_fee(1);
+        replacement.sign(&private_key).unwrap();
+        blockchain.submit_transaction(replacement.clone()).unwrap();
+        assert_eq!(blockchain.mempool().len(), 4);
+        assert!(blockchain.get_account(&other.address()).is_none());
+
+        blockchain.finalize_and_mint_pending_block().unwrap();
+        let block = blockchain.last_block().unwrap();
+        let nonces: Vec<u64> = block.transactions().iter().map(|tx| tx.nonce()).collect();
+        assert_eq!(nonces, vec![0, 1, 2]);
+        assert_eq!(block.transactions()[1], replacement);
+        assert_eq!(
+            blockchain.get_account(&other.address()).unwrap().balance(),
+            7
+        );
+        assert_eq!(blockchain.mempool().len(), 1);
+
+        assert!(matches!(
+            blockchain.submit_transaction(transfer(1, 2)),
+            Err(AdmissionError::Invalid(_))
+        ));
+        let mut unsigned = transfer(1, 3);
+        unsigned.set_signature(&Default::default());
+        assert!(blockchain.submit_transaction(unsigned).is_err());
+    }
+
+    #[test]
+    fn test_proof_of_work() {
+        let (_, _, mut producer) = test_chain();
+        let mut node =
+            Blockchain::from_blocks(producer.blocks().unwrap(), ConsensusConfig::default())
+                .unwrap();
+        let mut unmined = producer.pending_block().clone().build_at(1);
+        let consensus = ConsensusConfig {
+            proof_of_work: Some(DifficultyConfig {
+                initial_difficulty: 1 << 8,
+                ..DifficultyConfig::default()
+            }),
+            ..ConsensusConfig::default()
+        };
+        producer.set_consensus(consensus);
+        node.set_consensus(consensus);
+
+        assert_eq!(
+            node.import_block(unmined.clone()),
+            Err(LedgerError::InvalidDifficulty {
+                index: 1,
+                expected: 1 << 8,
+                got: 0
+            })
+        );
+        producer.finalize_and_mint_pending_block().unwrap();
+        let block = producer.last_block().unwrap().clone();
+        assert_eq!(block.difficulty(), 1 << 8);
+        assert!(meets_difficulty(block.hash().as_ref(), 1 << 8));
+        node.import_block(block).unwrap();
+        assert_eq!(node.chain_work(), 1 + (1 << 8));
+        assert_eq!(node.chain_work(), producer.chain_work());
+
+        // claiming the difficulty without doing the work
+        let mut builder = node.pending_block().clone();
+        let difficulty = node.next_difficulty().unwrap();
+        let timestamp = node.last_block().unwrap().timestamp();
+        builder.set_work(difficulty, 0);
+        let nonce = (0..)
+            .find(|nonce| {
+                !meets_difficulty(
+                    builder.hash_with_nonce(timestamp, *nonce).as_ref(),
+                    difficulty,
+                )
+            })
+            .unwrap();
+        builder.set_work(difficulty, nonce);
+        unmined = builder.build_at(timestamp);
+        assert_eq!(
+            node.import_block(unmined),
+            Err(LedgerError::InsufficientWork(2))
+        );
+
+        // the work is checked again when replaying the chain
+        producer.finalize_and_mint_pending_block().unwrap();
+        producer.validate_chain().unwrap();
+        let blocks = producer.blocks().unwrap();
+        let chain_work = producer.chain_work();
+        let reopened = Blockchain::open(producer.into_storage(), consensus).unwrap();
+        assert_eq!(reopened.chain_work(), chain_work);
+        let mut forged = blocks.clone();
+        let mut builder = BlockBuilder::new(2, &blocks[1].hash());
+        builder.set_history_root(&blocks[2].history_root());
+        forged[2] = builder.build_at(blocks[2].timestamp());
+        assert_eq!(
+            Blockchain::from_blocks(forged, consensus).err(),
+            Some(LedgerError::InvalidDifficulty {
+                index: 2,
+                expected: blocks[2].difficulty(),
+                got: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_mint_sealed_block() {
+        let (private_key, public_key, mut blockchain) = test_chain();
+        blockchain.set_consensus(ConsensusConfig {
+            proof_of_work: Some(DifficultyConfig {
+                initial_difficulty: 1 << 4,
+                ..DifficultyConfig::default()
+            }),
+            ..ConsensusConfig::default()
+        });
+        let transfer = |nonce| {
+            Transaction::new_and_sign(&public_key, &public_key.address(), 10, nonce, &private_key)
+                .unwrap()
+        };
+        let miner = Miner::new(1);
+        let cancel = CancelToken::new();
+        blockchain.add_transaction(transfer(0)).unwrap();
+        let mut stale = blockchain.seal_pending_block().unwrap();
+        assert!(stale.mine(&miner, &cancel));
+
+        // the pending block changed while the template was mined
+        blockchain.add_transaction(transfer(1)).unwrap();
+        assert_eq!(
+            blockchain.mint_sealed_block(stale),
+            Err(LedgerError::StaleTemplate)
+        );
+        assert_eq!(blockchain.pending_block().transactions().len(), 2);
+
+        let mut template = blockchain.seal_pending_block().unwrap();
+        assert_eq!(template.index(), 1);
+        assert_eq!(template.difficulty(), Some(1 << 4));
+        assert!(template.mine(&miner, &cancel));
+        blockchain.mint_sealed_block(template.clone()).unwrap();
+        assert_eq!(blockchain.last_block().unwrap().transactions().len(), 2);
+        assert_eq!(
+            blockchain.mint_sealed_block(template),
+            Err(LedgerError::StaleTemplate)
+        );
+    }
+
+    #[test]
+    fn test_uncles() {
+        let (_, _, genesis) = test_genesis();
+        let (_, miner_a) = generate_keypair_from_secret(b"miner a");
+        let (_, miner_b) = generate_keypair_from_secret(b"miner b");
+        let consensus = ConsensusConfig {
+            block_reward: 70,
+            proof_of_work: Some(DifficultyConfig {
+                initial_difficulty: 1 << 4,
+                ..DifficultyConfig::default()
+            }),
+            ..ConsensusConfig::default()
+        };
+        let chain = |miner| {
+            let mut blockchain = Blockchain::new(genesis.clone(), 0).unwrap();
+            blockchain.set_consensus(consensus);
+            blockchain.set_beneficiary(Some(miner));
+            blockchain
+        };
+        let mut a = chain(miner_a);
+        let mut b = chain(miner_b);
+        a.finalize_and_mint_pending_block().unwrap();
+        let stale = a.last_block().unwrap().clone();
+        b.finalize_and_mint_pending_block().unwrap();
+        assert_eq!(b.import_block(stale.clone()), Ok(vec![]));
+
+        // the stale block is picked up by the next block of the other chain
+        b.finalize_and_mint_pending_block().unwrap();
+        let including = b.last_block().unwrap().clone();
+        assert_eq!(including.uncles().len(), 1);
+        assert_eq!(including.uncles()[0].hash(), stale.hash());
+        assert_eq!(
+            b.get_account(&miner_a.address()).map(|a| a.balance()),
+            Some(60)
+        );
+        assert_eq!(b.total_supply(), 1000 + 70 + 70 + 60);
+        b.validate_chain().unwrap();
+
+        // and checked again by the producer of the stale block switching
+        let b_blocks = b.blocks().unwrap();
+        a.import_block(b_blocks[1].clone()).unwrap();
+        a.import_block(including).unwrap();
+        assert_eq!(a.state_root(), b.state_root());
+
+        let with_uncle = |blockchain: &Blockchain, uncle: &Block| {
+            let mut builder = blockchain.pending_block().clone();
+            builder.add_uncle(Uncle::new(uncle).unwrap()).unwrap();
+            let timestamp = blockchain.last_block().unwrap().timestamp();
+            if let Some(difficulty) = blockchain.next_difficulty() {
+                Miner::with_available_parallelism().mine_block(
+                    &mut builder,
+                    difficulty,
+                    timestamp,
+                    &CancelToken::new(),
+                );
+            }
+            builder.build_at(timestamp)
+        };
+        let invalid_uncle = |uncle: &Block, error| {
+            Err(LedgerError::InvalidUncle {
+                index: 3,
+                uncle: uncle.hash(),
+                error,
+            })
+        };
+        assert_eq!(
+            a.import_block(with_uncle(&a, &stale)),
+            invalid_uncle(&stale, UncleError::AlreadyIncluded)
+        );
+        assert_eq!(
+            a.import_block(with_uncle(&a, &b_blocks[1])),
+            invalid_uncle(&b_blocks[1], UncleError::NotStale)
+        );
+        b.finalize_and_mint_pending_block().unwrap();
+        let sibling = b.last_block().unwrap().clone();
+        assert_eq!(
+            a.import_block(with_uncle(&a, &sibling)),
+            invalid_uncle(&sibling, UncleError::OutOfWindow)
+        );
+
+        // a node restarts on a chain including uncles
+        let state_root = b.state_root();
+        let reopened = Blockchain::open(b.into_storage(), consensus).unwrap();
+        assert_eq!(reopened.state_root(), state_root);
+        assert_eq!(
+            reopened
+                .get_account(&miner_a.address())
+                .map(|a| a.balance()),
+            Some(60)
+        );
+
+        let mut without_work = Blockchain::new(genesis.clone(), 0).unwrap();
+        assert_eq!(
+            without_work.import_block(with_uncle(&without_work, &stale)),
+            Err(LedgerError::InvalidUncle {
+                index: 1,
+                uncle: stale.hash(),
+                error: UncleError::WithoutProofOfWork
+            })
+        );
+        let mut builder = without_work.pending_block().clone();
+        for _ in 0..MAX_UNCLES {
+            builder.add_uncle(Uncle::new(&stale).unwrap()).unwrap();
+        }
+        assert_eq!(
+            builder.add_uncle(Uncle::new(&stale).unwrap()),
+            Err(BlockError::TooManyUncles {
+                count: MAX_UNCLES + 1,
+                max: MAX_UNCLES
+            })
+        );
+    }
+
+    #[test]
+    fn test_history_proof() {
+        let (_, _, mut blockchain) = test_chain();
+        for _ in 0..5 {
+            blockchain.finalize_and_mint_pending_block().unwrap();
+        }
+
+        let old = blockchain.get_block_by_index(1).unwrap().unwrap();
+        let tip = blockchain.last_block().unwrap();
+        let proof = blockchain.history_proof(1, tip.index()).unwrap();
+        proof.verify(&old.hash(), &tip.history_root()).unwrap();
+        assert!(proof.verify(&tip.hash(), &tip.history_root()).is_err());
+        assert!(blockchain.history_proof(5, 5).is_none());
+
+        let replayed =
+            Blockchain::from_blocks(blockchain.blocks().unwrap(), ConsensusConfig::default())
+                .unwrap();
+        assert_eq!(replayed.history_root(), blockchain.history_root());
+    }
+
+    #[test]
+    fn test_transaction_proof() {
+        let (private_key, public_key, mut blockchain) = test_chain();
+        let transactions: Vec<Transaction> = (0..3)
+            .map(|nonce| {
+                Transaction::new_and_sign(
+                    &public_key,
+                    &public_key.address(),
+                    1,
+                    nonce,
+                    &private_key,
+                )
+                .unwrap()
+            })
+            .collect();
+        for tx in &transactions {
+            blockchain.add_transaction(tx.clone()).unwrap();
+        }
+        blockchain.finalize_and_mint_pending_block().unwrap();
+
+        let block = blockchain.last_block().unwrap();
+        let proof = block.transaction_proof(&transactions[2].id()).unwrap();
+        let count = block.header().transaction_count();
+        proof
+            .verify(&transactions[2], &block.transactions_root(), count)
+            .unwrap();
+        assert!(proof
+            .verify(&transactions[1], &block.transactions_root(), count)
+            .is_err());
+        let genesis = blockchain.get_block_by_index(0).unwrap().unwrap();
+        assert!(genesis.transaction_proof(&transactions[0].id()).is_none());
     }
 }
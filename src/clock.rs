@@ -0,0 +1,162 @@
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::block::Block;
+use crate::error::LedgerError;
+
+/// minimum number of peer samples before the network time is trusted
+const MIN_PEER_SAMPLES: usize = 5;
+const MAX_PEER_SAMPLES: usize = 200;
+
+/// local clock in seconds since the unix epoch
+pub fn now() -> u64 {
+    // a clock set before the epoch reads as 0 rather than panicking
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimestampPolicy {
+    /// how far in the future of the (network adjusted) clock a block
+    /// timestamp may be, in seconds
+    pub max_future_drift: u64,
+}
+
+impl Default for TimestampPolicy {
+    fn default() -> Self {
+        TimestampPolicy {
+            max_future_drift: 15,
+        }
+    }
+}
+
+impl TimestampPolicy {
+    /// Block timestamps must not go backwards and must not be more than
+    /// `max_future_drift` seconds ahead of `now`
+    pub fn check(&self, block: &Block, previous: &Block, now: u64) -> Result<(), LedgerError> {
+        if block.timestamp() < previous.timestamp() {
+            return Err(LedgerError::OlderThanParent(block.index()));
+        }
+        let max = now.saturating_add(self.max_future_drift);
+        if block.timestamp() > max {
+            return Err(LedgerError::FutureTimestamp {
+                index: block.index(),
+                timestamp: block.timestamp(),
+                max,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Median of the clock offsets reported by peers, so a single node with a
+/// skewed clock does not produce blocks the others reject
+#[derive(Debug, Clone)]
+pub struct NetworkTime {
+    offsets: HashMap<String, i64>,
+    /// offsets larger than this are ignored, in seconds
+    max_adjustment: i64,
+}
+
+impl Default for NetworkTime {
+    fn default() -> Self {
+        NetworkTime::new(70 * 60)
+    }
+}
+
+impl NetworkTime {
+    pub fn new(max_adjustment: u64) -> NetworkTime {
+        NetworkTime {
+            offsets: HashMap::new(),
+            max_adjustment: i64::try_from(max_adjustment).unwrap_or(i64::MAX),
+        }
+    }
+
+    /// record `peer_time - local_time` for a peer, one sample per peer
+    pub fn add_sample(&mut self, peer: &str, offset: i64) {
+        if self.offsets.len() >= MAX_PEER_SAMPLES && !self.offsets.contains_key(peer) {
+            return;
+        }
+        self.offsets.insert(peer.to_string(), offset);
+    }
+
+    pub fn remove_peer(&mut self, peer: &str) {
+        self.offsets.remove(peer);
+    }
+
+    /// median peer offset, 0 until enough peers reported or when the median
+    /// is beyond the maximum adjustment
+    pub fn offset(&self) -> i64 {
+        if self.offsets.len() < MIN_PEER_SAMPLES {
+            return 0;
+        }
+        let mut offsets: Vec<i64> = self.offsets.values().copied().collect();
+        offsets.sort_unstable();
+        let median = offsets[offsets.len() / 2];
+        if median.abs() > self.max_adjustment {
+            return 0;
+        }
+        median
+    }
+
+    pub fn adjusted(&self, local_time: u64) -> u64 {
+        local_time.saturating_add_signed(self.offset())
+    }
+
+    pub fn now(&self) -> u64 {
+        self.adjusted(now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockBuilder;
+    use crate::crypto::Hash;
+
+    #[test]
+    fn test_timestamp_policy() {
+        let policy = TimestampPolicy {
+            max_future_drift: 15,
+        };
+        let previous = BlockBuilder::new(0, &Hash::default()).build_at(100);
+        let at = |timestamp| BlockBuilder::new(1, &previous.hash()).build_at(timestamp);
+
+        policy.check(&at(100), &previous, 1000).unwrap();
+        assert_eq!(
+            policy.check(&at(99), &previous, 1000),
+            Err(LedgerError::OlderThanParent(1))
+        );
+        policy.check(&at(1015), &previous, 1000).unwrap();
+        assert_eq!(
+            policy.check(&at(1016), &previous, 1000),
+            Err(LedgerError::FutureTimestamp {
+                index: 1,
+                timestamp: 1016,
+                max: 1015
+            })
+        );
+    }
+
+    #[test]
+    fn test_network_time_median() {
+        let mut time = NetworkTime::default();
+        time.add_sample("a", 10);
+        time.add_sample("b", 12);
+        time.add_sample("c", 11);
+        time.add_sample("d", -3000);
+        assert_eq!(time.offset(), 0);
+
+        time.add_sample("e", 500);
+        assert_eq!(time.offset(), 11);
+        assert_eq!(time.adjusted(1000), 1011);
+
+        time.add_sample("a", 100_000);
+        time.add_sample("b", 100_000);
+        time.add_sample("c", 100_000);
+        assert_eq!(time.offset(), 0);
+    }
+}
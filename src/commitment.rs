@@ -0,0 +1,147 @@
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
+use std::collections::BTreeMap;
+
+use crate::account::Account;
+use crate::address::Address;
+use crate::crypto::Hash;
+use crate::merkle::MerkleTree;
+
+/// Authenticated structure committing to the account state. Execution only
+/// reports changed accounts, so alternative structures (Patricia trie,
+/// Verkle tree, ...) can be plugged into the blockchain and compared.
+pub trait StateCommitment: Send {
+    /// record the new state of an account, inserting it if needed
+    fn update(&mut self, account: &Account);
+
+    /// forget an account, used when the change creating it is rolled back
+    fn remove(&mut self, address: &Address);
+
+    fn root(&self) -> Hash;
+
+    /// structure of the same kind without any account, what a replay of
+    /// the chain starts from
+    fn empty(&self) -> Box<dyn StateCommitment>;
+}
+
+/// Binary merkle tree over the accounts sorted by address
+#[derive(Debug, Clone, Default)]
+pub struct MerkleStateCommitment {
+    accounts: BTreeMap<Vec<u8>, Account>,
+}
+
+impl MerkleStateCommitment {
+    pub fn new() -> MerkleStateCommitment {
+        MerkleStateCommitment::default()
+    }
+}
+
+impl StateCommitment for MerkleStateCommitment {
+    fn update(&mut self, account: &Account) {
+        self.accounts
+            .insert(account.address().as_ref().to_vec(), account.clone());
+    }
+
+    fn remove(&mut self, address: &Address) {
+        self.accounts.remove(address.as_ref());
+    }
+
+    fn root(&self) -> Hash {
+        let accounts: Vec<Account> = self.accounts.values().cloned().collect();
+        MerkleTree::<Account>::new(&accounts).root()
+    }
+
+    fn empty(&self) -> Box<dyn StateCommitment> {
+        Box::new(MerkleStateCommitment::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::consensus::ConsensusConfig;
+    use crate::crypto::generate_keypair_from_secret;
+    use crate::error::LedgerError;
+    use crate::fixture::test_chain;
+    use crate::transaction::Transaction;
+
+    /// merkle tree whose root is hashed again, a commitment of another kind
+    #[derive(Default)]
+    struct RehashedCommitment(MerkleStateCommitment);
+
+    impl StateCommitment for RehashedCommitment {
+        fn update(&mut self, account: &Account) {
+            self.0.update(account);
+        }
+
+        fn remove(&mut self, address: &Address) {
+            self.0.remove(address);
+        }
+
+        fn root(&self) -> Hash {
+            Hash::from(self.0.root().as_ref())
+        }
+
+        fn empty(&self) -> Box<dyn StateCommitment> {
+            Box::new(RehashedCommitment::default())
+        }
+    }
+
+    #[test]
+    fn test_root_ignores_update_order() {
+        let a = generate_keypair_from_secret(b"a").1.address();
+        let b = generate_keypair_from_secret(b"b").1.address();
+        let mut left = MerkleStateCommitment::new();
+        let mut right = MerkleStateCommitment::new();
+        assert_eq!(left.root(), Hash::default());
+
+        left.update(&Account::new(&a));
+        left.update(&Account::new(&b));
+        right.update(&Account::new(&b));
+        right.update(&Account::new(&a));
+        assert_eq!(left.root(), right.root());
+
+        let mut funded = Account::new(&a);
+        funded.deposit(5).unwrap();
+        right.update(&funded);
+        assert_ne!(left.root(), right.root());
+    }
+
+    #[test]
+    fn test_replay_with_commitment() {
+        let (private_key, public_key, mut blockchain) = test_chain();
+        let (_, other) = generate_keypair_from_secret(b"other");
+        blockchain.set_state_commitment(Box::new(RehashedCommitment::default()));
+        for nonce in 0..2 {
+            let tx =
+                Transaction::new_and_sign(&public_key, &other.address(), 10, nonce, &private_key)
+                    .unwrap();
+            blockchain.add_transaction(tx).unwrap();
+            blockchain.finalize_and_mint_pending_block().unwrap();
+        }
+        blockchain.validate_chain().unwrap();
+
+        let blocks = blockchain.blocks().unwrap();
+        let consensus = ConsensusConfig::default();
+        let rebuilt = Blockchain::from_blocks_with_commitment(
+            blocks.clone(),
+            consensus,
+            Box::new(RehashedCommitment::default()),
+        )
+        .unwrap();
+        assert_eq!(rebuilt.state_root(), blockchain.state_root());
+        assert!(matches!(
+            Blockchain::from_blocks(blocks, consensus),
+            Err(LedgerError::InvalidStateRoot { index: 1, .. })
+        ));
+        let state_root = blockchain.state_root();
+        let reopened = Blockchain::open_with_commitment(
+            blockchain.into_storage(),
+            consensus,
+            Box::new(RehashedCommitment::default()),
+        )
+        .unwrap();
+        assert_eq!(reopened.state_root(), state_root);
+    }
+}
@@ -0,0 +1,53 @@
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
+//! Byte slice interface over `crypto` for callers that do not hold the typed
+//! keys, every input is validated and nothing panics
+
+use crate::crypto::{self, CryptoError, Hash, PrivateKey, PublicKey, Signature};
+
+pub fn hash(data: &[u8]) -> Vec<u8> {
+    Hash::from(data).as_ref().to_vec()
+}
+
+pub fn public_key(private_key: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let private_key = PrivateKey::try_from(private_key)?;
+    let public_key = PublicKey::try_from(&private_key)?;
+    Ok(public_key.as_ref().to_vec())
+}
+
+pub fn sign_hash(hash: &[u8], private_key: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let hash = Hash::from_slice(hash)?;
+    let private_key = PrivateKey::try_from(private_key)?;
+    let signature = crypto::sign_hash(&hash, &private_key)?;
+    Ok(signature.as_ref().to_vec())
+}
+
+pub fn verify_signature(
+    public_key: &[u8],
+    hash: &[u8],
+    signature: &[u8],
+) -> Result<(), CryptoError> {
+    let public_key = PublicKey::try_from(public_key)?;
+    let hash = Hash::from_slice(hash)?;
+    let signature = Signature::try_from(signature)?;
+    crypto::verify_signature(&public_key, &hash, &signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compat_rejects_bad_lengths() {
+        let private_key = [7u8; 32];
+        let key = public_key(&private_key).unwrap();
+        let digest = hash(b"data");
+        let signature = sign_hash(&digest, &private_key).unwrap();
+        verify_signature(&key, &digest, &signature).unwrap();
+
+        assert!(public_key(&[7u8; 31]).is_err());
+        assert!(sign_hash(&digest[1..], &private_key).is_err());
+        assert!(verify_signature(&key[1..], &digest, &signature).is_err());
+        assert!(verify_signature(&key, &digest, &signature[1..]).is_err());
+    }
+}
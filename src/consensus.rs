@@ -0,0 +1,66 @@
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
+use crate::difficulty::DifficultyConfig;
+
+/// Chain a configuration belongs to, told apart in the text form of
+/// addresses so funds are not sent with an address of another network
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Network {
+    #[default]
+    Mainnet,
+    Testnet,
+}
+
+impl Network {
+    pub const ALL: [Network; 2] = [Network::Mainnet, Network::Testnet];
+
+    /// human readable part of the bech32 addresses of the network
+    pub fn hrp(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "cl",
+            Network::Testnet => "tcl",
+        }
+    }
+}
+
+/// most blocks an uncle may be below the block referencing it
+pub const MAX_UNCLE_DEPTH: u64 = 6;
+
+/// gas a validation predicate may spend by default, ten signature checks
+pub const DEFAULT_PREDICATE_GAS_LIMIT: u64 = 10 * crate::script::CHECK_SIG_GAS;
+
+/// Rules every node of a chain must agree on, a block valid under one
+/// configuration is rejected under another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConsensusConfig {
+    /// minted by every coinbase on top of the fees it collects, the amount
+    /// of a coinbase must be exactly both
+    pub block_reward: u64,
+    pub network: Network,
+    /// most gas the validation predicate of an account may spend on a
+    /// transaction, a predicate running out of gas rejects it
+    pub predicate_gas_limit: u64,
+    /// retarget parameters of the proof of work every block after genesis
+    /// must meet, `None` when blocks are minted without work
+    pub proof_of_work: Option<DifficultyConfig>,
+}
+
+impl ConsensusConfig {
+    /// Minted for the producer of an uncle `depth` blocks below the block
+    /// referencing it, a share of the block reward shrinking with the depth
+    pub fn uncle_reward(&self, depth: u64) -> u64 {
+        let share = MAX_UNCLE_DEPTH.saturating_sub(depth) + 1;
+        (self.block_reward as u128 * share as u128 / (MAX_UNCLE_DEPTH as u128 + 1)) as u64
+    }
+}
+
+impl Default for ConsensusConfig {
+    fn default() -> Self {
+        ConsensusConfig {
+            block_reward: 0,
+            network: Network::default(),
+            predicate_gas_limit: DEFAULT_PREDICATE_GAS_LIMIT,
+            proof_of_work: None,
+        }
+    }
+}
@@ -1,24 +1,85 @@
-use secp256k1::hashes::{sha256, Hash as Hash_lib};
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
+use secp256k1::hashes::hmac::{Hmac, HmacEngine};
+use secp256k1::hashes::{sha256, Hash as Hash_lib, HashEngine};
 use secp256k1::rand::rngs::OsRng;
+use secp256k1::rand::{CryptoRng, RngCore};
 use secp256k1::{
     ecdsa::Signature as Signature_lib, Message, PublicKey as PublicKey_lib, Secp256k1, SecretKey,
+    VerifyOnly,
 };
+use serde::de::{Error as DeError, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::sync::OnceLock;
+use subtle::ConstantTimeEq;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CryptoError {
+    InvalidLength {
+        expected: usize,
+        got: usize,
+    },
+    InvalidPublicKey,
+    InvalidPrivateKey,
+    InvalidSignature,
+    /// text that is not the hex or address form of a value
+    InvalidEncoding(String),
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CryptoError::InvalidLength { expected, got } => {
+                write!(
+                    f,
+                    "Invalid length: expected {} bytes, got {}",
+                    expected, got
+                )
+            }
+            CryptoError::InvalidPublicKey => write!(f, "Invalid public key"),
+            CryptoError::InvalidPrivateKey => write!(f, "Invalid private key"),
+            CryptoError::InvalidSignature => write!(f, "Invalid signature"),
+            CryptoError::InvalidEncoding(e) => write!(f, "Invalid encoding: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct Hash([u8; 32]);
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Compared in constant time and redacted from `Debug` output, the raw
+/// bytes are only reachable through `expose_secret`
+#[derive(Clone, Copy)]
 pub struct PrivateKey([u8; 32]);
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// ordered by its bytes, the order of the account state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct PublicKey([u8; 33]);
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Signature([u8; 64]);
 
-impl AsRef<[u8]> for PrivateKey {
-    fn as_ref(&self) -> &[u8] {
+impl PrivateKey {
+    pub fn expose_secret(&self) -> &[u8; 32] {
         &self.0
     }
 }
 
+impl PartialEq for PrivateKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.ct_eq(&other.0).into()
+    }
+}
+
+impl Eq for PrivateKey {}
+
+impl fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PrivateKey(<redacted>)")
+    }
+}
+
 impl AsRef<[u8]> for PublicKey {
     fn as_ref(&self) -> &[u8] {
         &self.0
@@ -27,7 +88,7 @@ impl AsRef<[u8]> for PublicKey {
 
 impl From<&[u8]> for Hash {
     fn from(data: &[u8]) -> Self {
-        let hash_bytes = sha256::Hash::hash(data).as_byte_array().clone();
+        let hash_bytes = *sha256::Hash::hash(data).as_byte_array();
         Hash(hash_bytes)
     }
 }
@@ -38,9 +99,96 @@ impl AsRef<[u8]> for Hash {
     }
 }
 
-impl Default for Hash {
-    fn default() -> Self {
-        Hash([0; 32])
+fn to_array<const N: usize>(bytes: &[u8]) -> Result<[u8; N], CryptoError> {
+    bytes.try_into().map_err(|_| CryptoError::InvalidLength {
+        expected: N,
+        got: bytes.len(),
+    })
+}
+
+impl Hash {
+    /// wrap an existing 32 bytes digest, `Hash::from` hashes its input
+    pub fn from_slice(bytes: &[u8]) -> Result<Hash, CryptoError> {
+        Ok(Hash(to_array(bytes)?))
+    }
+
+    /// hash of the concatenation of `parts`, without building it
+    pub fn from_parts(parts: &[&[u8]]) -> Hash {
+        let mut writer = HashWriter::new();
+        for part in parts {
+            writer.input(part);
+        }
+        writer.finish()
+    }
+}
+
+/// Streaming hasher, values can be serialized straight into it
+pub(crate) struct HashWriter(sha256::HashEngine);
+
+impl HashWriter {
+    pub(crate) fn new() -> HashWriter {
+        HashWriter(sha256::Hash::engine())
+    }
+
+    pub(crate) fn input(&mut self, data: &[u8]) {
+        self.0.input(data);
+    }
+
+    pub(crate) fn finish(self) -> Hash {
+        Hash(sha256::Hash::from_engine(self.0).to_byte_array())
+    }
+}
+
+impl std::io::Write for HashWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.input(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// checks the key is a valid point on the curve
+impl TryFrom<&[u8]> for PublicKey {
+    type Error = CryptoError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let bytes = to_array(bytes)?;
+        PublicKey_lib::from_slice(&bytes).map_err(|_| CryptoError::InvalidPublicKey)?;
+        Ok(PublicKey(bytes))
+    }
+}
+
+/// checks the key is a valid scalar
+impl TryFrom<&[u8]> for PrivateKey {
+    type Error = CryptoError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let bytes = to_array(bytes)?;
+        SecretKey::from_slice(&bytes).map_err(|_| CryptoError::InvalidPrivateKey)?;
+        Ok(PrivateKey(bytes))
+    }
+}
+
+/// checks the compact encoding is valid, the default (all zero) signature
+/// of unsigned transactions is also accepted
+impl TryFrom<&[u8]> for Signature {
+    type Error = CryptoError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let bytes = to_array(bytes)?;
+        if bytes != [0; 64] {
+            Signature_lib::from_compact(&bytes).map_err(|_| CryptoError::InvalidSignature)?;
+        }
+        Ok(Signature(bytes))
+    }
+}
+
+impl AsRef<[u8]> for Signature {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
     }
 }
 
@@ -50,50 +198,214 @@ impl Default for Signature {
     }
 }
 
-impl From<&PrivateKey> for PublicKey {
-    fn from(data: &PrivateKey) -> Self {
+/// Fixed size byte types are encoded as tuples, without a length prefix.
+/// Decoding goes through `$parse` so malformed values are rejected.
+macro_rules! impl_serde_bytes {
+    ($type:ident, $len:expr, $parse:path) => {
+        impl Serialize for $type {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut tuple = serializer.serialize_tuple($len)?;
+                for byte in self.0.iter() {
+                    tuple.serialize_element(byte)?;
+                }
+                tuple.end()
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $type {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct BytesVisitor;
+
+                impl<'de> Visitor<'de> for BytesVisitor {
+                    type Value = $type;
+
+                    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                        write!(formatter, "{} bytes", $len)
+                    }
+
+                    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<$type, A::Error> {
+                        let mut bytes = [0u8; $len];
+                        for (i, byte) in bytes.iter_mut().enumerate() {
+                            *byte = seq
+                                .next_element()?
+                                .ok_or_else(|| A::Error::invalid_length(i, &self))?;
+                        }
+                        $parse(&bytes[..]).map_err(A::Error::custom)
+                    }
+                }
+
+                deserializer.deserialize_tuple($len, BytesVisitor)
+            }
+        }
+    };
+}
+
+impl_serde_bytes!(Hash, 32, Hash::from_slice);
+impl_serde_bytes!(PublicKey, 33, PublicKey::try_from);
+impl_serde_bytes!(Signature, 64, Signature::try_from);
+
+/// Lowercase hex as text form, parsed back through `$parse`
+macro_rules! impl_hex_text {
+    ($type:ident, $parse:path) => {
+        impl fmt::LowerHex for $type {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(&hex::encode(self.0))
+            }
+        }
+
+        impl fmt::Display for $type {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{:x}", self)
+            }
+        }
+
+        impl std::str::FromStr for $type {
+            type Err = CryptoError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let bytes = hex::decode(s.trim())
+                    .map_err(|e| CryptoError::InvalidEncoding(e.to_string()))?;
+                $parse(&bytes[..])
+            }
+        }
+    };
+}
+
+impl_hex_text!(Hash, Hash::from_slice);
+impl_hex_text!(PublicKey, PublicKey::try_from);
+impl_hex_text!(Signature, Signature::try_from);
+
+impl TryFrom<&PrivateKey> for PublicKey {
+    type Error = CryptoError;
+
+    fn try_from(data: &PrivateKey) -> Result<Self, Self::Error> {
         let secp = Secp256k1::new();
-        let secret_key = SecretKey::from_slice(data.0.as_slice()).unwrap();
+        let secret_key =
+            SecretKey::from_slice(data.0.as_slice()).map_err(|_| CryptoError::InvalidPrivateKey)?;
         let public_key = PublicKey_lib::from_secret_key(&secp, &secret_key);
-        PublicKey(public_key.serialize())
+        Ok(PublicKey(public_key.serialize()))
     }
 }
 
-pub fn sign_hash(hash: &Hash, private_key: &PrivateKey) -> Result<Signature, String> {
+pub fn sign_hash(hash: &Hash, private_key: &PrivateKey) -> Result<Signature, CryptoError> {
     let secp = Secp256k1::new();
-    let message = Message::from_digest_slice(hash.0.as_slice()).map_err(|e| e.to_string())?;
-    let secret_key = SecretKey::from_slice(private_key.0.as_slice()).map_err(|e| e.to_string())?;
+    let message = Message::from_digest(hash.0);
+    let secret_key = SecretKey::from_slice(private_key.0.as_slice())
+        .map_err(|_| CryptoError::InvalidPrivateKey)?;
     let signature = secp.sign_ecdsa(&message, &secret_key).serialize_compact();
     Ok(Signature(signature))
 }
 
+/// built once and shared by every thread, verifying needs none of the
+/// signing tables a full context precomputes
+fn verification_context() -> &'static Secp256k1<VerifyOnly> {
+    static CONTEXT: OnceLock<Secp256k1<VerifyOnly>> = OnceLock::new();
+    CONTEXT.get_or_init(Secp256k1::verification_only)
+}
+
 pub fn verify_signature(
     public_key: &PublicKey,
     hash: &Hash,
     signature: &Signature,
-) -> Result<(), String> {
-    let secp = Secp256k1::new();
-    let public_key =
-        PublicKey_lib::from_slice(public_key.0.as_slice()).map_err(|e| e.to_string())?;
-    let message = Message::from_digest_slice(hash.0.as_slice()).map_err(|e| e.to_string())?;
-    let signature =
-        Signature_lib::from_compact(signature.0.as_slice()).map_err(|e| e.to_string())?;
+) -> Result<(), CryptoError> {
+    let secp = verification_context();
+    let public_key = PublicKey_lib::from_slice(public_key.0.as_slice())
+        .map_err(|_| CryptoError::InvalidPublicKey)?;
+    let message = Message::from_digest(hash.0);
+    let signature = Signature_lib::from_compact(signature.0.as_slice())
+        .map_err(|_| CryptoError::InvalidSignature)?;
     secp.verify_ecdsa(&message, &signature, &public_key)
-        .map_err(|e| e.to_string())
+        .map_err(|_| CryptoError::InvalidSignature)
+}
+
+/// Prefix of off-chain signed messages. Transaction signing preimages start
+/// with a public key (0x02 or 0x03), never with 0x19, so a message signature
+/// can't be replayed as a transaction signature.
+const MESSAGE_DOMAIN: &[u8] = b"\x19CoreLedger Signed Message:\n";
+
+/// hash of the domain tag, the message length and the message
+pub fn message_hash(message: &[u8]) -> Hash {
+    let length = (message.len() as u64).to_be_bytes();
+    Hash::from_parts(&[MESSAGE_DOMAIN, &length, message])
+}
+
+pub fn sign_message(message: &[u8], private_key: &PrivateKey) -> Result<Signature, CryptoError> {
+    sign_hash(&message_hash(message), private_key)
+}
+
+pub fn verify_message(
+    public_key: &PublicKey,
+    message: &[u8],
+    signature: &Signature,
+) -> Result<(), CryptoError> {
+    verify_signature(public_key, &message_hash(message), signature)
 }
 
 /// Generate a new keypair for the secp256k1 curve
 /// Returns a tuple (secret_key, public_key)
 pub fn generate_keypair() -> (PrivateKey, PublicKey) {
+    generate_keypair_with_rng(&mut OsRng)
+}
+
+/// Same as `generate_keypair` with a caller provided RNG, e.g. a seeded one
+/// for deterministic tests and simulations
+pub fn generate_keypair_with_rng<R: RngCore + CryptoRng + ?Sized>(
+    rng: &mut R,
+) -> (PrivateKey, PublicKey) {
     let secp = Secp256k1::new();
-    let (secret_key, public_key) = secp.generate_keypair(&mut OsRng);
+    let (secret_key, public_key) = secp.generate_keypair(rng);
     (
         PrivateKey(secret_key.secret_bytes()),
         PublicKey(public_key.serialize()),
     )
 }
 
+/// HKDF info prefix, keeps derived keys apart from any other use of the
+/// same secret
+const KEY_DERIVATION_DOMAIN: &[u8] = b"core-ledger/secp256k1-key/v1";
+
+fn hmac_sha256(key: &[u8], data: &[&[u8]]) -> [u8; 32] {
+    let mut engine = HmacEngine::<sha256::Hash>::new(key);
+    for chunk in data {
+        engine.input(chunk);
+    }
+    Hmac::<sha256::Hash>::from_engine(engine).to_byte_array()
+}
+
+/// HKDF-SHA256 (RFC 5869) producing a single 32 bytes block
+fn hkdf_sha256(salt: &[u8], input_key: &[u8], info: &[u8]) -> [u8; 32] {
+    let pseudo_random_key = hmac_sha256(salt, &[input_key]);
+    hmac_sha256(&pseudo_random_key, &[info, &[1]])
+}
+
+/// Derive a keypair from a secret and a salt with HKDF. In the rare case the
+/// output is not a valid scalar, derivation is retried with the next counter.
+/// This does not make a weak secret strong: use a high entropy secret.
+pub fn derive_keypair(secret: &[u8], salt: &[u8]) -> (PrivateKey, PublicKey) {
+    let secp = Secp256k1::new();
+    let mut counter: u32 = 0;
+    loop {
+        let info = [KEY_DERIVATION_DOMAIN, &counter.to_be_bytes()].concat();
+        let okm = hkdf_sha256(salt, secret, &info);
+        if let Ok(secret_key) = SecretKey::from_slice(&okm) {
+            let public_key = PublicKey_lib::from_secret_key(&secp, &secret_key);
+            return (
+                PrivateKey(secret_key.secret_bytes()),
+                PublicKey(public_key.serialize()),
+            );
+        }
+        counter = counter.wrapping_add(1);
+    }
+}
+
 pub fn generate_keypair_from_secret(secret: &[u8]) -> (PrivateKey, PublicKey) {
+    derive_keypair(secret, &[])
+}
+
+/// Previous derivation, the secret hashed straight into the key
+#[cfg(feature = "legacy-key-derivation")]
+#[deprecated(note = "use generate_keypair_from_secret or derive_keypair")]
+pub fn generate_keypair_from_secret_legacy(secret: &[u8]) -> (PrivateKey, PublicKey) {
     let secp = Secp256k1::new();
     let secret_key = SecretKey::from_hashed_data::<sha256::Hash>(secret);
     let public_key = PublicKey_lib::from_secret_key(&secp, &secret_key);
@@ -105,10 +417,17 @@ pub fn generate_keypair_from_secret(secret: &[u8]) -> (PrivateKey, PublicKey) {
 
 #[cfg(test)]
 mod tests {
-    use std::hash;
-
     use super::*;
 
+    #[test]
+    fn test_hash_from_parts() {
+        let parts: [&[u8]; 3] = [b"core", b"", b"ledger"];
+        assert_eq!(
+            Hash::from_parts(&parts),
+            Hash::from(parts.concat().as_ref())
+        );
+    }
+
     #[test]
     fn test_generate_keypair() {
         let (private_key, public_key) = generate_keypair();
@@ -116,6 +435,17 @@ mod tests {
         assert_eq!(public_key.0.len(), 33);
     }
 
+    #[test]
+    fn test_generate_keypair_with_seeded_rng() {
+        use secp256k1::rand::{rngs::StdRng, SeedableRng};
+
+        let keypair1 = generate_keypair_with_rng(&mut StdRng::seed_from_u64(42));
+        let keypair2 = generate_keypair_with_rng(&mut StdRng::seed_from_u64(42));
+        let keypair3 = generate_keypair_with_rng(&mut StdRng::seed_from_u64(43));
+        assert_eq!(keypair1, keypair2);
+        assert_ne!(keypair1, keypair3);
+    }
+
     #[test]
     fn test_sign_and_verify() {
         let (private_key, public_key) = generate_keypair();
@@ -124,6 +454,17 @@ mod tests {
         verify_signature(&public_key, &hash, &signature).unwrap();
     }
 
+    #[test]
+    fn test_sign_message() {
+        let (private_key, public_key) = generate_keypair();
+        let signature = sign_message(b"hello", &private_key).unwrap();
+        verify_message(&public_key, b"hello", &signature).unwrap();
+        assert!(verify_message(&public_key, b"hello!", &signature).is_err());
+        // not valid over the raw hash of the message
+        let raw_hash = Hash::from("hello".as_bytes());
+        assert!(verify_signature(&public_key, &raw_hash, &signature).is_err());
+    }
+
     #[test]
     fn test_generate_keypair_from_secret() {
         let secret = b"hello world";
@@ -135,6 +476,84 @@ mod tests {
         verify_signature(&public_key, &hash, &signature).unwrap();
     }
 
+    #[test]
+    fn test_try_from_bytes() {
+        let (private_key, public_key) = generate_keypair();
+        assert_eq!(PublicKey::try_from(public_key.as_ref()), Ok(public_key));
+        assert_eq!(
+            PrivateKey::try_from(&private_key.expose_secret()[..]),
+            Ok(private_key)
+        );
+        assert_eq!(
+            PublicKey::try_from(&public_key.as_ref()[1..]),
+            Err(CryptoError::InvalidLength {
+                expected: 33,
+                got: 32
+            })
+        );
+        assert_eq!(
+            PublicKey::try_from(&[5u8; 33][..]),
+            Err(CryptoError::InvalidPublicKey)
+        );
+        assert_eq!(
+            PrivateKey::try_from(&[0u8; 32][..]),
+            Err(CryptoError::InvalidPrivateKey)
+        );
+        assert_eq!(
+            Signature::try_from(&[0xffu8; 64][..]),
+            Err(CryptoError::InvalidSignature)
+        );
+        assert!(Hash::from_slice(&[1u8; 31]).is_err());
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let (private_key, public_key) = generate_keypair();
+        let hash = Hash::from(&b"text"[..]);
+        let signature = sign_hash(&hash, &private_key).unwrap();
+        assert_eq!(public_key.to_string(), hex::encode(public_key));
+        assert_eq!(public_key.to_string().parse(), Ok(public_key));
+        assert_eq!(hash.to_string().parse(), Ok(hash));
+        assert_eq!(signature.to_string().parse(), Ok(signature));
+        assert!(matches!(
+            "0g".parse::<Hash>(),
+            Err(CryptoError::InvalidEncoding(_))
+        ));
+        assert_eq!(
+            "05".repeat(33).parse::<PublicKey>(),
+            Err(CryptoError::InvalidPublicKey)
+        );
+    }
+
+    #[test]
+    fn test_hkdf_rfc5869_vector() {
+        // RFC 5869 test case 1, first 32 bytes of the output
+        let ikm = [0x0b; 22];
+        let salt: Vec<u8> = (0x00..=0x0c).collect();
+        let info: Vec<u8> = (0xf0..=0xf9).collect();
+        assert_eq!(
+            hkdf_sha256(&salt, &ikm, &info).to_vec(),
+            hex::decode("3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_derive_keypair_salt() {
+        let (private_key1, _) = derive_keypair(b"secret", b"salt 1");
+        let (private_key2, _) = derive_keypair(b"secret", b"salt 2");
+        assert_ne!(private_key1, private_key2);
+        assert_eq!(derive_keypair(b"secret", b"salt 1").0, private_key1);
+    }
+
+    #[test]
+    fn test_private_key_debug_is_redacted() {
+        let (private_key, _) = generate_keypair();
+        let debug = format!("{:?}", private_key);
+        assert_eq!(debug, "PrivateKey(<redacted>)");
+        assert!(!debug.contains(&format!("{:?}", private_key.expose_secret())));
+    }
+
     #[test]
     fn test_generate_keypair_from_same_secret() {
         let secret = b"secret";
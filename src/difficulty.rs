@@ -0,0 +1,158 @@
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
+/// Parameters of the LWMA difficulty retarget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DifficultyConfig {
+    /// target block interval in seconds
+    pub target_block_time: u64,
+    /// number of blocks in the rolling window
+    pub window: usize,
+    pub initial_difficulty: u64,
+    pub min_difficulty: u64,
+}
+
+impl Default for DifficultyConfig {
+    fn default() -> Self {
+        DifficultyConfig {
+            target_block_time: 10,
+            window: 45,
+            initial_difficulty: 1 << 16,
+            min_difficulty: 1,
+        }
+    }
+}
+
+/// Difficulty of the next block with a linearly weighted moving average
+/// (LWMA) retarget: recent solve times weigh more than older ones.
+/// `history` holds `(timestamp, difficulty)` of the previous blocks, oldest
+/// first; only the last `window + 1` entries are used.
+pub fn next_difficulty(config: &DifficultyConfig, history: &[(u64, u64)]) -> u64 {
+    if history.len() < 2 || config.window == 0 {
+        return config.initial_difficulty.max(config.min_difficulty);
+    }
+    let start = history.len().saturating_sub(config.window + 1);
+    let history = &history[start..];
+    let n = (history.len() - 1) as u128;
+    let target = config.target_block_time.max(1) as u128;
+
+    let mut weighted_solve_times: u128 = 0;
+    let mut sum_difficulty: u128 = 0;
+    for (i, pair) in history.windows(2).enumerate() {
+        // clamp to keep out of order or skewed timestamps from swinging
+        // the difficulty too far in one block
+        let solve_time = pair[1]
+            .0
+            .saturating_sub(pair[0].0)
+            .clamp(1, 6 * target as u64);
+        weighted_solve_times += (i as u128 + 1) * solve_time as u128;
+        sum_difficulty += pair[1].1 as u128;
+    }
+
+    // next = average difficulty * target / weighted average solve time
+    let k = n * (n + 1) / 2 * target;
+    let next = sum_difficulty * k / (n * weighted_solve_times);
+    (next.min(u64::MAX as u128) as u64).max(config.min_difficulty)
+}
+
+/// Largest hash value meeting `difficulty`, as big-endian bytes:
+/// `(2^256 - 1) / difficulty`
+pub fn target(difficulty: u64) -> [u8; 32] {
+    let difficulty = difficulty.max(1) as u128;
+    let mut target = [0u8; 32];
+    let mut remainder: u128 = 0;
+    for byte in target.iter_mut() {
+        let dividend = (remainder << 8) | 0xff;
+        *byte = (dividend / difficulty) as u8;
+        remainder = dividend % difficulty;
+    }
+    target
+}
+
+/// expected number of hashes to meet `difficulty`, what fork choice sums
+pub fn work(difficulty: u64) -> u128 {
+    difficulty.max(1) as u128
+}
+
+/// hash interpreted as a big-endian 256 bits number is at most the target
+pub fn meets_difficulty(hash: &[u8], difficulty: u64) -> bool {
+    hash <= &target(difficulty)[..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// blocks found at the expected solve time for `hashrate` hashes per second
+    fn simulate(
+        config: &DifficultyConfig,
+        history: &mut Vec<(u64, u64)>,
+        hashrate: u64,
+        blocks: usize,
+    ) {
+        for _ in 0..blocks {
+            let difficulty = next_difficulty(config, history);
+            let (timestamp, _) = *history.last().unwrap();
+            history.push((timestamp + (difficulty / hashrate).max(1), difficulty));
+        }
+    }
+
+    fn assert_close(value: u64, expected: u64) {
+        let ratio = value as f64 / expected as f64;
+        assert!(
+            (0.9..1.1).contains(&ratio),
+            "{} not close to {}",
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_stable_hashrate() {
+        let config = DifficultyConfig::default();
+        let hashrate = 1000;
+        let equilibrium = hashrate * config.target_block_time;
+        let mut history = vec![(0, equilibrium)];
+        simulate(&config, &mut history, hashrate, 100);
+        assert_close(next_difficulty(&config, &history), equilibrium);
+    }
+
+    #[test]
+    fn test_hashrate_swings() {
+        let config = DifficultyConfig::default();
+        let hashrate = 1000;
+        let mut history = vec![(0, hashrate * config.target_block_time)];
+        simulate(&config, &mut history, hashrate, 100);
+
+        simulate(&config, &mut history, hashrate * 4, 3 * config.window);
+        assert_close(
+            next_difficulty(&config, &history),
+            hashrate * 4 * config.target_block_time,
+        );
+
+        simulate(&config, &mut history, hashrate / 2, 3 * config.window);
+        assert_close(
+            next_difficulty(&config, &history),
+            hashrate / 2 * config.target_block_time,
+        );
+    }
+
+    #[test]
+    fn test_min_difficulty() {
+        let config = DifficultyConfig {
+            min_difficulty: 50,
+            ..DifficultyConfig::default()
+        };
+        let history: Vec<(u64, u64)> = (0..10).map(|i| (i * 10_000, 60)).collect();
+        assert_eq!(next_difficulty(&config, &history), 50);
+    }
+
+    #[test]
+    fn test_target() {
+        assert_eq!(target(1), [0xff; 32]);
+        let half = target(2);
+        assert_eq!(half[0], 0x7f);
+        assert!(half[1..].iter().all(|b| *b == 0xff));
+        assert!(meets_difficulty(&[0u8; 32], u64::MAX));
+        assert!(!meets_difficulty(&[0xff; 32], 2));
+    }
+}
@@ -0,0 +1,28 @@
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+/// Same layout as `bincode::serialize`: fixed size integers, little endian
+fn options() -> impl Options {
+    bincode::DefaultOptions::new().with_fixint_encoding()
+}
+
+pub(crate) fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, bincode::Error> {
+    options().serialize(value)
+}
+
+/// serialize into `writer` without an intermediate buffer
+pub(crate) fn encode_into<W: std::io::Write, T: Serialize + ?Sized>(
+    writer: W,
+    value: &T,
+) -> Result<(), bincode::Error> {
+    options().serialize_into(writer, value)
+}
+
+/// Strict decoding for untrusted input: trailing bytes are rejected and no
+/// length prefix can claim more than the input size
+pub(crate) fn decode<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<T, bincode::Error> {
+    options()
+        .with_limit(bytes.len() as u64)
+        .reject_trailing_bytes()
+        .deserialize(bytes)
+}
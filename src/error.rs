@@ -0,0 +1,500 @@
+use std::fmt;
+
+use crate::crypto::{CryptoError, Hash};
+use crate::script::ScriptError;
+use crate::state::AccountDiff;
+use crate::transaction::DecodeError;
+
+/// Why a transaction cannot be executed on top of the current state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxError {
+    Crypto(CryptoError),
+    Decode(DecodeError),
+    InvalidAmount,
+    AccountNotFound,
+    InvalidNonce {
+        expected: u64,
+        got: u64,
+    },
+    NonceAlreadyUsed,
+    InsufficientFunds {
+        balance: u64,
+        required: u64,
+    },
+    Overflow,
+    BurnExceedsSupply,
+    GenesisNotTransfer,
+    PendingBlockFull,
+    /// a coinbase anywhere but at the end of a block
+    UnexpectedCoinbase,
+    InvalidCoinbaseAmount {
+        expected: u64,
+        got: u64,
+    },
+    /// the fee granter is the sender itself
+    InvalidFeeGranter,
+    NoFeeGrant,
+    FeeGrantExpired,
+    FeeGrantExceeded {
+        remaining: u64,
+        required: u64,
+    },
+    /// fewer cosigners of the sender validation signed than its threshold
+    NotEnoughCosignatures {
+        threshold: u32,
+        signed: usize,
+    },
+    /// more cosignatures than the sender validation has cosigners
+    TooManyCosignatures,
+    /// a cosignature by none of the cosigners left, a duplicate or junk
+    /// that would change the transaction id
+    InvalidCosignature,
+    /// cosignatures out of ascending byte order, which a relayer could
+    /// change without invalidating them
+    UnsortedCosignatures,
+    /// a witness for a sender without validation predicate
+    UnexpectedWitness,
+    /// a validation with duplicate cosigners or an unreachable threshold
+    InvalidValidation,
+    SpendLimitExceeded {
+        limit: u64,
+        spent: u64,
+    },
+    /// the validation predicate of the sender does not hold
+    PredicateFailed(ScriptError),
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TxError::Crypto(e) => write!(f, "{}", e),
+            TxError::Decode(e) => write!(f, "{}", e),
+            TxError::InvalidAmount => write!(f, "Invalid transaction amount"),
+            TxError::AccountNotFound => write!(f, "From account not found"),
+            TxError::InvalidNonce { expected, got } => {
+                write!(f, "Invalid nonce: expected {}, got {}", expected, got)
+            }
+            TxError::NonceAlreadyUsed => write!(f, "Nonce already used"),
+            TxError::InsufficientFunds { balance, required } => write!(
+                f,
+                "Insufficient funds: balance {}, required {}",
+                balance, required
+            ),
+            TxError::Overflow => write!(f, "Overflow error"),
+            TxError::BurnExceedsSupply => write!(f, "Burn exceeds total supply"),
+            TxError::GenesisNotTransfer => write!(f, "Genesis transactions must be transfers"),
+            TxError::PendingBlockFull => write!(f, "Pending block is full"),
+            TxError::UnexpectedCoinbase => {
+                write!(f, "Coinbase must be the last transaction of a block")
+            }
+            TxError::InvalidCoinbaseAmount { expected, got } => write!(
+                f,
+                "Invalid coinbase amount: expected {}, got {}",
+                expected, got
+            ),
+            TxError::InvalidFeeGranter => write!(f, "Fee granter is the sender"),
+            TxError::NoFeeGrant => write!(f, "No fee grant to the sender"),
+            TxError::FeeGrantExpired => write!(f, "Fee grant expired"),
+            TxError::FeeGrantExceeded {
+                remaining,
+                required,
+            } => write!(
+                f,
+                "Fee grant exceeded: remaining {}, required {}",
+                remaining, required
+            ),
+            TxError::NotEnoughCosignatures { threshold, signed } => write!(
+                f,
+                "Not enough cosignatures: {} of {} required",
+                signed, threshold
+            ),
+            TxError::TooManyCosignatures => write!(f, "Too many cosignatures"),
+            TxError::InvalidCosignature => write!(f, "Invalid cosignature"),
+            TxError::UnsortedCosignatures => write!(f, "Cosignatures are not sorted"),
+            TxError::UnexpectedWitness => write!(f, "Witness without validation predicate"),
+            TxError::InvalidValidation => write!(f, "Invalid account validation"),
+            TxError::SpendLimitExceeded { limit, spent } => {
+                write!(f, "Spend limit exceeded: limit {}, spent {}", limit, spent)
+            }
+            TxError::PredicateFailed(e) => write!(f, "Validation predicate failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TxError {}
+
+impl From<CryptoError> for TxError {
+    fn from(e: CryptoError) -> Self {
+        TxError::Crypto(e)
+    }
+}
+
+impl From<DecodeError> for TxError {
+    fn from(e: DecodeError) -> Self {
+        TxError::Decode(e)
+    }
+}
+
+/// Why a block is not self consistent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockError {
+    ExtraDataTooLarge {
+        size: usize,
+        max: usize,
+    },
+    TooManyTransactions {
+        count: usize,
+        max: usize,
+    },
+    Full,
+    EmptyGenesis,
+    InvalidTransaction(TxError),
+    InvalidTransactionsRoot,
+    InvalidHash,
+    TooManyUncles {
+        count: usize,
+        max: usize,
+    },
+    InvalidUnclesRoot,
+    /// an uncle header does not match its hash, or its coinbase proof
+    InvalidUncle,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockError::ExtraDataTooLarge { size, max } => write!(
+                f,
+                "Extra data too large: {} bytes, maximum is {}",
+                size, max
+            ),
+            BlockError::TooManyTransactions { count, max } => {
+                write!(f, "Too many transactions: {}, maximum is {}", count, max)
+            }
+            BlockError::Full => write!(f, "Block is full"),
+            BlockError::EmptyGenesis => {
+                write!(f, "Genesis block must have at least one transaction")
+            }
+            BlockError::InvalidTransaction(e) => write!(f, "Invalid transaction: {}", e),
+            BlockError::InvalidTransactionsRoot => write!(f, "Invalid transactions root"),
+            BlockError::InvalidHash => write!(f, "Invalid block hash"),
+            BlockError::TooManyUncles { count, max } => {
+                write!(f, "Too many uncles: {}, maximum is {}", count, max)
+            }
+            BlockError::InvalidUnclesRoot => write!(f, "Invalid uncles root"),
+            BlockError::InvalidUncle => write!(f, "Invalid uncle header or coinbase proof"),
+        }
+    }
+}
+
+impl std::error::Error for BlockError {}
+
+/// Why a block may not reference a stale block as uncle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UncleError {
+    /// uncles are only rewarded on chains with proof of work
+    WithoutProofOfWork,
+    /// the uncle is not one to `MAX_UNCLE_DEPTH` blocks below the block
+    OutOfWindow,
+    /// the uncle does not fork off the chain, or is part of it
+    NotStale,
+    /// referenced by an ancestor or earlier in the same block
+    AlreadyIncluded,
+    InvalidDifficulty {
+        expected: u64,
+        got: u64,
+    },
+    InsufficientWork,
+}
+
+impl fmt::Display for UncleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UncleError::WithoutProofOfWork => write!(f, "Uncles require proof of work"),
+            UncleError::OutOfWindow => write!(f, "Uncle is out of the inclusion window"),
+            UncleError::NotStale => write!(f, "Uncle is not a stale block of the chain"),
+            UncleError::AlreadyIncluded => write!(f, "Uncle is already included"),
+            UncleError::InvalidDifficulty { expected, got } => {
+                write!(f, "Uncle has difficulty {}, expected {}", got, expected)
+            }
+            UncleError::InsufficientWork => write!(f, "Uncle does not meet its difficulty"),
+        }
+    }
+}
+
+impl std::error::Error for UncleError {}
+
+/// Why the chain could not be built, extended or read
+#[derive(Debug, Clone, PartialEq)]
+pub enum LedgerError {
+    Block(BlockError),
+    Transaction(TxError),
+    /// a transaction of block `index` failed to execute
+    BlockTransaction {
+        index: u64,
+        error: TxError,
+    },
+    InvalidGenesis,
+    UnexpectedIndex(u64),
+    UnlinkedBlock(u64),
+    OlderThanParent(u64),
+    /// the timestamp of block `index` is past `max`, the network adjusted
+    /// time plus the allowed drift
+    FutureTimestamp {
+        index: u64,
+        timestamp: u64,
+        max: u64,
+    },
+    InvalidHistoryRoot(u64),
+    /// executing block `index` does not reach the state its header commits
+    /// to, with the first account off the expected state when it is known
+    InvalidStateRoot {
+        index: u64,
+        expected: Hash,
+        computed: Hash,
+        first_diff: Option<Box<AccountDiff>>,
+    },
+    InvalidDifficulty {
+        index: u64,
+        expected: u64,
+        got: u64,
+    },
+    /// the hash of block `index` does not meet its difficulty
+    InsufficientWork(u64),
+    /// block `index` references `uncle`, not a valid uncle at its height
+    InvalidUncle {
+        index: u64,
+        uncle: Hash,
+        error: UncleError,
+    },
+    MissingBlock(u64),
+    /// the account state can only be overwritten with an empty pending block
+    PendingBlockNotEmpty,
+    /// the tip or the pending block changed since the template was sealed
+    StaleTemplate,
+    /// block `index` is already part of the chain or a known fork
+    KnownBlock(u64),
+    /// the header of block `index` differs from the one in the chain
+    UnknownHeader(u64),
+    InvalidProof(ProofError),
+    /// block `index` forks off further back than blocks can be rolled back
+    ForkTooDeep(u64),
+    EmptyStorage,
+    HistoryMismatch,
+    /// first account whose stored state differs from the replayed one
+    StateMismatch(Box<AccountDiff>),
+    Storage(StorageError),
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LedgerError::Block(e) => write!(f, "{}", e),
+            LedgerError::Transaction(e) => write!(f, "{}", e),
+            LedgerError::BlockTransaction { index, error } => {
+                write!(f, "Block {}: {}", index, error)
+            }
+            LedgerError::InvalidGenesis => write!(f, "Invalid genesis block"),
+            LedgerError::UnexpectedIndex(index) => write!(f, "Unexpected block index {}", index),
+            LedgerError::UnlinkedBlock(index) => {
+                write!(f, "Block {} does not link to its parent", index)
+            }
+            LedgerError::OlderThanParent(index) => {
+                write!(f, "Block {} is older than its parent", index)
+            }
+            LedgerError::FutureTimestamp {
+                index,
+                timestamp,
+                max,
+            } => write!(
+                f,
+                "Block {} has timestamp {}, too far in the future of {}",
+                index, timestamp, max
+            ),
+            LedgerError::InvalidHistoryRoot(index) => {
+                write!(f, "Block {} has an invalid history root", index)
+            }
+            LedgerError::InvalidStateRoot {
+                index,
+                expected,
+                computed,
+                first_diff,
+            } => {
+                write!(
+                    f,
+                    "Block {} reaches state root {}, expected {}",
+                    index,
+                    hex::encode(computed),
+                    hex::encode(expected)
+                )?;
+                match first_diff {
+                    Some(diff) => write!(f, ", first difference on {}", diff),
+                    None => Ok(()),
+                }
+            }
+            LedgerError::InvalidDifficulty {
+                index,
+                expected,
+                got,
+            } => write!(
+                f,
+                "Block {} has difficulty {}, expected {}",
+                index, got, expected
+            ),
+            LedgerError::InsufficientWork(index) => {
+                write!(f, "Block {} does not meet its difficulty", index)
+            }
+            LedgerError::InvalidUncle {
+                index,
+                uncle,
+                error,
+            } => write!(
+                f,
+                "Block {} references uncle {}: {}",
+                index,
+                hex::encode(uncle),
+                error
+            ),
+            LedgerError::MissingBlock(index) => write!(f, "Missing block {}", index),
+            LedgerError::PendingBlockNotEmpty => write!(f, "Pending block is not empty"),
+            LedgerError::StaleTemplate => write!(f, "Block template is stale"),
+            LedgerError::KnownBlock(index) => write!(f, "Block {} is already known", index),
+            LedgerError::UnknownHeader(index) => {
+                write!(f, "Header {} is not part of the chain", index)
+            }
+            LedgerError::InvalidProof(e) => write!(f, "Invalid proof: {}", e),
+            LedgerError::ForkTooDeep(index) => {
+                write!(f, "Block {} forks off too deep to switch to", index)
+            }
+            LedgerError::EmptyStorage => write!(f, "Empty storage"),
+            LedgerError::HistoryMismatch => {
+                write!(f, "Stored blocks do not match the chain history")
+            }
+            LedgerError::StateMismatch(diff) => write!(
+                f,
+                "Stored state does not match the replayed chain: {}",
+                diff
+            ),
+            LedgerError::Storage(e) => write!(f, "Storage error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LedgerError {}
+
+impl From<BlockError> for LedgerError {
+    fn from(e: BlockError) -> Self {
+        LedgerError::Block(e)
+    }
+}
+
+impl From<TxError> for LedgerError {
+    fn from(e: TxError) -> Self {
+        LedgerError::Transaction(e)
+    }
+}
+
+impl From<StorageError> for LedgerError {
+    fn from(e: StorageError) -> Self {
+        LedgerError::Storage(e)
+    }
+}
+
+/// Why a `Storage` could not read or write
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageError {
+    #[cfg(feature = "sled")]
+    Sled(sled::Error),
+    /// a record that cannot be encoded or a stored one that does not decode
+    Encoding(String),
+    /// a written block does not follow the last stored one
+    UnexpectedBlock(u64),
+    InvalidIndex,
+    /// failure of write `n` armed through a `FaultInjector`
+    InjectedFault(usize),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "sled")]
+            StorageError::Sled(e) => write!(f, "{}", e),
+            StorageError::Encoding(e) => write!(f, "Invalid record encoding: {}", e),
+            StorageError::UnexpectedBlock(index) => {
+                write!(f, "Block {} does not extend the stored chain", index)
+            }
+            StorageError::InvalidIndex => write!(f, "Invalid block index"),
+            StorageError::InjectedFault(n) => write!(f, "Injected failure of write {}", n),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+#[cfg(feature = "sled")]
+impl From<sled::Error> for StorageError {
+    fn from(e: sled::Error) -> Self {
+        StorageError::Sled(e)
+    }
+}
+
+impl From<bincode::Error> for StorageError {
+    fn from(e: bincode::Error) -> Self {
+        StorageError::Encoding(e.to_string())
+    }
+}
+
+/// Why a merkle or MMR inclusion proof does not hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofError {
+    IndexOutOfRange,
+    /// more or fewer siblings than the path from the leaf has nodes
+    InvalidLength,
+    InvalidPeakCount,
+    /// the path does not lead to the peak of the leaf mountain
+    PeakMismatch,
+    InvalidRoot,
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProofError::IndexOutOfRange => write!(f, "Leaf index out of range"),
+            ProofError::InvalidLength => write!(f, "Invalid proof length"),
+            ProofError::InvalidPeakCount => write!(f, "Invalid number of peaks"),
+            ProofError::PeakMismatch => write!(f, "Leaf does not match its peak"),
+            ProofError::InvalidRoot => write!(f, "Invalid root"),
+        }
+    }
+}
+
+impl std::error::Error for ProofError {}
+
+/// How a state snapshot differs from the expected one
+#[derive(Debug, Clone, PartialEq)]
+pub enum StateError {
+    /// `count` accounts differ, `first` by address
+    AccountsDiffer {
+        count: usize,
+        first: Box<AccountDiff>,
+    },
+    TotalSupply {
+        expected: u64,
+        got: u64,
+    },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::AccountsDiffer { count, first } => {
+                write!(f, "{} accounts differ, first {}", count, first)
+            }
+            StateError::TotalSupply { expected, got } => {
+                write!(f, "total supply: expected {}, got {}", expected, got)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::account::Account;
+use crate::address::Address;
+use crate::block::Block;
+use crate::crypto::Hash;
+use crate::error::StorageError;
+use crate::storage::{Storage, StorageBatch};
+
+#[derive(Debug, Default)]
+struct Faults {
+    /// records written before the one failing, `None` when writes succeed
+    failing_write: Option<usize>,
+    read_delay: Option<Duration>,
+    corrupt_blocks: HashSet<u64>,
+}
+
+/// Handle arming the faults of a `FaultyStorage`, kept by the test while the
+/// storage itself is owned by the chain
+#[derive(Debug, Clone, Default)]
+pub struct FaultInjector {
+    faults: Arc<Mutex<Faults>>,
+}
+
+impl FaultInjector {
+    pub fn new() -> FaultInjector {
+        FaultInjector::default()
+    }
+
+    fn faults(&self) -> MutexGuard<'_, Faults> {
+        self.faults.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Make the `k`-th record written from now on fail, 1 being the next
+    /// one. The batch holding it is rejected as a whole, nothing of it
+    /// reaches the storage.
+    pub fn fail_write(&self, k: usize) {
+        self.faults().failing_write = k.checked_sub(1);
+    }
+
+    /// sleep before every read, `None` to read at full speed again
+    pub fn delay_reads(&self, delay: Option<Duration>) {
+        self.faults().read_delay = delay;
+    }
+
+    /// return block `index` altered, so it no longer matches its hash, until
+    /// `clear` is called
+    pub fn corrupt_block_on_read(&self, index: u64) {
+        self.faults().corrupt_blocks.insert(index);
+    }
+
+    pub fn clear(&self) {
+        *self.faults() = Faults::default();
+    }
+
+    fn before_commit(&self, batch: &StorageBatch) -> Result<(), StorageError> {
+        let mut faults = self.faults();
+        if let Some(before) = faults.failing_write {
+            if before < batch.len() {
+                faults.failing_write = None;
+                return Err(StorageError::InjectedFault(before + 1));
+            }
+            faults.failing_write = Some(before - batch.len());
+        }
+        Ok(())
+    }
+
+    fn before_read(&self) {
+        let delay = self.faults().read_delay;
+        if let Some(delay) = delay {
+            thread::sleep(delay);
+        }
+    }
+
+    fn after_block_read(&self, block: Option<Block>) -> Option<Block> {
+        let corrupt = |block: &Block| self.faults().corrupt_blocks.contains(&block.index());
+        block.map(|mut block| {
+            if corrupt(&block) {
+                block.corrupt();
+            }
+            block
+        })
+    }
+}
+
+/// Storage wrapper failing, slowing down or corrupting operations as armed
+/// through its `FaultInjector`, to exercise crash-safety and recovery paths
+pub struct FaultyStorage<S> {
+    inner: S,
+    injector: FaultInjector,
+}
+
+impl<S: Storage> FaultyStorage<S> {
+    pub fn new(inner: S) -> FaultyStorage<S> {
+        FaultyStorage {
+            inner,
+            injector: FaultInjector::new(),
+        }
+    }
+
+    pub fn injector(&self) -> FaultInjector {
+        self.injector.clone()
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: Storage> Storage for FaultyStorage<S> {
+    fn commit(&mut self, batch: &StorageBatch) -> Result<(), StorageError> {
+        self.injector.before_commit(batch)?;
+        self.inner.commit(batch)
+    }
+
+    fn block_by_index(&self, index: u64) -> Result<Option<Block>, StorageError> {
+        self.injector.before_read();
+        Ok(self
+            .injector
+            .after_block_read(self.inner.block_by_index(index)?))
+    }
+
+    fn block_by_hash(&self, hash: &Hash) -> Result<Option<Block>, StorageError> {
+        self.injector.before_read();
+        Ok(self
+            .injector
+            .after_block_read(self.inner.block_by_hash(hash)?))
+    }
+
+    fn account(&self, address: &Address) -> Result<Option<Account>, StorageError> {
+        self.injector.before_read();
+        self.inner.account(address)
+    }
+
+    fn head(&self) -> Result<Option<u64>, StorageError> {
+        self.injector.before_read();
+        self.inner.head()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::consensus::ConsensusConfig;
+    use crate::crypto::generate_keypair_from_secret;
+    use crate::error::{BlockError, LedgerError};
+    use crate::storage::MemoryStorage;
+    use crate::transaction::Transaction;
+    use std::time::Instant;
+
+    #[test]
+    fn test_recover_from_injected_faults() {
+        let (private_key, public_key) = generate_keypair_from_secret(b"faults");
+        let genesis =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 1000, 0, &private_key)
+                .unwrap();
+        let mut blockchain = Blockchain::new(genesis, 0).unwrap();
+        let storage = FaultyStorage::new(MemoryStorage::new());
+        let injector = storage.injector();
+        blockchain.set_storage(Box::new(storage)).unwrap();
+        for nonce in 0..2 {
+            let tx = Transaction::new_and_sign(
+                &public_key,
+                &public_key.address(),
+                10,
+                nonce,
+                &private_key,
+            )
+            .unwrap();
+            blockchain.add_transaction(tx).unwrap();
+            if nonce == 1 {
+                injector.fail_write(1);
+            }
+            let result = blockchain.finalize_and_mint_pending_block();
+            assert_eq!(result.is_err(), nonce == 1);
+        }
+
+        // the failed block never reached the storage, reopening recovers
+        // the chain as of the block before
+        let storage = blockchain.into_storage();
+        let reopened = Blockchain::open(storage, ConsensusConfig::default()).unwrap();
+        assert_eq!(reopened.last_block().unwrap().index(), 1);
+
+        injector.corrupt_block_on_read(1);
+        injector.delay_reads(Some(Duration::from_millis(10)));
+        let start = Instant::now();
+        assert_eq!(
+            Blockchain::open(reopened.into_storage(), ConsensusConfig::default()).err(),
+            Some(LedgerError::Block(BlockError::InvalidHash))
+        );
+        assert!(start.elapsed() >= Duration::from_millis(10));
+    }
+
+    #[test]
+    fn test_failed_write_leaves_chain_unchanged() {
+        let (private_key, public_key) = generate_keypair_from_secret(b"faults");
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let genesis =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 1000, 0, &private_key)
+                .unwrap();
+        let mut blockchain = Blockchain::new(genesis, 0).unwrap();
+        let storage = FaultyStorage::new(MemoryStorage::new());
+        let injector = storage.injector();
+        blockchain.set_storage(Box::new(storage)).unwrap();
+        let tx =
+            Transaction::new_and_sign(&public_key, &other.address(), 10, 0, &private_key).unwrap();
+        blockchain.add_transaction(tx.clone()).unwrap();
+        let last_block = blockchain.last_block().cloned();
+
+        // the block, both accounts and the head
+        for k in 1..=4 {
+            injector.fail_write(k);
+            assert!(blockchain.finalize_and_mint_pending_block().is_err());
+            assert_eq!(blockchain.last_block().cloned(), last_block);
+            assert_eq!(blockchain.get_account(&other.address()), None);
+            assert_eq!(blockchain.mempool().len(), 1);
+            blockchain.validate_chain().unwrap();
+        }
+        injector.clear();
+        blockchain.finalize_and_mint_pending_block().unwrap();
+        assert!(blockchain.get_transaction(&tx.id()).unwrap().is_some());
+        let reopened =
+            Blockchain::open(blockchain.into_storage(), ConsensusConfig::default()).unwrap();
+        assert_eq!(
+            reopened.get_account(&other.address()).unwrap().balance(),
+            10
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::account::Account;
+use crate::address::Address;
+use crate::backup::BackupError;
+use crate::blockchain::Blockchain;
+use crate::consensus::ConsensusConfig;
+#[cfg(test)]
+use crate::crypto::{generate_keypair_from_secret, PrivateKey, PublicKey};
+use crate::error::LedgerError;
+#[cfg(test)]
+use crate::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+
+/// State of a single account, enough to recreate it in another chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountSnapshot {
+    pub address: Address,
+    pub balance: u64,
+    pub nonce: u64,
+}
+
+/// Chain and state pinned as bytes for regression tests, in the format of
+/// `Blockchain::backup_to`, under the default consensus rules. Loading replays every block and checks the
+/// replayed state against the pinned one.
+pub struct TestChain {
+    blockchain: Blockchain,
+}
+
+impl TestChain {
+    pub fn from_fixture(bytes: &[u8]) -> Result<TestChain, BackupError> {
+        Ok(TestChain {
+            blockchain: Blockchain::from_backup_bytes(bytes, ConsensusConfig::default())?,
+        })
+    }
+
+    pub fn to_fixture(blockchain: &Blockchain) -> Result<Vec<u8>, BackupError> {
+        blockchain.to_backup_bytes()
+    }
+
+    pub fn export_account(&self, address: &Address) -> Option<AccountSnapshot> {
+        let account = self.blockchain.get_account(address)?;
+        Some(AccountSnapshot {
+            address: account.address(),
+            balance: account.balance(),
+            nonce: account.nonce(),
+        })
+    }
+
+    /// Put an account in the state directly instead of replaying the
+    /// transactions that would lead to it. The chain can no longer be
+    /// reopened from its storage or backups afterwards.
+    pub fn import_account(&mut self, snapshot: &AccountSnapshot) -> Result<(), LedgerError> {
+        self.blockchain.overwrite_account(Account::from_parts(
+            &snapshot.address,
+            snapshot.balance,
+            snapshot.nonce,
+        ))
+    }
+
+    pub fn into_inner(self) -> Blockchain {
+        self.blockchain
+    }
+}
+
+impl From<Blockchain> for TestChain {
+    fn from(blockchain: Blockchain) -> Self {
+        TestChain { blockchain }
+    }
+}
+
+impl Deref for TestChain {
+    type Target = Blockchain;
+
+    fn deref(&self) -> &Blockchain {
+        &self.blockchain
+    }
+}
+
+impl DerefMut for TestChain {
+    fn deref_mut(&mut self) -> &mut Blockchain {
+        &mut self.blockchain
+    }
+}
+
+/// genesis transaction of the unit tests, crediting 1000 to the key
+/// derived from `b"genesis"`
+#[cfg(test)]
+pub(crate) fn test_genesis() -> (PrivateKey, PublicKey, Transaction) {
+    let (private_key, public_key) = generate_keypair_from_secret(b"genesis");
+    let genesis =
+        Transaction::new_and_sign(&public_key, &public_key.address(), 1000, 0, &private_key)
+            .unwrap();
+    (private_key, public_key, genesis)
+}
+
+/// chain holding only the block of `test_genesis`
+#[cfg(test)]
+pub(crate) fn test_chain() -> (PrivateKey, PublicKey, Blockchain) {
+    let (private_key, public_key, genesis) = test_genesis();
+    (
+        private_key,
+        public_key,
+        Blockchain::new(genesis, 0).unwrap(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixture_round_trip() {
+        let (private_key, public_key) = generate_keypair_from_secret(b"fixture");
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let genesis =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 1000, 0, &private_key)
+                .unwrap();
+        let mut blockchain = Blockchain::new(genesis, 0).unwrap();
+        let tx =
+            Transaction::new_and_sign(&public_key, &other.address(), 10, 0, &private_key).unwrap();
+        blockchain.add_transaction(tx).unwrap();
+        blockchain.finalize_and_mint_pending_block().unwrap();
+
+        let fixture = TestChain::to_fixture(&blockchain).unwrap();
+        let mut chain = TestChain::from_fixture(&fixture).unwrap();
+        assert_eq!(chain.blocks(), blockchain.blocks());
+        assert_eq!(chain.state_root(), blockchain.state_root());
+
+        let tx =
+            Transaction::new_and_sign(&public_key, &other.address(), 10, 1, &private_key).unwrap();
+        chain.add_transaction(tx).unwrap();
+        assert_eq!(chain.get_account(&other.address()).unwrap().balance(), 20);
+        assert!(TestChain::from_fixture(&fixture[1..]).is_err());
+    }
+
+    #[test]
+    fn test_import_account() {
+        let (private_key, public_key) = generate_keypair_from_secret(b"fixture");
+        let (other_key, other) = generate_keypair_from_secret(b"other");
+        let genesis =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 1000, 0, &private_key)
+                .unwrap();
+        let mut chain = TestChain::from(Blockchain::new(genesis, 0).unwrap());
+        assert_eq!(chain.export_account(&other.address()), None);
+
+        let snapshot = AccountSnapshot {
+            address: other.address(),
+            balance: 500,
+            nonce: 7,
+        };
+        chain.import_account(&snapshot).unwrap();
+        assert_eq!(chain.export_account(&other.address()), Some(snapshot));
+        assert_eq!(chain.total_supply(), 1500);
+
+        let tx =
+            Transaction::new_and_sign(&other, &public_key.address(), 50, 7, &other_key).unwrap();
+        chain.add_transaction(tx).unwrap();
+        assert_eq!(
+            chain.get_account(&public_key.address()).unwrap().balance(),
+            1050
+        );
+        assert!(chain
+            .import_account(&chain.export_account(&public_key.address()).unwrap())
+            .is_err());
+    }
+}
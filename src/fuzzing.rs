@@ -0,0 +1,140 @@
+//! Differential harness: the same random transaction stream is executed
+//! live, block by block, and then replayed from the resulting blocks. Both
+//! paths, and a state commitment rebuilt from scratch, must agree.
+
+use std::fmt;
+
+use secp256k1::rand::rngs::StdRng;
+use secp256k1::rand::{Rng, SeedableRng};
+
+use crate::blockchain::Blockchain;
+use crate::commitment::MerkleStateCommitment;
+use crate::crypto::{generate_keypair_from_secret, CryptoError, Hash, PrivateKey, PublicKey};
+use crate::error::{LedgerError, StateError};
+use crate::transaction::Transaction;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FuzzError {
+    Crypto(CryptoError),
+    Ledger(LedgerError),
+    /// the replayed state differs from the live one
+    StateDiverges {
+        seed: u64,
+        error: StateError,
+    },
+    StateRootDiverges {
+        seed: u64,
+    },
+    /// the incremental state root differs from one rebuilt from scratch
+    RebuiltRootDiverges {
+        seed: u64,
+    },
+}
+
+impl fmt::Display for FuzzError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FuzzError::Crypto(e) => write!(f, "{}", e),
+            FuzzError::Ledger(e) => write!(f, "{}", e),
+            FuzzError::StateDiverges { seed, error } => {
+                write!(f, "Seed {}: replayed state diverges, {}", seed, error)
+            }
+            FuzzError::StateRootDiverges { seed } => {
+                write!(f, "Seed {}: replayed state root diverges", seed)
+            }
+            FuzzError::RebuiltRootDiverges { seed } => write!(
+                f,
+                "Seed {}: incremental state root diverges from a rebuild",
+                seed
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FuzzError {}
+
+impl From<CryptoError> for FuzzError {
+    fn from(e: CryptoError) -> Self {
+        FuzzError::Crypto(e)
+    }
+}
+
+impl From<LedgerError> for FuzzError {
+    fn from(e: LedgerError) -> Self {
+        FuzzError::Ledger(e)
+    }
+}
+
+/// Run `steps` random transactions derived from `seed` over `accounts`
+/// accounts and return the agreed state root
+pub fn differential_run(seed: u64, accounts: usize, steps: usize) -> Result<Hash, FuzzError> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let keys: Vec<(PrivateKey, PublicKey)> = (0..accounts.max(1))
+        .map(|i| generate_keypair_from_secret(format!("fuzz-{}", i).as_bytes()))
+        .collect();
+    let (genesis_key, genesis_address) = keys[0];
+    let genesis = Transaction::new_and_sign(
+        &genesis_address,
+        &genesis_address.address(),
+        1_000_000,
+        0,
+        &genesis_key,
+    )?;
+    let mut live = Blockchain::new(genesis, 0)?;
+
+    for _ in 0..steps {
+        let (private_key, from) = keys[rng.gen_range(0..keys.len())];
+        let (_, to) = keys[rng.gen_range(0..keys.len())];
+        let account = live.get_account(&from.address());
+        let balance = account.map_or(0, |a| a.balance());
+        let mut nonce = account.map_or(0, |a| a.nonce());
+        // some transactions are invalid on purpose, both paths must reject
+        // them the same way
+        if rng.gen_ratio(1, 10) {
+            nonce += 1;
+        }
+        let amount = rng.gen_range(0..=balance.saturating_mul(2).max(1));
+        let mut tx = if rng.gen_ratio(1, 8) {
+            Transaction::new_burn(&from, amount, nonce)
+        } else {
+            Transaction::new(&from, &to.address(), amount, nonce)
+        };
+        tx.sign(&private_key)?;
+        let _ = live.add_transaction(tx);
+        if rng.gen_ratio(1, 5) {
+            live.finalize_and_mint_pending_block()?;
+        }
+    }
+    live.finalize_and_mint_pending_block()?;
+
+    let replayed = Blockchain::from_blocks(live.blocks()?, *live.consensus())?;
+    replayed
+        .state_snapshot()
+        .check_matches(&live.state_snapshot())
+        .map_err(|error| FuzzError::StateDiverges { seed, error })?;
+    if live.state_root() != replayed.state_root() {
+        return Err(FuzzError::StateRootDiverges { seed });
+    }
+    let mut rebuilt = Blockchain::from_blocks(live.blocks()?, *live.consensus())?;
+    rebuilt.set_state_commitment(Box::new(MerkleStateCommitment::new()));
+    if rebuilt.state_root() != live.state_root() {
+        return Err(FuzzError::RebuiltRootDiverges { seed });
+    }
+    Ok(live.state_root())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_differential_runs() {
+        for seed in 0..4 {
+            differential_run(seed, 6, 150).unwrap();
+        }
+        assert_eq!(
+            differential_run(42, 6, 100).unwrap(),
+            differential_run(42, 6, 100).unwrap()
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::TxError;
+
+/// Fees an account agreed to pay for another, set by a
+/// `TransactionKind::GrantFees` transaction of the granter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeGrant {
+    /// fees left to pay, the grant is removed once it reaches 0
+    pub remaining: u64,
+    /// first block index the grant no longer pays fees in
+    pub expires_at: u64,
+}
+
+impl FeeGrant {
+    pub fn is_expired(&self, index: u64) -> bool {
+        index >= self.expires_at
+    }
+
+    /// the grant after paying `fee` in block `index`
+    pub fn spend(&self, fee: u64, index: u64) -> Result<FeeGrant, TxError> {
+        if self.is_expired(index) {
+            return Err(TxError::FeeGrantExpired);
+        }
+        let remaining = self
+            .remaining
+            .checked_sub(fee)
+            .ok_or(TxError::FeeGrantExceeded {
+                remaining: self.remaining,
+                required: fee,
+            })?;
+        Ok(FeeGrant {
+            remaining,
+            expires_at: self.expires_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spend_grant() {
+        let grant = FeeGrant {
+            remaining: 10,
+            expires_at: 5,
+        };
+        assert_eq!(grant.spend(4, 4).unwrap().remaining, 6);
+        assert_eq!(grant.spend(4, 5), Err(TxError::FeeGrantExpired));
+        assert_eq!(
+            grant.spend(11, 0),
+            Err(TxError::FeeGrantExceeded {
+                remaining: 10,
+                required: 11
+            })
+        );
+    }
+}
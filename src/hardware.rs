@@ -0,0 +1,251 @@
+use std::fmt;
+
+use crate::crypto::{Hash, PublicKey, Signature};
+use crate::encoding;
+use crate::signer::{Signer, SignerError};
+use crate::transaction::Transaction;
+
+pub const CLA: u8 = 0xe0;
+pub const INS_GET_PUBLIC_KEY: u8 = 0x02;
+pub const INS_SIGN_HASH: u8 = 0x04;
+pub const INS_SIGN_TRANSACTION: u8 = 0x06;
+pub const INS_SIGN_MESSAGE: u8 = 0x08;
+
+pub const SW_OK: u16 = 0x9000;
+pub const SW_DENIED: u16 = 0x6985;
+pub const SW_WRONG_DATA: u16 = 0x6a80;
+pub const SW_INS_NOT_SUPPORTED: u16 = 0x6d00;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceError {
+    /// more data than a short APDU holds
+    ApduTooLong,
+    InvalidApdu,
+    /// a response without status word or with data that does not decode
+    InvalidResponse,
+    /// the user refused the request on the device
+    Denied,
+    Status(u16),
+    /// reported by the `Transport`, e.g. the device was unplugged
+    Transport(String),
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeviceError::ApduTooLong => write!(f, "APDU data too long"),
+            DeviceError::InvalidApdu => write!(f, "Invalid APDU"),
+            DeviceError::InvalidResponse => write!(f, "Invalid APDU response"),
+            DeviceError::Denied => write!(f, "Request denied on device"),
+            DeviceError::Status(sw) => write!(f, "Device error: {:#06x}", sw),
+            DeviceError::Transport(e) => write!(f, "Transport error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+/// Command sent to a device, ISO 7816 short APDU layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct Apdu {
+    pub cla: u8,
+    pub ins: u8,
+    pub p1: u8,
+    pub p2: u8,
+    pub data: Vec<u8>,
+}
+
+impl Apdu {
+    pub fn new(ins: u8, p1: u8, data: Vec<u8>) -> Apdu {
+        Apdu {
+            cla: CLA,
+            ins,
+            p1,
+            p2: 0,
+            data,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DeviceError> {
+        let len = u8::try_from(self.data.len()).map_err(|_| DeviceError::ApduTooLong)?;
+        let header = [self.cla, self.ins, self.p1, self.p2, len];
+        Ok([&header[..], &self.data].concat())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Apdu, DeviceError> {
+        if bytes.len() < 5 || bytes.len() != 5 + bytes[4] as usize {
+            return Err(DeviceError::InvalidApdu);
+        }
+        Ok(Apdu {
+            cla: bytes[0],
+            ins: bytes[1],
+            p1: bytes[2],
+            p2: bytes[3],
+            data: bytes[5..].to_vec(),
+        })
+    }
+}
+
+/// Moves raw APDUs to a device (USB HID, BLE, TCP to an emulator...) and
+/// returns the raw response, data followed by the two status bytes
+pub trait Transport {
+    fn exchange(&self, command: &[u8]) -> Result<Vec<u8>, DeviceError>;
+}
+
+pub struct HardwareSigner<T: Transport> {
+    transport: T,
+    account: u8,
+    public_key: PublicKey,
+}
+
+impl<T: Transport> HardwareSigner<T> {
+    /// Open the key at `account` on the device
+    pub fn connect(transport: T, account: u8) -> Result<HardwareSigner<T>, DeviceError> {
+        let response = send(&transport, &Apdu::new(INS_GET_PUBLIC_KEY, account, vec![]))?;
+        let public_key = encoding::decode(&response).map_err(|_| DeviceError::InvalidResponse)?;
+        Ok(HardwareSigner {
+            transport,
+            account,
+            public_key,
+        })
+    }
+
+    pub fn account(&self) -> u8 {
+        self.account
+    }
+
+    fn request_signature(&self, ins: u8, data: Vec<u8>) -> Result<Signature, DeviceError> {
+        let response = send(&self.transport, &Apdu::new(ins, self.account, data))?;
+        encoding::decode(&response).map_err(|_| DeviceError::InvalidResponse)
+    }
+}
+
+fn send<T: Transport>(transport: &T, apdu: &Apdu) -> Result<Vec<u8>, DeviceError> {
+    let mut response = transport.exchange(&apdu.to_bytes()?)?;
+    if response.len() < 2 {
+        return Err(DeviceError::InvalidResponse);
+    }
+    let sw = response.split_off(response.len() - 2);
+    match u16::from_be_bytes([sw[0], sw[1]]) {
+        SW_OK => Ok(response),
+        SW_DENIED => Err(DeviceError::Denied),
+        sw => Err(DeviceError::Status(sw)),
+    }
+}
+
+impl<T: Transport> Signer for HardwareSigner<T> {
+    fn public_key(&self) -> PublicKey {
+        self.public_key
+    }
+
+    fn sign_hash(&self, hash: &Hash) -> Result<Signature, SignerError> {
+        Ok(self.request_signature(INS_SIGN_HASH, hash.as_ref().to_vec())?)
+    }
+
+    fn sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        Ok(self.request_signature(INS_SIGN_MESSAGE, message.to_vec())?)
+    }
+
+    fn sign_transaction(&self, tx: &mut Transaction) -> Result<(), SignerError> {
+        if tx.from() != self.public_key {
+            return Err(SignerError::WrongSigner);
+        }
+        let signature = self.request_signature(INS_SIGN_TRANSACTION, tx.to_bytes())?;
+        tx.set_signature(&signature);
+        Ok(tx.verify()?)
+    }
+}
+
+#[cfg(any(test, feature = "hardware-emulator"))]
+pub use emulator::EmulatedDevice;
+
+#[cfg(any(test, feature = "hardware-emulator"))]
+mod emulator {
+    use super::*;
+    use crate::crypto::{generate_keypair_from_secret, message_hash, sign_hash, PrivateKey};
+
+    /// In-process device deriving its accounts from a seed, useful to test
+    /// integrations without hardware
+    pub struct EmulatedDevice {
+        seed: Vec<u8>,
+        blind_signing: bool,
+    }
+
+    impl EmulatedDevice {
+        pub fn new(seed: &[u8]) -> EmulatedDevice {
+            EmulatedDevice {
+                seed: seed.to_vec(),
+                blind_signing: false,
+            }
+        }
+
+        /// real devices refuse to sign hashes they cannot display by default
+        pub fn allow_blind_signing(&mut self, allow: bool) {
+            self.blind_signing = allow;
+        }
+
+        fn key(&self, account: u8) -> PrivateKey {
+            generate_keypair_from_secret(&[&self.seed[..], &[account]].concat()).0
+        }
+
+        fn handle(&self, apdu: &Apdu) -> Result<Vec<u8>, u16> {
+            if apdu.cla != CLA {
+                return Err(SW_INS_NOT_SUPPORTED);
+            }
+            let key = self.key(apdu.p1);
+            let hash = match apdu.ins {
+                INS_GET_PUBLIC_KEY => {
+                    let public_key = PublicKey::try_from(&key).map_err(|_| SW_WRONG_DATA)?;
+                    return encoding::encode(&public_key).map_err(|_| SW_WRONG_DATA);
+                }
+                INS_SIGN_HASH if self.blind_signing => {
+                    encoding::decode::<Hash>(&apdu.data).map_err(|_| SW_WRONG_DATA)?
+                }
+                INS_SIGN_HASH => return Err(SW_DENIED),
+                INS_SIGN_TRANSACTION => Transaction::from_bytes(&apdu.data)
+                    .map_err(|_| SW_WRONG_DATA)?
+                    .signing_hash(),
+                INS_SIGN_MESSAGE => message_hash(&apdu.data),
+                _ => return Err(SW_INS_NOT_SUPPORTED),
+            };
+            let signature = sign_hash(&hash, &key).map_err(|_| SW_WRONG_DATA)?;
+            encoding::encode(&signature).map_err(|_| SW_WRONG_DATA)
+        }
+    }
+
+    impl Transport for EmulatedDevice {
+        fn exchange(&self, command: &[u8]) -> Result<Vec<u8>, DeviceError> {
+            let (data, sw) = match Apdu::from_bytes(command) {
+                Ok(apdu) => match self.handle(&apdu) {
+                    Ok(data) => (data, SW_OK),
+                    Err(sw) => (vec![], sw),
+                },
+                Err(_) => (vec![], SW_WRONG_DATA),
+            };
+            Ok([&data[..], &sw.to_be_bytes()].concat())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_transaction_on_device() {
+        let signer = HardwareSigner::connect(EmulatedDevice::new(b"seed"), 1).unwrap();
+        let mut tx = Transaction::new(&signer.public_key(), &signer.public_key().address(), 10, 0);
+        signer.sign_transaction(&mut tx).unwrap();
+        tx.verify().unwrap();
+    }
+
+    #[test]
+    fn test_blind_signing_denied() {
+        let signer = HardwareSigner::connect(EmulatedDevice::new(b"seed"), 0).unwrap();
+        let hash = Hash::from("message".as_bytes());
+        assert_eq!(
+            signer.sign_hash(&hash),
+            Err(SignerError::Device(DeviceError::Denied))
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use crate::crypto::{CryptoError, Hash, PrivateKey, PublicKey, Signature};
+use crate::transaction::Transaction;
+use crate::typed_data::{sign_typed_data, verify_typed_data, Domain, StructEncoder, TypedData};
+use crate::wallet::PaymentRequest;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvoiceError {
+    Crypto(CryptoError),
+    /// signed by another key than the one of the recipient
+    WrongSigner,
+    Expired,
+}
+
+impl fmt::Display for InvoiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvoiceError::Crypto(e) => write!(f, "{}", e),
+            InvoiceError::WrongSigner => write!(f, "Invoice must be signed by its recipient"),
+            InvoiceError::Expired => write!(f, "Invoice expired"),
+        }
+    }
+}
+
+impl std::error::Error for InvoiceError {}
+
+impl From<CryptoError> for InvoiceError {
+    fn from(e: CryptoError) -> Self {
+        InvoiceError::Crypto(e)
+    }
+}
+
+/// Payment claim issued by the recipient, signed as typed data so a payer
+/// can check who asked for the funds before paying
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invoice {
+    /// chosen by the recipient, e.g. an order number
+    pub id: u64,
+    pub recipient: PublicKey,
+    /// only account allowed to settle the invoice, anyone when `None`
+    pub payer: Option<PublicKey>,
+    pub amount: u64,
+    pub memo: String,
+    /// unix timestamp in seconds after which the invoice must not be paid
+    pub expiry: u64,
+}
+
+impl TypedData for Invoice {
+    fn encode_type() -> String {
+        "Invoice(uint64 id,bytes recipient,bytes payer,uint64 amount,string memo,uint64 expiry)"
+            .to_string()
+    }
+
+    fn encode_data(&self) -> Vec<u8> {
+        StructEncoder::new()
+            .u64(self.id)
+            .public_key(&self.recipient)
+            .bytes(self.payer.as_ref().map_or(&[][..], |payer| payer.as_ref()))
+            .u64(self.amount)
+            .string(&self.memo)
+            .u64(self.expiry)
+            .finish()
+    }
+}
+
+impl Invoice {
+    /// set on the paying transaction, commits to the id and the memo
+    pub fn reference(&self) -> Hash {
+        Hash::from_parts(&[&self.id.to_be_bytes(), self.memo.as_bytes()])
+    }
+
+    /// sign with the key of the recipient, no one else can issue invoices
+    /// for an account
+    pub fn sign(
+        self,
+        domain: &Domain,
+        private_key: &PrivateKey,
+    ) -> Result<SignedInvoice, InvoiceError> {
+        let public_key = PublicKey::try_from(private_key)?;
+        if public_key != self.recipient {
+            return Err(InvoiceError::WrongSigner);
+        }
+        let signature = sign_typed_data(domain, &self, private_key)?;
+        Ok(SignedInvoice {
+            invoice: self,
+            signature,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedInvoice {
+    pub invoice: Invoice,
+    pub signature: Signature,
+}
+
+impl SignedInvoice {
+    /// signature by the recipient and expiry, to check before paying
+    pub fn verify(&self, domain: &Domain, now: u64) -> Result<(), InvoiceError> {
+        verify_typed_data(
+            domain,
+            &self.invoice,
+            &self.invoice.recipient,
+            &self.signature,
+        )?;
+        if now > self.invoice.expiry {
+            return Err(InvoiceError::Expired);
+        }
+        Ok(())
+    }
+
+    /// Whether `tx` pays the invoice in full: sent to the recipient with the
+    /// reference of the invoice, and by its payer if it names one
+    pub fn is_settled_by(&self, tx: &Transaction) -> bool {
+        let invoice = &self.invoice;
+        tx.to() == Some(invoice.recipient.address())
+            && tx.amount() >= invoice.amount
+            && tx.reference() == Some(invoice.reference())
+            && invoice.payer.is_none_or(|payer| tx.from() == payer)
+    }
+
+    pub fn to_payment_request(&self) -> PaymentRequest {
+        PaymentRequest {
+            recipient: self.invoice.recipient.address(),
+            amount: Some(self.invoice.amount),
+            memo: Some(self.invoice.memo.clone()),
+            reference: Some(self.invoice.reference()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keypair;
+    use crate::wallet::Wallet;
+
+    #[test]
+    fn test_verify_and_settle_invoice() {
+        let (merchant_key, merchant) = generate_keypair();
+        let domain = Domain {
+            name: "shop".to_string(),
+            version: "1".to_string(),
+            chain_id: 1,
+        };
+        let invoice = Invoice {
+            id: 7,
+            recipient: merchant,
+            payer: None,
+            amount: 30,
+            memo: "order 7".to_string(),
+            expiry: 1_000,
+        };
+        let (other_key, _) = generate_keypair();
+        assert!(invoice.clone().sign(&domain, &other_key).is_err());
+
+        let signed = invoice.sign(&domain, &merchant_key).unwrap();
+        signed.verify(&domain, 1_000).unwrap();
+        assert_eq!(signed.verify(&domain, 1_001), Err(InvoiceError::Expired));
+        let mut tampered = signed.clone();
+        tampered.invoice.amount = 3;
+        assert!(tampered.verify(&domain, 0).is_err());
+
+        let payer = Wallet::generate();
+        let tx = payer.build_payment(&signed.to_payment_request()).unwrap();
+        assert!(signed.is_settled_by(&tx));
+        let mut short = payer.build_transfer(&merchant.address(), 29);
+        short.set_reference(Some(signed.invoice.reference()));
+        assert!(!signed.is_settled_by(&short));
+        // same amount and recipient, paying another invoice
+        let mut other = signed.clone();
+        other.invoice.id = 8;
+        other.invoice.memo = "order 8".to_string();
+        assert!(!other.is_settled_by(&tx));
+        assert!(!signed.is_settled_by(&payer.build_transfer(&merchant.address(), 30)));
+
+        let mut addressed = signed.invoice.clone();
+        addressed.payer = Some(payer.public_key());
+        let addressed = addressed.sign(&domain, &merchant_key).unwrap();
+        assert_ne!(addressed.signature, signed.signature);
+        assert!(addressed.is_settled_by(&tx));
+        let stranger = Wallet::generate();
+        let tx = stranger
+            .build_payment(&addressed.to_payment_request())
+            .unwrap();
+        assert!(!addressed.is_settled_by(&tx));
+    }
+}
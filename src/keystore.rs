@@ -0,0 +1,334 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use bip39::Mnemonic;
+use secp256k1::hashes::hmac::{Hmac, HmacEngine};
+use secp256k1::hashes::{sha512, Hash as _, HashEngine};
+use secp256k1::rand::rngs::OsRng;
+use secp256k1::rand::RngCore;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::backup::write_atomically;
+use crate::crypto::{CryptoError, PrivateKey, PublicKey};
+use crate::encoding;
+use crate::signer::LocalSigner;
+
+const KEYSTORE_MAGIC: &[u8; 4] = b"CLKS";
+const KEYSTORE_VERSION: u8 = 1;
+/// HMAC key of the BIP-32 master key derivation
+const BIP32_SEED_KEY: &[u8] = b"Bitcoin seed";
+/// most memory, `128 * r * 2^log_n` bytes, a keystore may make scrypt use
+const MAX_KDF_MEMORY: u128 = 1 << 30;
+const MAX_KDF_PARALLELISM: u32 = 16;
+
+#[derive(Debug)]
+pub enum KeystoreError {
+    Crypto(CryptoError),
+    InvalidKdfParams,
+    /// the scrypt parameters of the keystore exceed the memory or parallelism
+    /// caps, refused before deriving
+    KdfTooCostly(KdfParams),
+    Encryption,
+    /// the ciphertext or the address does not authenticate under the key
+    WrongPassphrase,
+    NotAKeystore,
+    UnsupportedVersion(u8),
+    Encoding(String),
+    Io(io::Error),
+    Mnemonic(bip39::Error),
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeystoreError::Crypto(e) => write!(f, "{}", e),
+            KeystoreError::InvalidKdfParams => write!(f, "Invalid scrypt parameters"),
+            KeystoreError::KdfTooCostly(kdf) => write!(
+                f,
+                "Scrypt parameters log_n {}, r {}, p {} exceed the limits",
+                kdf.log_n, kdf.r, kdf.p
+            ),
+            KeystoreError::Encryption => write!(f, "Encryption failed"),
+            KeystoreError::WrongPassphrase => {
+                write!(f, "Wrong passphrase or corrupted keystore")
+            }
+            KeystoreError::NotAKeystore => write!(f, "Not a keystore file"),
+            KeystoreError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported keystore version {}", version)
+            }
+            KeystoreError::Encoding(e) => write!(f, "Encoding error: {}", e),
+            KeystoreError::Io(e) => write!(f, "{}", e),
+            KeystoreError::Mnemonic(e) => write!(f, "Invalid mnemonic: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for KeystoreError {}
+
+impl From<CryptoError> for KeystoreError {
+    fn from(e: CryptoError) -> Self {
+        KeystoreError::Crypto(e)
+    }
+}
+
+impl From<io::Error> for KeystoreError {
+    fn from(e: io::Error) -> Self {
+        KeystoreError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for KeystoreError {
+    fn from(e: bincode::Error) -> Self {
+        KeystoreError::Encoding(e.to_string())
+    }
+}
+
+impl From<bip39::Error> for KeystoreError {
+    fn from(e: bip39::Error) -> Self {
+        KeystoreError::Mnemonic(e)
+    }
+}
+
+/// scrypt cost of the key encrypting the private key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl KdfParams {
+    /// refuse parameters costing more than `MAX_KDF_MEMORY` or
+    /// `MAX_KDF_PARALLELISM`, they come from the file being decrypted
+    pub fn check(&self) -> Result<(), KeystoreError> {
+        // cannot overflow for any `log_n` below 64
+        let memory = (128 * u128::from(self.r)) << self.log_n.min(64);
+        if self.log_n >= 64 || memory > MAX_KDF_MEMORY || self.p > MAX_KDF_PARALLELISM {
+            return Err(KeystoreError::KdfTooCostly(*self));
+        }
+        Ok(())
+    }
+}
+
+impl Default for KdfParams {
+    /// the interactive login parameters of the scrypt paper
+    fn default() -> Self {
+        KdfParams {
+            log_n: 15,
+            r: 8,
+            p: 1,
+        }
+    }
+}
+
+/// Private key encrypted with AES-256-GCM under a key derived from a
+/// passphrase with scrypt. The address is kept in clear, authenticated with
+/// the ciphertext, to tell keystores apart without the passphrase.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keystore {
+    address: PublicKey,
+    kdf: KdfParams,
+    salt: [u8; 16],
+    nonce: [u8; 12],
+    ciphertext: Vec<u8>,
+}
+
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    kdf: &KdfParams,
+) -> Result<Zeroizing<[u8; 32]>, KeystoreError> {
+    kdf.check()?;
+    let params = scrypt::Params::new(kdf.log_n, kdf.r, kdf.p, 32)
+        .map_err(|_| KeystoreError::InvalidKdfParams)?;
+    let mut key = Zeroizing::new([0; 32]);
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, key.as_mut())
+        .map_err(|_| KeystoreError::InvalidKdfParams)?;
+    Ok(key)
+}
+
+impl Keystore {
+    pub fn encrypt(private_key: &PrivateKey, passphrase: &str) -> Result<Keystore, KeystoreError> {
+        Keystore::encrypt_with(private_key, passphrase, KdfParams::default())
+    }
+
+    /// `encrypt` with a chosen scrypt cost, lower ones only suit tests
+    pub fn encrypt_with(
+        private_key: &PrivateKey,
+        passphrase: &str,
+        kdf: KdfParams,
+    ) -> Result<Keystore, KeystoreError> {
+        let address = PublicKey::try_from(private_key)?;
+        let mut salt = [0; 16];
+        let mut nonce = [0; 12];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+        let key = derive_key(passphrase, &salt, &kdf)?;
+        let cipher =
+            Aes256Gcm::new_from_slice(key.as_ref()).map_err(|_| KeystoreError::Encryption)?;
+        let payload = Payload {
+            msg: private_key.expose_secret(),
+            aad: address.as_ref(),
+        };
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| KeystoreError::Encryption)?;
+        Ok(Keystore {
+            address,
+            kdf,
+            salt,
+            nonce,
+            ciphertext,
+        })
+    }
+
+    pub fn decrypt(&self, passphrase: &str) -> Result<PrivateKey, KeystoreError> {
+        let key = derive_key(passphrase, &self.salt, &self.kdf)?;
+        let cipher =
+            Aes256Gcm::new_from_slice(key.as_ref()).map_err(|_| KeystoreError::Encryption)?;
+        let payload = Payload {
+            msg: &self.ciphertext,
+            aad: self.address.as_ref(),
+        };
+        let secret = Zeroizing::new(
+            cipher
+                .decrypt(Nonce::from_slice(&self.nonce), payload)
+                .map_err(|_| KeystoreError::WrongPassphrase)?,
+        );
+        Ok(PrivateKey::try_from(secret.as_slice())?)
+    }
+
+    /// decrypt into a signer, as taken by `Wallet::with_signer` or `sweep`
+    pub fn signer(&self, passphrase: &str) -> Result<LocalSigner, KeystoreError> {
+        Ok(LocalSigner::new(&self.decrypt(passphrase)?)?)
+    }
+
+    pub fn address(&self) -> PublicKey {
+        self.address
+    }
+
+    /// magic, version and the encoded keystore
+    pub fn to_bytes(&self) -> Result<Vec<u8>, KeystoreError> {
+        let body = encoding::encode(self)?;
+        Ok([KEYSTORE_MAGIC.as_ref(), &[KEYSTORE_VERSION], &body].concat())
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Keystore, KeystoreError> {
+        let header_len = KEYSTORE_MAGIC.len() + 1;
+        if data.len() < header_len || &data[..4] != KEYSTORE_MAGIC {
+            return Err(KeystoreError::NotAKeystore);
+        }
+        if data[4] != KEYSTORE_VERSION {
+            return Err(KeystoreError::UnsupportedVersion(data[4]));
+        }
+        Ok(encoding::decode(&data[header_len..])?)
+    }
+
+    /// write `to_bytes` to `path` atomically
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), KeystoreError> {
+        let data = self.to_bytes()?;
+        Ok(write_atomically(path.as_ref(), &data)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Keystore, KeystoreError> {
+        let data = fs::read(path)?;
+        Keystore::from_bytes(&data)
+    }
+}
+
+/// new 24 words BIP-39 mnemonic from the OS randomness
+pub fn generate_mnemonic() -> Result<String, KeystoreError> {
+    let mut entropy = Zeroizing::new([0; 32]);
+    OsRng.fill_bytes(entropy.as_mut());
+    let mnemonic = Mnemonic::from_entropy(entropy.as_ref())?;
+    Ok(mnemonic.to_string())
+}
+
+/// Keypair of a BIP-39 mnemonic: the BIP-32 master key of the seed of
+/// `phrase` and `passphrase`, without further derivation path
+pub fn keypair_from_mnemonic(
+    phrase: &str,
+    passphrase: &str,
+) -> Result<(PrivateKey, PublicKey), KeystoreError> {
+    let mnemonic = Mnemonic::parse(phrase)?;
+    let seed = Zeroizing::new(mnemonic.to_seed(passphrase));
+    let mut engine = HmacEngine::<sha512::Hash>::new(BIP32_SEED_KEY);
+    engine.input(seed.as_ref());
+    let master = Zeroizing::new(Hmac::<sha512::Hash>::from_engine(engine).to_byte_array());
+    let private_key = PrivateKey::try_from(&master[..32])?;
+    let public_key = PublicKey::try_from(&private_key)?;
+    Ok((private_key, public_key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keypair;
+    use crate::signer::Signer;
+
+    const TEST_KDF: KdfParams = KdfParams {
+        log_n: 4,
+        r: 8,
+        p: 1,
+    };
+
+    #[test]
+    fn test_keystore_round_trip() {
+        let (private_key, public_key) = generate_keypair();
+        let keystore = Keystore::encrypt_with(&private_key, "hunter2", TEST_KDF).unwrap();
+        assert_eq!(keystore.address(), public_key);
+        assert!(matches!(
+            keystore.decrypt("hunter3"),
+            Err(KeystoreError::WrongPassphrase)
+        ));
+
+        let path =
+            std::env::temp_dir().join(format!("core-ledger-keystore-{}", std::process::id()));
+        keystore.save(&path).unwrap();
+        let loaded = Keystore::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, keystore);
+        assert_eq!(loaded.decrypt("hunter2").unwrap(), private_key);
+        assert_eq!(loaded.signer("hunter2").unwrap().public_key(), public_key);
+
+        let mut tampered = keystore.clone();
+        tampered.address = generate_keypair().1;
+        assert!(tampered.decrypt("hunter2").is_err());
+        // a crafted file must not make scrypt allocate terabytes
+        let mut costly = keystore.clone();
+        costly.kdf.log_n = 40;
+        assert!(matches!(
+            costly.decrypt("hunter2"),
+            Err(KeystoreError::KdfTooCostly(_))
+        ));
+        assert!(matches!(
+            Keystore::from_bytes(b"CLBK\x01"),
+            Err(KeystoreError::NotAKeystore)
+        ));
+    }
+
+    #[test]
+    fn test_keypair_from_mnemonic() {
+        // BIP-32 master key of the BIP-39 test vector seed
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon \
+                      abandon abandon abandon about";
+        let (private_key, _) = keypair_from_mnemonic(phrase, "TREZOR").unwrap();
+        assert_eq!(
+            hex::encode(private_key.expose_secret()),
+            "cbedc75b0d6412c85c79bc13875112ef912fd1e756631b5a00330866f22ff184"
+        );
+        assert!(keypair_from_mnemonic("abandon abandon", "").is_err());
+
+        let phrase = generate_mnemonic().unwrap();
+        assert_eq!(phrase.split(' ').count(), 24);
+        assert_eq!(
+            keypair_from_mnemonic(&phrase, "").unwrap(),
+            keypair_from_mnemonic(&phrase, "").unwrap()
+        );
+    }
+}
@@ -1,13 +1,95 @@
 mod account;
+mod address;
+mod backup;
+#[cfg(any(test, feature = "bench"))]
+mod bench;
 mod block;
 mod blockchain;
+mod clock;
+mod commitment;
+pub mod compat;
+mod consensus;
 mod crypto;
+mod difficulty;
+mod encoding;
+mod error;
+#[cfg(any(test, feature = "fault-injection"))]
+mod fault;
+#[cfg(any(test, feature = "test-utils"))]
+mod fixture;
+#[cfg(any(test, feature = "fuzzing"))]
+mod fuzzing;
+mod grant;
+mod hardware;
+mod invoice;
+#[cfg(feature = "keystore")]
+mod keystore;
+mod light;
+mod memory;
+mod mempool;
 mod merkle;
+mod miner;
+mod mmr;
+mod notifier;
+mod ownership;
+mod policy;
+mod producer;
+mod replay;
+mod scoring;
+mod script;
+mod signer;
+mod state;
+mod storage;
 mod transaction;
+mod typed_data;
+mod ur;
+mod validation;
+mod view;
+mod wallet;
 
 pub use account::*;
+pub use address::*;
+pub use backup::*;
+#[cfg(any(test, feature = "bench"))]
+pub use bench::*;
 pub use block::*;
 pub use blockchain::*;
+pub use clock::*;
+pub use commitment::*;
+pub use consensus::*;
 pub use crypto::*;
+pub use difficulty::*;
+pub use error::*;
+#[cfg(any(test, feature = "fault-injection"))]
+pub use fault::*;
+#[cfg(any(test, feature = "test-utils"))]
+pub use fixture::*;
+#[cfg(any(test, feature = "fuzzing"))]
+pub use fuzzing::*;
+pub use grant::*;
+pub use hardware::*;
+pub use invoice::*;
+#[cfg(feature = "keystore")]
+pub use keystore::*;
+pub use light::*;
+pub use memory::*;
+pub use mempool::*;
 pub use merkle::*;
+pub use miner::*;
+pub use mmr::*;
+pub use notifier::*;
+pub use ownership::*;
+pub use policy::*;
+pub use producer::*;
+pub use replay::*;
+pub use scoring::*;
+pub use script::*;
+pub use signer::*;
+pub use state::*;
+pub use storage::*;
 pub use transaction::*;
+pub use typed_data::*;
+pub use ur::*;
+pub use validation::*;
+pub use view::*;
+pub use wallet::*;
@@ -0,0 +1,275 @@
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
+use crate::block::BlockHeader;
+use crate::crypto::Hash;
+use crate::difficulty::{meets_difficulty, next_difficulty, work, DifficultyConfig};
+use crate::error::LedgerError;
+use crate::merkle::MerkleProof;
+use crate::mmr::Mmr;
+use crate::transaction::Transaction;
+
+/// Chain of block headers without transactions or accounts, checked with
+/// the same header rules as `Blockchain`. Payments are verified with the
+/// merkle proofs full nodes hand out.
+#[derive(Debug, Clone)]
+pub struct HeaderChain {
+    headers: Vec<BlockHeader>,
+    /// MMR over the hashes of every header, as committed by the next one
+    history: Mmr,
+    proof_of_work: Option<DifficultyConfig>,
+    /// work of the chain up to every header, as summed by `Blockchain`
+    chain_work: Vec<u128>,
+}
+
+impl HeaderChain {
+    /// start from the genesis header, trusted as is
+    pub fn new(genesis: BlockHeader) -> Result<HeaderChain, LedgerError> {
+        if genesis.index() != 0
+            || genesis.previous_hash() != Hash::default()
+            || genesis.history_root() != Hash::default()
+        {
+            return Err(LedgerError::InvalidGenesis);
+        }
+        genesis.verify()?;
+        let mut history = Mmr::new();
+        history.push(&genesis.hash());
+        Ok(HeaderChain {
+            headers: vec![genesis],
+            history,
+            proof_of_work: None,
+            chain_work: vec![1],
+        })
+    }
+
+    /// require the next headers to meet the retargeted difficulty, as set
+    /// in the `ConsensusConfig` of the full nodes
+    pub fn set_proof_of_work(&mut self, config: Option<DifficultyConfig>) {
+        self.proof_of_work = config;
+    }
+
+    /// append the header following the tip
+    pub fn push(&mut self, header: BlockHeader) -> Result<(), LedgerError> {
+        header.verify()?;
+        let tip = self.tip();
+        if header.index() != tip.index() + 1 {
+            return Err(LedgerError::UnexpectedIndex(header.index()));
+        }
+        if header.previous_hash() != tip.hash() {
+            return Err(LedgerError::UnlinkedBlock(header.index()));
+        }
+        if header.timestamp() < tip.timestamp() {
+            return Err(LedgerError::OlderThanParent(header.index()));
+        }
+        if header.history_root() != self.history.root() {
+            return Err(LedgerError::InvalidHistoryRoot(header.index()));
+        }
+        if let Some(config) = &self.proof_of_work {
+            let start = self.headers.len().saturating_sub(config.window + 1);
+            let work_history: Vec<(u64, u64)> = self.headers[start..]
+                .iter()
+                .map(|h| (h.timestamp(), h.difficulty()))
+                .collect();
+            let expected = next_difficulty(config, &work_history);
+            if header.difficulty() != expected {
+                return Err(LedgerError::InvalidDifficulty {
+                    index: header.index(),
+                    expected,
+                    got: header.difficulty(),
+                });
+            }
+            if !meets_difficulty(header.hash().as_ref(), expected) {
+                return Err(LedgerError::InsufficientWork(header.index()));
+            }
+        }
+        let header_work = match self.proof_of_work {
+            Some(_) => work(header.difficulty()),
+            None => 1,
+        };
+        self.chain_work
+            .push(self.chain_work().saturating_add(header_work));
+        self.history.push(&header.hash());
+        self.headers.push(header);
+        Ok(())
+    }
+
+    pub fn tip(&self) -> &BlockHeader {
+        &self.headers[self.headers.len() - 1]
+    }
+
+    pub fn header(&self, index: u64) -> Option<&BlockHeader> {
+        self.headers.get(index as usize)
+    }
+
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    /// Work of the chain up to and including the tip. Of two valid chains
+    /// from the same genesis, the one with more work is the one full nodes
+    /// follow.
+    pub fn chain_work(&self) -> u128 {
+        self.chain_work.last().copied().unwrap_or(0)
+    }
+
+    /// work of the chain up to and including the header at `index`
+    pub fn work_at(&self, index: u64) -> Option<u128> {
+        self.chain_work.get(index as usize).copied()
+    }
+
+    /// never true, the genesis header is always there
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    /// Check that `tx` is part of the block of `header`, itself part of
+    /// this chain, with a proof from `Block::transaction_proof`
+    pub fn verify_transaction(
+        &self,
+        tx: &Transaction,
+        proof: &MerkleProof,
+        header: &BlockHeader,
+    ) -> Result<(), LedgerError> {
+        if self.header(header.index()) != Some(header) {
+            return Err(LedgerError::UnknownHeader(header.index()));
+        }
+        proof
+            .verify(tx, &header.transactions_root(), header.transaction_count())
+            .map_err(LedgerError::InvalidProof)
+    }
+
+    /// append `headers` in order, stopping at the first invalid one
+    pub fn extend(
+        &mut self,
+        headers: impl IntoIterator<Item = BlockHeader>,
+    ) -> Result<(), LedgerError> {
+        for header in headers {
+            self.push(header)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::consensus::ConsensusConfig;
+    use crate::crypto::generate_keypair_from_secret;
+    use crate::error::BlockError;
+    use crate::fixture::test_chain;
+    use crate::miner::{CancelToken, Miner};
+
+    #[test]
+    fn test_verify_payment_from_headers() {
+        let (private_key, public_key) = generate_keypair_from_secret(b"light");
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let genesis =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 1000, 0, &private_key)
+                .unwrap();
+        let mut blockchain = Blockchain::new(genesis, 0).unwrap();
+        let payment =
+            Transaction::new_and_sign(&public_key, &other.address(), 10, 0, &private_key).unwrap();
+        blockchain.add_transaction(payment.clone()).unwrap();
+        blockchain.finalize_and_mint_pending_block().unwrap();
+        blockchain.finalize_and_mint_pending_block().unwrap();
+        let blocks = blockchain.blocks().unwrap();
+
+        let mut headers = HeaderChain::new(blocks[0].header()).unwrap();
+        let mut tampered = blocks[2].clone();
+        tampered.corrupt();
+        headers.push(blocks[1].header()).unwrap();
+        assert_eq!(
+            headers.push(tampered.header()),
+            Err(LedgerError::Block(BlockError::InvalidHash))
+        );
+        assert_eq!(
+            headers.push(blocks[1].header()),
+            Err(LedgerError::UnexpectedIndex(1))
+        );
+        headers
+            .extend(blocks[2..].iter().map(|b| b.header()))
+            .unwrap();
+        assert_eq!(headers.tip().hash(), blocks[2].hash());
+        assert_eq!(headers.chain_work(), blockchain.chain_work());
+        assert_eq!(headers.work_at(1), blockchain.work_at(1));
+
+        let proof = blocks[1].transaction_proof(&payment.id()).unwrap();
+        headers
+            .verify_transaction(&payment, &proof, &blocks[1].header())
+            .unwrap();
+        assert!(headers
+            .verify_transaction(&payment, &proof, &blocks[2].header())
+            .is_err());
+        let other_tx =
+            Transaction::new_and_sign(&public_key, &other.address(), 11, 0, &private_key).unwrap();
+        assert!(headers
+            .verify_transaction(&other_tx, &proof, &blocks[1].header())
+            .is_err());
+    }
+
+    #[test]
+    fn test_headers_proof_of_work() {
+        let config = DifficultyConfig {
+            initial_difficulty: 1 << 8,
+            ..DifficultyConfig::default()
+        };
+        let (_, _, mut producer) = test_chain();
+        let (_, _, rival) = test_chain();
+        producer.set_consensus(ConsensusConfig {
+            proof_of_work: Some(config),
+            ..ConsensusConfig::default()
+        });
+        producer.finalize_and_mint_pending_block().unwrap();
+        let blocks = producer.blocks().unwrap();
+        let mut headers = HeaderChain::new(blocks[0].header()).unwrap();
+        headers.set_proof_of_work(Some(config));
+
+        // a header at the retargeted difficulty whose nonce misses it
+        let mut unmined = rival.pending_block().clone();
+        unmined.set_work(1 << 8, 0);
+        let nonce = (0..)
+            .find(|nonce| !meets_difficulty(unmined.hash_with_nonce(1, *nonce).as_ref(), 1 << 8))
+            .unwrap();
+        unmined.set_work(1 << 8, nonce);
+        assert_eq!(
+            headers.push(unmined.clone().build_at(1).header()),
+            Err(LedgerError::InsufficientWork(1))
+        );
+        unmined.set_work(1 << 4, 0);
+        assert_eq!(
+            headers.push(unmined.build_at(1).header()),
+            Err(LedgerError::InvalidDifficulty {
+                index: 1,
+                expected: 1 << 8,
+                got: 1 << 4
+            })
+        );
+        headers.push(blocks[1].header()).unwrap();
+        assert_eq!(headers.chain_work(), 1 + (1 << 8));
+
+        // a competing header at the same height does not replace the tip,
+        // the chain it starts is followed separately and compared by work
+        let mut competing = rival.pending_block().clone();
+        Miner::with_available_parallelism().mine_block(
+            &mut competing,
+            1 << 8,
+            1,
+            &CancelToken::new(),
+        );
+        let competing = competing.build_at(1).header();
+        assert_eq!(
+            headers.push(competing.clone()),
+            Err(LedgerError::UnexpectedIndex(1))
+        );
+        assert_eq!(headers.tip(), &blocks[1].header());
+        let mut fork = HeaderChain::new(blocks[0].header()).unwrap();
+        fork.set_proof_of_work(Some(config));
+        fork.push(competing).unwrap();
+        assert_eq!(fork.chain_work(), headers.chain_work());
+        producer.finalize_and_mint_pending_block().unwrap();
+        headers
+            .push(producer.last_block().unwrap().header())
+            .unwrap();
+        assert!(headers.chain_work() > fork.chain_work());
+    }
+}
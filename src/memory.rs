@@ -0,0 +1,109 @@
+//! Approximate heap accounting. Sizes come from capacities and type sizes,
+//! not from the allocator, they are meant as metrics and for ceilings.
+
+use std::collections::{BTreeMap, HashMap};
+use std::mem::{size_of, size_of_val};
+
+use crate::block::{Block, BlockBuilder};
+use crate::blockchain::Blockchain;
+use crate::policy::{PolicyError, TransactionPolicy};
+use crate::transaction::Transaction;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryUsage {
+    pub accounts: usize,
+    pub blocks: usize,
+    /// transaction, nonce and burn indexes
+    pub indexes: usize,
+    pub pending_block: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.accounts + self.blocks + self.indexes + self.pending_block
+    }
+}
+
+/// one control byte per bucket on top of the entries
+pub(crate) fn map_bytes<K, V>(map: &HashMap<K, V>) -> usize {
+    map.capacity() * (size_of::<K>() + size_of::<V>() + 1)
+}
+
+/// entries only, nodes are mostly full
+pub(crate) fn btree_bytes<K, V>(map: &BTreeMap<K, V>) -> usize {
+    map.len() * (size_of::<K>() + size_of::<V>())
+}
+
+fn transactions_bytes(transactions: &[Transaction]) -> usize {
+    size_of_val(transactions)
+}
+
+pub(crate) fn block_bytes(block: &Block) -> usize {
+    size_of::<Block>()
+        + block.extra_data().len()
+        + transactions_bytes(block.transactions())
+        + size_of_val(block.uncles())
+}
+
+pub(crate) fn pending_block_bytes(builder: &BlockBuilder) -> usize {
+    size_of::<BlockBuilder>()
+        + builder.extra_data().len()
+        + transactions_bytes(builder.transactions())
+}
+
+/// Refuses new transactions once the pending block holds `max_pending_bytes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingMemoryLimit {
+    pub max_pending_bytes: usize,
+}
+
+impl TransactionPolicy for PendingMemoryLimit {
+    fn check(&self, _tx: &Transaction, blockchain: &Blockchain) -> Result<(), PolicyError> {
+        let usage = pending_block_bytes(blockchain.pending_block()) + size_of::<Transaction>();
+        if usage > self.max_pending_bytes {
+            return Err(PolicyError::MemoryLimit {
+                usage,
+                limit: self.max_pending_bytes,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::test_chain;
+    use crate::policy::AdmissionError;
+
+    #[test]
+    fn test_pending_memory_limit() {
+        let (private_key, public_key, mut blockchain) = test_chain();
+        let before = blockchain.memory_usage();
+        assert!(before.accounts > 0 && before.blocks > 0);
+
+        blockchain.add_policy(Box::new(PendingMemoryLimit {
+            max_pending_bytes: size_of::<BlockBuilder>() + 4 * size_of::<Transaction>(),
+        }));
+        let mut nonce = 0;
+        let result = loop {
+            let tx = Transaction::new_and_sign(
+                &public_key,
+                &public_key.address(),
+                1,
+                nonce,
+                &private_key,
+            )
+            .unwrap();
+            if let Err(e) = blockchain.admit_transaction(tx) {
+                break e;
+            }
+            nonce += 1;
+        };
+        assert!(matches!(
+            result,
+            AdmissionError::Policy(PolicyError::MemoryLimit { .. })
+        ));
+        assert!(nonce >= 1 && blockchain.memory_usage().pending_block > before.pending_block);
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::crypto::PublicKey;
+use crate::transaction::Transaction;
+
+/// Bounds of a `Mempool`, a transaction beyond them is rejected unless it
+/// scores higher than one it can evict
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MempoolConfig {
+    pub max_transactions: usize,
+    pub max_per_sender: usize,
+    /// how far ahead of its account nonce a sender may queue
+    pub max_nonce_gap: u64,
+    /// fee increase in percent, at least 1, of a transaction replacing a
+    /// queued one with the same nonce
+    pub min_fee_bump_percent: u64,
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        MempoolConfig {
+            max_transactions: 10_000,
+            max_per_sender: 64,
+            max_nonce_gap: 64,
+            min_fee_bump_percent: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MempoolError {
+    NonceGapTooLarge {
+        gap: u64,
+        max: u64,
+    },
+    SenderQueueFull {
+        max: usize,
+    },
+    /// the mempool is full of transactions scoring at least as high
+    Full,
+    ReplacementUnderpriced {
+        fee: u64,
+        required: u64,
+    },
+}
+
+impl fmt::Display for MempoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MempoolError::NonceGapTooLarge { gap, max } => {
+                write!(f, "Nonce {} ahead of the account, maximum is {}", gap, max)
+            }
+            MempoolError::SenderQueueFull { max } => {
+                write!(f, "Sender already has {} queued transactions", max)
+            }
+            MempoolError::Full => write!(f, "Mempool is full"),
+            MempoolError::ReplacementUnderpriced { fee, required } => write!(
+                f,
+                "Replacement fee {} is below the required {}",
+                fee, required
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MempoolError {}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    tx: Transaction,
+    score: u64,
+    /// arrival rank, kept by a replacement
+    sequence: u64,
+}
+
+/// Transactions waiting to be executed, queued per sender by nonce. A
+/// sender may queue nonces ahead of its account, they become ready once the
+/// gap is filled.
+#[derive(Debug, Clone, Default)]
+pub struct Mempool {
+    config: MempoolConfig,
+    senders: HashMap<PublicKey, BTreeMap<u64, Entry>>,
+    len: usize,
+    next_sequence: u64,
+}
+
+impl Mempool {
+    pub fn new() -> Mempool {
+        Mempool::default()
+    }
+
+    pub fn with_config(config: MempoolConfig) -> Mempool {
+        Mempool {
+            config,
+            ..Mempool::default()
+        }
+    }
+
+    pub fn config(&self) -> &MempoolConfig {
+        &self.config
+    }
+
+    /// bounds of the next insertions, the queued transactions stay
+    pub fn set_config(&mut self, config: MempoolConfig) {
+        self.config = config;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Queue `tx` with its `score`, `account_nonce` being the next nonce of
+    /// its sender. Return the transaction with the same sender and nonce it
+    /// replaces, or the one it evicted when the mempool is full: the lowest
+    /// score among the last queued nonce of every other sender.
+    pub fn insert(
+        &mut self,
+        tx: Transaction,
+        score: u64,
+        account_nonce: u64,
+    ) -> Result<Option<Transaction>, MempoolError> {
+        let gap = tx.nonce().saturating_sub(account_nonce);
+        if gap > self.config.max_nonce_gap {
+            return Err(MempoolError::NonceGapTooLarge {
+                gap,
+                max: self.config.max_nonce_gap,
+            });
+        }
+        let queue = self.senders.get(&tx.from());
+        if let Some(queued) = queue.and_then(|queue| queue.get(&tx.nonce())) {
+            let fee = queued.tx.fee();
+            let bump = (fee as u128 * self.config.min_fee_bump_percent as u128 / 100).max(1);
+            let required = (fee as u128 + bump).min(u64::MAX as u128) as u64;
+            if tx.fee() < required {
+                return Err(MempoolError::ReplacementUnderpriced {
+                    fee: tx.fee(),
+                    required,
+                });
+            }
+            let sequence = queued.sequence;
+            let replaced = self.senders.entry(tx.from()).or_default().insert(
+                tx.nonce(),
+                Entry {
+                    tx,
+                    score,
+                    sequence,
+                },
+            );
+            return Ok(replaced.map(|entry| entry.tx));
+        }
+        if queue.map_or(0, |queue| queue.len()) >= self.config.max_per_sender {
+            return Err(MempoolError::SenderQueueFull {
+                max: self.config.max_per_sender,
+            });
+        }
+        let evicted = if self.len >= self.config.max_transactions {
+            Some(self.evict(&tx.from(), score)?)
+        } else {
+            None
+        };
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.senders.entry(tx.from()).or_default().insert(
+            tx.nonce(),
+            Entry {
+                tx,
+                score,
+                sequence,
+            },
+        );
+        self.len += 1;
+        Ok(evicted)
+    }
+
+    /// Remove the lowest scored transaction below `score` that leaves no
+    /// nonce gap, the newest first on a tie. The sender making room is
+    /// spared.
+    fn evict(&mut self, sender: &PublicKey, score: u64) -> Result<Transaction, MempoolError> {
+        let lowest = self
+            .senders
+            .iter()
+            .filter(|(address, _)| *address != sender)
+            .filter_map(|(_, queue)| queue.values().next_back())
+            .filter(|entry| entry.score < score)
+            .min_by_key(|entry| (entry.score, std::cmp::Reverse(entry.sequence)))
+            .map(|entry| (entry.tx.from(), entry.tx.nonce()));
+        let (address, nonce) = lowest.ok_or(MempoolError::Full)?;
+        self.remove(&address, nonce).ok_or(MempoolError::Full)
+    }
+
+    pub fn get(&self, sender: &PublicKey, nonce: u64) -> Option<&Transaction> {
+        self.senders.get(sender)?.get(&nonce).map(|entry| &entry.tx)
+    }
+
+    pub fn remove(&mut self, sender: &PublicKey, nonce: u64) -> Option<Transaction> {
+        let queue = self.senders.get_mut(sender)?;
+        let entry = queue.remove(&nonce)?;
+        if queue.is_empty() {
+            self.senders.remove(sender);
+        }
+        self.len -= 1;
+        Some(entry.tx)
+    }
+
+    /// queued transactions of `sender` in nonce order
+    pub fn transactions_from(&self, sender: &PublicKey) -> impl Iterator<Item = &Transaction> {
+        self.senders
+            .get(sender)
+            .into_iter()
+            .flat_map(|queue| queue.values().map(|entry| &entry.tx))
+    }
+
+    /// The transactions `nonce_of` makes executable: for every sender the
+    /// run of consecutive nonces starting at its account nonce. They come in
+    /// arrival order, a replacement taking the place of the transaction it
+    /// replaced.
+    pub fn ready(&self, nonce_of: impl Fn(&PublicKey) -> u64) -> Vec<&Transaction> {
+        let mut ready: Vec<&Entry> = vec![];
+        for (sender, queue) in &self.senders {
+            let mut nonce = nonce_of(sender);
+            while let Some(entry) = queue.get(&nonce) {
+                ready.push(entry);
+                nonce += 1;
+            }
+        }
+        ready.sort_by_key(|entry| entry.sequence);
+        ready.into_iter().map(|entry| &entry.tx).collect()
+    }
+
+    /// `ready` removing the transactions from the mempool
+    pub fn take_ready(&mut self, nonce_of: impl Fn(&PublicKey) -> u64) -> Vec<Transaction> {
+        let ready: Vec<(PublicKey, u64)> = self
+            .ready(nonce_of)
+            .into_iter()
+            .map(|tx| (tx.from(), tx.nonce()))
+            .collect();
+        ready
+            .into_iter()
+            .filter_map(|(sender, nonce)| self.remove(&sender, nonce))
+            .collect()
+    }
+
+    /// number of transactions `take_ready` would return
+    pub fn count_ready(&self, nonce_of: impl Fn(&PublicKey) -> u64) -> usize {
+        self.senders
+            .iter()
+            .map(|(sender, queue)| {
+                let start = nonce_of(sender);
+                queue
+                    .keys()
+                    .skip_while(|nonce| **nonce < start)
+                    .zip(start..)
+                    .take_while(|(nonce, expected)| **nonce == *expected)
+                    .count()
+            })
+            .sum()
+    }
+
+    /// drop the transactions whose nonce `nonce_of` reports as consumed
+    pub fn prune(&mut self, nonce_of: impl Fn(&PublicKey) -> u64) {
+        let mut pruned = 0;
+        for (sender, queue) in &mut self.senders {
+            let pending = queue.split_off(&nonce_of(sender));
+            pruned += queue.len();
+            *queue = pending;
+        }
+        self.senders.retain(|_, queue| !queue.is_empty());
+        self.len -= pruned;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keypair_from_secret;
+
+    fn with_fee(mut tx: Transaction, fee: u64) -> Transaction {
+        tx.set_fee(fee);
+        tx
+    }
+
+    #[test]
+    fn test_queue_by_nonce() {
+        let (_, alice) = generate_keypair_from_secret(b"alice");
+        let (_, bob) = generate_keypair_from_secret(b"bob");
+        let mut mempool = Mempool::new();
+        for nonce in [3, 1, 0] {
+            let tx = with_fee(Transaction::new(&alice, &bob.address(), 1, nonce), 10);
+            assert_eq!(mempool.insert(tx, 0, 0), Ok(None));
+        }
+        mempool
+            .insert(Transaction::new(&bob, &alice.address(), 1, 5), 0, 4)
+            .unwrap();
+        assert_eq!(
+            mempool.insert(
+                with_fee(Transaction::new(&alice, &alice.address(), 2, 1), 10),
+                0,
+                0
+            ),
+            Err(MempoolError::ReplacementUnderpriced {
+                fee: 10,
+                required: 11
+            })
+        );
+        let replacement = with_fee(Transaction::new(&alice, &alice.address(), 2, 1), 11);
+        let replaced = mempool.insert(replacement.clone(), 0, 0);
+        assert_eq!(
+            replaced,
+            Ok(Some(with_fee(
+                Transaction::new(&alice, &bob.address(), 1, 1),
+                10
+            )))
+        );
+        assert_eq!(mempool.len(), 4);
+
+        // arrival order, the replacement keeps the place of nonce 1
+        let (_, carol) = generate_keypair_from_secret(b"carol");
+        mempool
+            .insert(Transaction::new(&carol, &alice.address(), 1, 0), 0, 0)
+            .unwrap();
+        let nonces = |address: &PublicKey| if *address == bob { 4 } else { 0 };
+        let ready: Vec<(PublicKey, u64)> = mempool
+            .ready(nonces)
+            .iter()
+            .map(|tx| (tx.from(), tx.nonce()))
+            .collect();
+        assert_eq!(ready, vec![(alice, 1), (alice, 0), (carol, 0)]);
+        assert_eq!(mempool.count_ready(nonces), 3);
+        let ready = mempool.take_ready(nonces);
+        assert_eq!(ready[0], replacement);
+        assert_eq!(mempool.len(), 2);
+        assert!(mempool.get(&alice, 3).is_some());
+
+        mempool.prune(|_| 4);
+        assert_eq!(mempool.len(), 1);
+        assert_eq!(mempool.transactions_from(&bob).count(), 1);
+        assert_eq!(mempool.remove(&bob, 5).map(|tx| tx.nonce()), Some(5));
+        assert!(mempool.is_empty());
+    }
+
+    #[test]
+    fn test_mempool_bounds() {
+        let (_, alice) = generate_keypair_from_secret(b"alice");
+        let (_, bob) = generate_keypair_from_secret(b"bob");
+        let (_, carol) = generate_keypair_from_secret(b"carol");
+        let mut mempool = Mempool::with_config(MempoolConfig {
+            max_transactions: 3,
+            max_per_sender: 2,
+            max_nonce_gap: 4,
+            min_fee_bump_percent: 10,
+        });
+        assert_eq!(
+            mempool.insert(Transaction::new(&alice, &bob.address(), 1, 5), 0, 0),
+            Err(MempoolError::NonceGapTooLarge { gap: 5, max: 4 })
+        );
+        for nonce in 0..2 {
+            mempool
+                .insert(Transaction::new(&alice, &bob.address(), 1, nonce), 5, 0)
+                .unwrap();
+        }
+        assert_eq!(
+            mempool.insert(Transaction::new(&alice, &bob.address(), 1, 2), 5, 0),
+            Err(MempoolError::SenderQueueFull { max: 2 })
+        );
+        mempool
+            .insert(Transaction::new(&bob, &alice.address(), 1, 0), 1, 0)
+            .unwrap();
+
+        // full: only a higher score gets in, evicting the lowest
+        assert_eq!(
+            mempool.insert(Transaction::new(&carol, &alice.address(), 1, 0), 1, 0),
+            Err(MempoolError::Full)
+        );
+        assert_eq!(
+            mempool.insert(Transaction::new(&carol, &alice.address(), 1, 0), 2, 0),
+            Ok(Some(Transaction::new(&bob, &alice.address(), 1, 0)))
+        );
+        assert_eq!(
+            mempool.insert(Transaction::new(&carol, &alice.address(), 1, 1), 9, 0),
+            Ok(Some(Transaction::new(&alice, &bob.address(), 1, 1)))
+        );
+        assert_eq!(mempool.len(), 3);
+        assert!(mempool.get(&alice, 0).is_some());
+    }
+}
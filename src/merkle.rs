@@ -1,25 +1,425 @@
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
+use crate::account::Account;
 use crate::crypto::Hash;
+use crate::error::ProofError;
+use crate::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::marker::PhantomData;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// prefixes keeping a leaf from ever hashing like an inner node
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// below this many nodes a level is cheaper to hash on the current thread
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 1024;
+
+pub trait Hasher {
+    /// hash of the concatenation of `parts`
+    fn hash(parts: &[&[u8]]) -> Hash;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sha256;
+
+impl Hasher for Sha256 {
+    fn hash(parts: &[&[u8]]) -> Hash {
+        Hash::from_parts(parts)
+    }
+}
+
+/// anything committed as a leaf of a merkle tree
+pub trait AsLeaf {
+    fn leaf_bytes(&self) -> Cow<'_, [u8]>;
+}
+
+impl AsLeaf for Hash {
+    fn leaf_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_ref())
+    }
+}
+
+impl AsLeaf for Transaction {
+    fn leaf_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(self.id().hash().as_ref().to_vec())
+    }
+}
+
+impl AsLeaf for Account {
+    fn leaf_bytes(&self) -> Cow<'_, [u8]> {
+        let balance = self.balance().to_be_bytes();
+        let nonce = self.nonce().to_be_bytes();
+        Cow::Owned([self.address().as_ref(), &balance, &nonce].concat())
+    }
+}
+
+/// Binary merkle tree over the leaf hashes. The last node of an odd level is
+/// promoted to the level above as is, duplicating it would give `[a, b, c]`
+/// and `[a, b, c, c]` the same root. An empty tree has the default hash as
+/// root.
+pub struct MerkleTree<L: AsLeaf, H: Hasher = Sha256> {
+    leaves: Vec<Hash>,
+    _marker: PhantomData<(L, H)>,
+}
+
+impl<L: AsLeaf, H: Hasher> MerkleTree<L, H> {
+    pub fn new(leaves: &[L]) -> MerkleTree<L, H> {
+        MerkleTree {
+            leaves: leaves.iter().map(Self::leaf_hash).collect(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn leaf_hash(leaf: &L) -> Hash {
+        H::hash(&[&[LEAF_PREFIX], &leaf.leaf_bytes()])
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    pub fn root(&self) -> Hash {
+        Self::reduce(self.leaves.clone())
+    }
+
+    /// root without copying the leaves
+    pub fn into_root(self) -> Hash {
+        Self::reduce(self.leaves)
+    }
+
+    /// proof that leaf `index` is part of the tree, `None` when out of range
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.leaves.len() {
+            return None;
+        }
+        let mut siblings = vec![];
+        let mut level = self.leaves.clone();
+        let mut position = index;
+        while level.len() > 1 {
+            // a promoted node has no sibling at this level
+            if let Some(sibling) = level.get(position ^ 1) {
+                siblings.push(*sibling);
+            }
+            level = level.chunks(2).map(Self::combine_chunk).collect();
+            position /= 2;
+        }
+        Some(MerkleProof {
+            leaf_index: index as u64,
+            siblings,
+        })
+    }
+
+    /// each level is written over the previous one, no buffer is allocated
+    fn reduce(mut nodes: Vec<Hash>) -> Hash {
+        let mut len = nodes.len();
+        if len == 0 {
+            return Hash::default();
+        }
+        while len > 1 {
+            for i in 0..len.div_ceil(2) {
+                nodes[i] = if 2 * i + 1 < len {
+                    Self::combine(&nodes[2 * i], &nodes[2 * i + 1])
+                } else {
+                    nodes[2 * i]
+                };
+            }
+            len = len.div_ceil(2);
+        }
+        nodes[0]
+    }
+
+    fn combine(left: &Hash, right: &Hash) -> Hash {
+        H::hash(&[&[NODE_PREFIX], left.as_ref(), right.as_ref()])
+    }
+
+    /// parent of a pair of nodes, or the last node of an odd level promoted
+    fn combine_chunk(chunk: &[Hash]) -> Hash {
+        match chunk {
+            [left, right] => Self::combine(left, right),
+            _ => chunk[0],
+        }
+    }
+}
+
+/// Path from a leaf to the root of a `MerkleTree`. The shape of the path
+/// depends on the number of leaves, which the proof does not carry: the
+/// verifier takes it from the same trusted source as the root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub leaf_index: u64,
+    /// sibling of the node on the path at each level it has one, from the
+    /// leaves up
+    pub siblings: Vec<Hash>,
+}
+
+impl MerkleProof {
+    /// check `leaf` is leaf `leaf_index` of the tree of `leaf_count` leaves
+    /// with `root`
+    pub fn verify<L: AsLeaf>(
+        &self,
+        leaf: &L,
+        root: &Hash,
+        leaf_count: u64,
+    ) -> Result<(), ProofError> {
+        self.verify_with::<L, Sha256>(leaf, root, leaf_count)
+    }
+
+    /// verify against a tree built with hasher `H`
+    pub fn verify_with<L: AsLeaf, H: Hasher>(
+        &self,
+        leaf: &L,
+        root: &Hash,
+        leaf_count: u64,
+    ) -> Result<(), ProofError> {
+        if self.leaf_index >= leaf_count {
+            return Err(ProofError::IndexOutOfRange);
+        }
+        let mut siblings = self.siblings.iter();
+        let mut index = self.leaf_index;
+        let mut width = leaf_count;
+        let mut node = MerkleTree::<L, H>::leaf_hash(leaf);
+        while width > 1 {
+            // the last node of an odd level is promoted without a sibling
+            if index != width - 1 || width & 1 == 0 {
+                let sibling = siblings.next().ok_or(ProofError::InvalidLength)?;
+                node = if index & 1 == 0 {
+                    MerkleTree::<L, H>::combine(&node, sibling)
+                } else {
+                    MerkleTree::<L, H>::combine(sibling, &node)
+                };
+            }
+            index /= 2;
+            width = width.div_ceil(2);
+        }
+        if siblings.next().is_some() {
+            return Err(ProofError::InvalidLength);
+        }
+        if node != *root {
+            return Err(ProofError::InvalidRoot);
+        }
+        Ok(())
+    }
+}
+
+/// Same tree and root as `MerkleTree`, built one leaf at a time. Only the
+/// last node of each level changes on a push, so updating the root costs a
+/// hash per level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncrementalMerkleTree<L: AsLeaf, H: Hasher = Sha256> {
+    /// every level from the leaf hashes up, the last one holds the root
+    levels: Vec<Vec<Hash>>,
+    _marker: PhantomData<(L, H)>,
+}
+
+impl<L: AsLeaf, H: Hasher> IncrementalMerkleTree<L, H> {
+    pub fn new() -> IncrementalMerkleTree<L, H> {
+        IncrementalMerkleTree::with_capacity(0)
+    }
+
+    /// preallocate the leaf level for `capacity` leaves
+    pub fn with_capacity(capacity: usize) -> IncrementalMerkleTree<L, H> {
+        IncrementalMerkleTree {
+            levels: vec![Vec::with_capacity(capacity)],
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn push(&mut self, leaf: &L) {
+        self.levels[0].push(MerkleTree::<L, H>::leaf_hash(leaf));
+        let mut height = 0;
+        while self.levels[height].len() > 1 {
+            let level = &self.levels[height];
+            let parent = (level.len() - 1) / 2;
+            let node = MerkleTree::<L, H>::combine_chunk(&level[2 * parent..]);
+            if self.levels.len() == height + 1 {
+                self.levels.push(vec![]);
+            }
+            let above = &mut self.levels[height + 1];
+            if above.len() == parent {
+                above.push(node);
+            } else {
+                above[parent] = node;
+            }
+            height += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels[0].is_empty()
+    }
 
-pub fn root_hash(hashes: Vec<Hash>) -> Hash {
-    if hashes.is_empty() {
-        return Hash::default();
+    pub fn root(&self) -> Hash {
+        self.levels
+            .last()
+            .and_then(|level| level.first())
+            .copied()
+            .unwrap_or_default()
     }
-    let mut parent_hashes = vec![];
-    for chunk in hashes.chunks(2) {
-        let hash = if chunk.len() > 1 {
-            combine_hashes(&chunk[0], &chunk[1])
+}
+
+impl<L: AsLeaf, H: Hasher> Default for IncrementalMerkleTree<L, H> {
+    fn default() -> Self {
+        IncrementalMerkleTree::new()
+    }
+}
+
+/// Same tree and root as the sequential path, with leaf and level hashing
+/// spread over the rayon pool
+#[cfg(feature = "parallel")]
+impl<L: AsLeaf + Sync, H: Hasher> MerkleTree<L, H> {
+    pub fn new_parallel(leaves: &[L]) -> MerkleTree<L, H> {
+        let leaves = if leaves.len() < PARALLEL_THRESHOLD {
+            leaves.iter().map(Self::leaf_hash).collect()
         } else {
-            combine_hashes(&chunk[0], &chunk[0])
+            leaves.par_iter().map(Self::leaf_hash).collect()
         };
-        parent_hashes.push(hash);
+        MerkleTree {
+            leaves,
+            _marker: PhantomData,
+        }
     }
-    if parent_hashes.len() > 1 {
-        root_hash(parent_hashes)
-    } else {
-        parent_hashes[0].clone()
+
+    pub fn root_parallel(&self) -> Hash {
+        if self.leaves.is_empty() {
+            return Hash::default();
+        }
+        let mut level = self.leaves.clone();
+        while level.len() > 1 {
+            level = if level.len() < PARALLEL_THRESHOLD {
+                level.chunks(2).map(Self::combine_chunk).collect()
+            } else {
+                level.par_chunks(2).map(Self::combine_chunk).collect()
+            };
+        }
+        level[0]
     }
 }
 
-fn combine_hashes(left: &Hash, right: &Hash) -> Hash {
-    Hash::from([left.as_ref(), right.as_ref()].concat().as_ref())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leaves_are_domain_separated() {
+        let a = Hash::from(b"a".as_ref());
+        let b = Hash::from(b"b".as_ref());
+        let tree = MerkleTree::<Hash>::new(&[a, b]);
+        let inner = MerkleTree::<Hash>::combine(
+            &MerkleTree::<Hash>::leaf_hash(&a),
+            &MerkleTree::<Hash>::leaf_hash(&b),
+        );
+        assert_eq!(tree.root(), inner);
+
+        // an inner node presented as a leaf must not reproduce the root
+        assert_ne!(MerkleTree::<Hash>::new(&[inner]).root(), tree.root());
+        assert_eq!(MerkleTree::<Hash>::new(&[]).root(), Hash::default());
+    }
+
+    #[test]
+    fn test_odd_levels_promote_last_node() {
+        let leaves: Vec<Hash> = [b"a", b"b", b"c"]
+            .iter()
+            .map(|data| Hash::from(data.as_ref()))
+            .collect();
+        let hashes: Vec<Hash> = leaves.iter().map(MerkleTree::<Hash>::leaf_hash).collect();
+        let expected = MerkleTree::<Hash>::combine(
+            &MerkleTree::<Hash>::combine(&hashes[0], &hashes[1]),
+            &hashes[2],
+        );
+        let tree = MerkleTree::<Hash>::new(&leaves);
+        assert_eq!(tree.root(), expected);
+        assert_eq!(tree.into_root(), expected);
+
+        // repeating the last leaf commits to a different list
+        let padded = [leaves[0], leaves[1], leaves[2], leaves[2]];
+        assert_ne!(MerkleTree::<Hash>::new(&padded).root(), expected);
+    }
+
+    #[test]
+    fn test_reject_phantom_leaf() {
+        let leaves: Vec<Hash> = [b"a", b"b", b"c"]
+            .iter()
+            .map(|data| Hash::from(data.as_ref()))
+            .collect();
+        let root = MerkleTree::<Hash>::new(&leaves).root();
+        let padded = MerkleTree::<Hash>::new(&[leaves[0], leaves[1], leaves[2], leaves[2]]);
+
+        // index 3 of the padded tree, presented against the 3 leaf tree
+        let mut phantom = padded.proof(3).unwrap();
+        assert!(phantom.verify(&leaves[2], &root, 3).is_err());
+        assert!(phantom.verify(&leaves[2], &root, 4).is_err());
+        let mut proof = MerkleTree::<Hash>::new(&leaves).proof(2).unwrap();
+        proof.verify(&leaves[2], &root, 3).unwrap();
+        proof.leaf_index = 3;
+        assert!(proof.verify(&leaves[2], &root, 3).is_err());
+        assert!(proof.verify(&leaves[2], &root, 4).is_err());
+        phantom.leaf_index = 2;
+        assert!(phantom.verify(&leaves[2], &root, 3).is_err());
+    }
+
+    #[test]
+    fn test_incremental_root_matches_tree() {
+        let leaves: Vec<Hash> = (0u64..37)
+            .map(|i| Hash::from(i.to_le_bytes().as_ref()))
+            .collect();
+        let mut tree = IncrementalMerkleTree::<Hash>::new();
+        assert_eq!(tree.root(), Hash::default());
+        for (count, leaf) in leaves.iter().enumerate() {
+            tree.push(leaf);
+            assert_eq!(tree.len(), count + 1);
+            assert_eq!(
+                tree.root(),
+                MerkleTree::<Hash>::new(&leaves[..=count]).root()
+            );
+        }
+    }
+
+    #[test]
+    fn test_inclusion_proofs() {
+        for count in 1u64..12 {
+            let leaves: Vec<Hash> = (0..count)
+                .map(|i| Hash::from(i.to_le_bytes().as_ref()))
+                .collect();
+            let tree = MerkleTree::<Hash>::new(&leaves);
+            let root = tree.root();
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = tree.proof(index).unwrap();
+                proof.verify(leaf, &root, count).unwrap();
+                let other = &leaves[(index + 1) % leaves.len()];
+                if other != leaf {
+                    assert!(proof.verify(other, &root, count).is_err());
+                }
+                if count.is_power_of_two() {
+                    assert!(proof.verify(leaf, &root, count + 1).is_err());
+                }
+            }
+            assert!(tree.proof(count as usize).is_none());
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_root_matches_sequential() {
+        for count in [0, 1, 7, PARALLEL_THRESHOLD + 3, 3 * PARALLEL_THRESHOLD] {
+            let leaves: Vec<Hash> = (0..count as u64)
+                .map(|i| Hash::from(i.to_le_bytes().as_ref()))
+                .collect();
+            let sequential = MerkleTree::<Hash>::new(&leaves).root();
+            let parallel = MerkleTree::<Hash>::new_parallel(&leaves).root_parallel();
+            assert_eq!(sequential, parallel);
+        }
+    }
 }
@@ -0,0 +1,228 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::block::BlockBuilder;
+use crate::crypto::Hash;
+use crate::difficulty::meets_difficulty;
+
+/// hashes done by a worker before publishing its count and checking for
+/// cancellation
+const BATCH_SIZE: u64 = 1024;
+
+/// Shared flag to abort mining, e.g. when a new chain tip arrives
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MiningReport {
+    /// winning nonce and hash, `None` if the job was cancelled
+    pub solution: Option<(u64, Hash)>,
+    pub hashes: u64,
+    pub elapsed: Duration,
+}
+
+impl MiningReport {
+    /// hashes per second
+    pub fn hashrate(&self) -> f64 {
+        self.hashes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Pending block sealed by `Blockchain::seal_pending_block`, mined without
+/// holding the chain and handed back to `Blockchain::mint_sealed_block`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockTemplate {
+    pub(crate) builder: BlockBuilder,
+    /// `None` on chains without proof of work, nothing to mine
+    pub(crate) difficulty: Option<u64>,
+    pub(crate) timestamp: u64,
+}
+
+impl BlockTemplate {
+    pub fn index(&self) -> u64 {
+        self.builder.index()
+    }
+
+    pub fn difficulty(&self) -> Option<u64> {
+        self.difficulty
+    }
+
+    /// search the nonce, false when cancelled first
+    pub fn mine(&mut self, miner: &Miner, cancel: &CancelToken) -> bool {
+        match self.difficulty {
+            Some(difficulty) => miner
+                .mine_block(&mut self.builder, difficulty, self.timestamp, cancel)
+                .solution
+                .is_some(),
+            None => true,
+        }
+    }
+}
+
+pub struct Miner {
+    threads: usize,
+    hashes: AtomicU64,
+    started: Mutex<Option<Instant>>,
+}
+
+impl Miner {
+    pub fn new(threads: usize) -> Miner {
+        Miner {
+            threads: threads.max(1),
+            hashes: AtomicU64::new(0),
+            started: Mutex::new(None),
+        }
+    }
+
+    /// one worker per available core
+    pub fn with_available_parallelism() -> Miner {
+        Miner::new(thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Hashrate of the current or last job, can be polled from another
+    /// thread while mining
+    pub fn hashrate(&self) -> f64 {
+        let started = *self.started.lock().unwrap_or_else(|e| e.into_inner());
+        match started {
+            Some(started) => {
+                self.hashes.load(Ordering::Relaxed) as f64
+                    / started.elapsed().as_secs_f64().max(f64::EPSILON)
+            }
+            None => 0.0,
+        }
+    }
+
+    /// Search a nonce whose hash meets `difficulty`. Worker `i` tries the
+    /// nonces `i, i + threads, i + 2 * threads...`; the first solution found
+    /// stops every worker.
+    pub fn mine<F>(&self, difficulty: u64, hash_fn: F, cancel: &CancelToken) -> MiningReport
+    where
+        F: Fn(u64) -> Hash + Sync,
+    {
+        let start = Instant::now();
+        self.hashes.store(0, Ordering::Relaxed);
+        *self.started.lock().unwrap_or_else(|e| e.into_inner()) = Some(start);
+
+        let found = AtomicBool::new(false);
+        let solution = Mutex::new(None);
+        let threads = self.threads as u64;
+        thread::scope(|scope| {
+            for worker in 0..threads {
+                let (hash_fn, found, solution) = (&hash_fn, &found, &solution);
+                scope.spawn(move || {
+                    let mut nonce = worker;
+                    loop {
+                        for _ in 0..BATCH_SIZE {
+                            let hash = hash_fn(nonce);
+                            if meets_difficulty(hash.as_ref(), difficulty) {
+                                if !found.swap(true, Ordering::Relaxed) {
+                                    *solution.lock().unwrap_or_else(|e| e.into_inner()) =
+                                        Some((nonce, hash));
+                                }
+                                return;
+                            }
+                            nonce = nonce.wrapping_add(threads);
+                        }
+                        self.hashes.fetch_add(BATCH_SIZE, Ordering::Relaxed);
+                        if found.load(Ordering::Relaxed) || cancel.is_cancelled() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+
+        MiningReport {
+            solution: solution.into_inner().unwrap_or_else(|e| e.into_inner()),
+            hashes: self.hashes.load(Ordering::Relaxed),
+            elapsed: start.elapsed(),
+        }
+    }
+
+    /// Mine the block `builder` will build at `timestamp`: the difficulty
+    /// is committed in its header and the winning nonce set, a cancelled
+    /// job leaves the nonce unset
+    pub fn mine_block(
+        &self,
+        builder: &mut BlockBuilder,
+        difficulty: u64,
+        timestamp: u64,
+        cancel: &CancelToken,
+    ) -> MiningReport {
+        builder.set_work(difficulty, 0);
+        let template = &*builder;
+        let report = self.mine(
+            difficulty,
+            |nonce| template.hash_with_nonce(timestamp, nonce),
+            cancel,
+        );
+        if let Some((nonce, _)) = report.solution {
+            builder.set_work(difficulty, nonce);
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash_nonce(nonce: u64) -> Hash {
+        Hash::from([&b"template"[..], &nonce.to_be_bytes()].concat().as_ref())
+    }
+
+    #[test]
+    fn test_mine() {
+        let miner = Miner::new(4);
+        let report = miner.mine(1 << 12, hash_nonce, &CancelToken::new());
+        let (nonce, hash) = report.solution.unwrap();
+        assert_eq!(hash, hash_nonce(nonce));
+        assert!(meets_difficulty(hash.as_ref(), 1 << 12));
+    }
+
+    #[test]
+    fn test_mine_block() {
+        let mut builder = BlockBuilder::new(1, &Hash::default());
+        let report = Miner::new(2).mine_block(&mut builder, 1 << 8, 42, &CancelToken::new());
+        let block = builder.build_at(42);
+        assert_eq!(report.solution, Some((block.nonce(), block.hash())));
+        assert_eq!(block.difficulty(), 1 << 8);
+        assert!(meets_difficulty(block.hash().as_ref(), 1 << 8));
+        block.verify().unwrap();
+    }
+
+    #[test]
+    fn test_cancel() {
+        let miner = Miner::new(2);
+        let cancel = CancelToken::new();
+        let report = thread::scope(|scope| {
+            let handle = scope.spawn(|| miner.mine(u64::MAX, hash_nonce, &cancel));
+            thread::sleep(Duration::from_millis(50));
+            assert!(miner.hashrate() > 0.0);
+            cancel.cancel();
+            handle.join().unwrap()
+        });
+        assert_eq!(report.solution, None);
+        assert!(report.hashes > 0);
+    }
+}
@@ -0,0 +1,220 @@
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
+use crate::crypto::{Hash, HashWriter};
+use crate::error::ProofError;
+use serde::{Deserialize, Serialize};
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+const PEAKS_PREFIX: u8 = 0x02;
+
+fn leaf_hash(leaf: &Hash) -> Hash {
+    Hash::from_parts(&[&[LEAF_PREFIX], leaf.as_ref()])
+}
+
+fn combine(left: &Hash, right: &Hash) -> Hash {
+    Hash::from_parts(&[&[NODE_PREFIX], left.as_ref(), right.as_ref()])
+}
+
+/// the peaks are committed together with the leaf count so two ranges of
+/// different sizes never share a root
+fn bag_peaks(leaf_count: u64, peaks: &[Hash]) -> Hash {
+    if leaf_count == 0 {
+        return Hash::default();
+    }
+    let mut writer = HashWriter::new();
+    writer.input(&[PEAKS_PREFIX]);
+    writer.input(&leaf_count.to_be_bytes());
+    for peak in peaks {
+        writer.input(peak.as_ref());
+    }
+    writer.finish()
+}
+
+/// (offset of the first leaf, height) of every perfect subtree, highest
+/// first, one per bit set in `leaf_count`
+fn mountains(leaf_count: u64) -> Vec<(u64, u32)> {
+    let mut offset = 0;
+    (0..u64::BITS)
+        .rev()
+        .filter(|height| leaf_count & (1 << height) != 0)
+        .map(|height| {
+            let mountain = (offset, height);
+            offset += 1 << height;
+            mountain
+        })
+        .collect()
+}
+
+/// Merkle mountain range, an append-only accumulator whose root commits to
+/// every leaf pushed so far
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mmr {
+    leaves: Vec<Hash>,
+    /// (height, hash) of the current peaks, highest first
+    peaks: Vec<(u32, Hash)>,
+}
+
+impl Mmr {
+    pub fn new() -> Mmr {
+        Mmr::default()
+    }
+
+    pub fn push(&mut self, leaf: &Hash) {
+        let mut node = (0, leaf_hash(leaf));
+        while let Some(&(height, peak)) = self.peaks.last() {
+            if height != node.0 {
+                break;
+            }
+            self.peaks.pop();
+            node = (height + 1, combine(&peak, &node.1));
+        }
+        self.peaks.push(node);
+        self.leaves.push(*leaf);
+    }
+
+    pub fn len(&self) -> u64 {
+        self.leaves.len() as u64
+    }
+
+    pub fn leaf(&self, index: u64) -> Option<Hash> {
+        self.leaves.get(index as usize).copied()
+    }
+
+    /// keep the first `len` leaves, the peaks are rebuilt from them
+    pub fn truncate(&mut self, len: u64) {
+        let mut leaves = std::mem::take(&mut self.leaves);
+        leaves.truncate(len as usize);
+        self.peaks.clear();
+        for leaf in &leaves {
+            self.push(leaf);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    pub fn root(&self) -> Hash {
+        let peaks: Vec<Hash> = self.peaks.iter().map(|(_, hash)| *hash).collect();
+        bag_peaks(self.len(), &peaks)
+    }
+
+    /// proof that leaf `index` belongs to the range formed by the first
+    /// `leaf_count` leaves
+    pub fn proof(&self, index: u64, leaf_count: u64) -> Option<MmrProof> {
+        if index >= leaf_count || leaf_count > self.len() {
+            return None;
+        }
+        let mut siblings = vec![];
+        let mut peaks = vec![];
+        for (offset, height) in mountains(leaf_count) {
+            let start = offset as usize;
+            let end = start + (1usize << height);
+            let mut level: Vec<Hash> = self.leaves[start..end].iter().map(leaf_hash).collect();
+            let mut position = (index >= offset && index < offset + (1 << height))
+                .then(|| (index - offset) as usize);
+            while level.len() > 1 {
+                if let Some(p) = position {
+                    siblings.push(level[p ^ 1]);
+                    position = Some(p / 2);
+                }
+                level = level
+                    .chunks(2)
+                    .map(|pair| combine(&pair[0], &pair[1]))
+                    .collect();
+            }
+            peaks.push(level[0]);
+        }
+        Some(MmrProof {
+            leaf_index: index,
+            leaf_count,
+            siblings,
+            peaks,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MmrProof {
+    pub leaf_index: u64,
+    pub leaf_count: u64,
+    /// path from the leaf to the peak of its mountain
+    pub siblings: Vec<Hash>,
+    pub peaks: Vec<Hash>,
+}
+
+impl MmrProof {
+    pub fn verify(&self, leaf: &Hash, root: &Hash) -> Result<(), ProofError> {
+        let mountains = mountains(self.leaf_count);
+        if mountains.len() != self.peaks.len() {
+            return Err(ProofError::InvalidPeakCount);
+        }
+        let (position, (offset, height)) = mountains
+            .iter()
+            .enumerate()
+            .find(|(_, (offset, height))| {
+                self.leaf_index >= *offset && self.leaf_index < offset + (1 << height)
+            })
+            .ok_or(ProofError::IndexOutOfRange)?;
+        if self.siblings.len() != *height as usize {
+            return Err(ProofError::InvalidLength);
+        }
+
+        let mut index = self.leaf_index - offset;
+        let mut node = leaf_hash(leaf);
+        for sibling in &self.siblings {
+            node = if index & 1 == 0 {
+                combine(&node, sibling)
+            } else {
+                combine(sibling, &node)
+            };
+            index >>= 1;
+        }
+        if node != self.peaks[position] {
+            return Err(ProofError::PeakMismatch);
+        }
+        if bag_peaks(self.leaf_count, &self.peaks) != *root {
+            return Err(ProofError::InvalidRoot);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proofs_for_every_prefix() {
+        let leaves: Vec<Hash> = (0u64..11)
+            .map(|i| Hash::from(i.to_be_bytes().as_ref()))
+            .collect();
+        let mut mmr = Mmr::new();
+        let mut roots = vec![mmr.root()];
+        for leaf in &leaves {
+            mmr.push(leaf);
+            roots.push(mmr.root());
+        }
+        assert_eq!(roots[0], Hash::default());
+
+        for leaf_count in 1..=leaves.len() as u64 {
+            let root = roots[leaf_count as usize];
+            for index in 0..leaf_count {
+                let proof = mmr.proof(index, leaf_count).unwrap();
+                let leaf = &leaves[index as usize];
+                proof.verify(leaf, &root).unwrap();
+                assert!(proof.verify(&leaves[0], &roots[0]).is_err());
+                if index > 0 {
+                    assert!(proof.verify(&leaves[0], &root).is_err());
+                }
+            }
+        }
+        assert!(mmr.proof(3, 3).is_none());
+        assert!(mmr.proof(0, 12).is_none());
+
+        mmr.truncate(5);
+        assert_eq!(mmr.len(), 5);
+        assert_eq!(mmr.root(), roots[5]);
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+
+use crate::address::Address;
+use crate::block::Block;
+use crate::crypto::{Hash, PublicKey};
+use crate::transaction::{TransactionKind, TxId};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainEvent {
+    NewBlock {
+        index: u64,
+        hash: Hash,
+    },
+    /// confirmed transfer of at least the configured threshold
+    LargeTransfer {
+        block_index: u64,
+        id: TxId,
+        from: PublicKey,
+        to: Address,
+        amount: u64,
+    },
+    Anomaly(Anomaly),
+    /// transaction accepted in the pending block or the mempool, not
+    /// confirmed yet
+    TransactionAdded {
+        id: TxId,
+    },
+    /// the chain switched to a fork, `depth` blocks were reverted, the
+    /// blocks of the fork follow as `NewBlock`
+    Reorg {
+        depth: u64,
+        old_tip: Hash,
+        new_tip: Hash,
+    },
+}
+
+/// Unusual activity in a confirmed block, flagged for risk monitoring
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Anomaly {
+    /// a sender has more transactions than the configured limit in a single
+    /// block, nonces are consecutive so a burst is how an account being
+    /// drained shows
+    BurstFromSender {
+        block_index: u64,
+        address: PublicKey,
+        first_nonce: u64,
+        last_nonce: u64,
+    },
+    /// total supply moved by more than the configured fraction in a block,
+    /// up by the coinbase or down by burns and fees left uncollected
+    SupplyChange {
+        block_index: u64,
+        before: u64,
+        after: u64,
+    },
+}
+
+/// Thresholds of the anomalies to flag, `None` disables a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AnomalyDetector {
+    pub max_transactions_per_sender: Option<u64>,
+    /// in basis points of the supply before the block
+    pub max_supply_change_bps: Option<u64>,
+}
+
+impl AnomalyDetector {
+    /// `before` and `after` are the total supply around the block
+    pub fn inspect(&self, block: &Block, before: u64, after: u64) -> Vec<Anomaly> {
+        let mut anomalies = vec![];
+        if let Some(max_transactions) = self.max_transactions_per_sender {
+            let mut nonces: HashMap<PublicKey, (u64, u64)> = HashMap::new();
+            let mut senders = vec![];
+            // a coinbase nonce is the block index, not a spend of the sender
+            for tx in block.transactions().iter().filter(|tx| !tx.is_coinbase()) {
+                let range = nonces.entry(tx.from()).or_insert_with(|| {
+                    senders.push(tx.from());
+                    (tx.nonce(), tx.nonce())
+                });
+                *range = (range.0.min(tx.nonce()), range.1.max(tx.nonce()));
+            }
+            for address in senders {
+                let (first_nonce, last_nonce) = nonces[&address];
+                if last_nonce - first_nonce + 1 > max_transactions {
+                    anomalies.push(Anomaly::BurstFromSender {
+                        block_index: block.index(),
+                        address,
+                        first_nonce,
+                        last_nonce,
+                    });
+                }
+            }
+        }
+        if let Some(max_supply_change_bps) = self.max_supply_change_bps {
+            let change = before.abs_diff(after);
+            if before > 0
+                && change as u128 * 10_000 > max_supply_change_bps as u128 * before as u128
+            {
+                anomalies.push(Anomaly::SupplyChange {
+                    block_index: block.index(),
+                    before,
+                    after,
+                });
+            }
+        }
+        anomalies
+    }
+}
+
+/// Called synchronously for every chain event, slow hooks should hand the
+/// event over to another thread, e.g. through a channel
+pub trait NotificationHook: Send {
+    fn notify(&self, event: &ChainEvent);
+}
+
+impl<F: Fn(&ChainEvent) + Send> NotificationHook for F {
+    fn notify(&self, event: &ChainEvent) {
+        self(event)
+    }
+}
+
+impl NotificationHook for Sender<ChainEvent> {
+    fn notify(&self, event: &ChainEvent) {
+        // a dropped receiver only means nobody listens anymore
+        let _ = self.send(event.clone());
+    }
+}
+
+#[derive(Default)]
+pub struct Notifier {
+    hooks: Vec<Box<dyn NotificationHook>>,
+    large_transfer_threshold: Option<u64>,
+    anomaly_detector: AnomalyDetector,
+}
+
+impl Notifier {
+    pub fn new() -> Notifier {
+        Notifier::default()
+    }
+
+    pub fn register(&mut self, hook: Box<dyn NotificationHook>) {
+        self.hooks.push(hook);
+    }
+
+    /// `None` disables large transfer events
+    pub fn set_large_transfer_threshold(&mut self, threshold: Option<u64>) {
+        self.large_transfer_threshold = threshold;
+    }
+
+    pub fn set_anomaly_detector(&mut self, anomaly_detector: AnomalyDetector) {
+        self.anomaly_detector = anomaly_detector;
+    }
+
+    pub fn notify(&self, event: &ChainEvent) {
+        for hook in &self.hooks {
+            hook.notify(event);
+        }
+    }
+
+    pub(crate) fn transaction_added(&self, id: TxId) {
+        self.notify(&ChainEvent::TransactionAdded { id });
+    }
+
+    /// `before` and `after` are the total supply around the block
+    pub(crate) fn block_added(&self, block: &Block, before: u64, after: u64) {
+        if self.hooks.is_empty() {
+            return;
+        }
+        self.notify(&ChainEvent::NewBlock {
+            index: block.index(),
+            hash: block.hash(),
+        });
+        if let Some(threshold) = self.large_transfer_threshold {
+            for tx in block.transactions() {
+                if let TransactionKind::Transfer { to } = tx.kind() {
+                    if tx.amount() >= threshold {
+                        self.notify(&ChainEvent::LargeTransfer {
+                            block_index: block.index(),
+                            id: tx.id(),
+                            from: tx.from(),
+                            to,
+                            amount: tx.amount(),
+                        });
+                    }
+                }
+            }
+        }
+        for anomaly in self.anomaly_detector.inspect(block, before, after) {
+            self.notify(&ChainEvent::Anomaly(anomaly));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::ConsensusConfig;
+    use crate::crypto::generate_keypair_from_secret;
+    use crate::fixture::test_chain;
+    use crate::transaction::Transaction;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_channel_hook() {
+        let (private_key, public_key, mut blockchain) = test_chain();
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let (sender, receiver) = mpsc::channel();
+        blockchain.notifier_mut().register(Box::new(sender));
+        blockchain
+            .notifier_mut()
+            .set_large_transfer_threshold(Some(100));
+
+        let small =
+            Transaction::new_and_sign(&public_key, &other.address(), 10, 0, &private_key).unwrap();
+        let large =
+            Transaction::new_and_sign(&public_key, &other.address(), 500, 1, &private_key).unwrap();
+        blockchain.add_transaction(small.clone()).unwrap();
+        blockchain.add_transaction(large.clone()).unwrap();
+        blockchain.finalize_and_mint_pending_block().unwrap();
+
+        let events: Vec<ChainEvent> = receiver.try_iter().collect();
+        let block = blockchain.last_block().unwrap();
+        assert_eq!(
+            events,
+            vec![
+                ChainEvent::TransactionAdded { id: small.id() },
+                ChainEvent::TransactionAdded { id: large.id() },
+                ChainEvent::NewBlock {
+                    index: 1,
+                    hash: block.hash()
+                },
+                ChainEvent::LargeTransfer {
+                    block_index: 1,
+                    id: large.id(),
+                    from: public_key,
+                    to: other.address(),
+                    amount: 500
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_anomaly_detector() {
+        let (private_key, public_key, mut blockchain) = test_chain();
+        let (sender, receiver) = mpsc::channel();
+        blockchain.notifier_mut().register(Box::new(sender));
+        blockchain
+            .notifier_mut()
+            .set_anomaly_detector(AnomalyDetector {
+                max_transactions_per_sender: Some(2),
+                max_supply_change_bps: Some(1_000),
+            });
+
+        for nonce in 0..3 {
+            let mut burn = Transaction::new_burn(&public_key, 100, nonce);
+            burn.sign(&private_key).unwrap();
+            blockchain.add_transaction(burn).unwrap();
+        }
+        blockchain.finalize_and_mint_pending_block().unwrap();
+
+        let anomalies: Vec<ChainEvent> = receiver
+            .try_iter()
+            .filter(|event| matches!(event, ChainEvent::Anomaly(_)))
+            .collect();
+        assert_eq!(
+            anomalies,
+            vec![
+                ChainEvent::Anomaly(Anomaly::BurstFromSender {
+                    block_index: 1,
+                    address: public_key,
+                    first_nonce: 0,
+                    last_nonce: 2
+                }),
+                ChainEvent::Anomaly(Anomaly::SupplyChange {
+                    block_index: 1,
+                    before: 1000,
+                    after: 700
+                }),
+            ]
+        );
+
+        // minted by the coinbase, not counted as a transaction of its
+        // beneficiary
+        blockchain.set_consensus(ConsensusConfig {
+            block_reward: 200,
+            ..ConsensusConfig::default()
+        });
+        blockchain.set_beneficiary(Some(public_key));
+        for nonce in 3..5 {
+            let mut burn = Transaction::new_burn(&public_key, 1, nonce);
+            burn.sign(&private_key).unwrap();
+            blockchain.add_transaction(burn).unwrap();
+        }
+        blockchain.finalize_and_mint_pending_block().unwrap();
+        let anomalies: Vec<ChainEvent> = receiver
+            .try_iter()
+            .filter(|event| matches!(event, ChainEvent::Anomaly(_)))
+            .collect();
+        assert_eq!(
+            anomalies,
+            vec![ChainEvent::Anomaly(Anomaly::SupplyChange {
+                block_index: 2,
+                before: 700,
+                after: 898
+            })]
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+use crate::crypto::{CryptoError, Hash, PublicKey, Signature};
+use crate::error::LedgerError;
+use crate::signer::{Signer, SignerError};
+use crate::typed_data::{typed_data_hash, verify_typed_data, Domain, StructEncoder, TypedData};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OwnershipError {
+    Crypto(CryptoError),
+    Signer(SignerError),
+    Ledger(LedgerError),
+    /// the signer does not hold the key of the challenged address
+    WrongSigner,
+    UnknownBlock(u64),
+    /// the challenge block was replaced by a fork or never part of the chain
+    ForkedBlock(u64),
+}
+
+impl fmt::Display for OwnershipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OwnershipError::Crypto(e) => write!(f, "{}", e),
+            OwnershipError::Signer(e) => write!(f, "{}", e),
+            OwnershipError::Ledger(e) => write!(f, "{}", e),
+            OwnershipError::WrongSigner => {
+                write!(f, "Signer does not control the challenged address")
+            }
+            OwnershipError::UnknownBlock(index) => {
+                write!(f, "Unknown challenge block {}", index)
+            }
+            OwnershipError::ForkedBlock(index) => {
+                write!(f, "Challenge block {} is not part of the chain", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OwnershipError {}
+
+/// Challenge issued by a verifier, e.g. an airdrop claim page. Anchoring it
+/// to a block proves control of the key no earlier than that block, the
+/// verifier picked `nonce` so the proof cannot be replayed to someone else.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnershipChallenge {
+    pub address: PublicKey,
+    pub block_index: u64,
+    pub block_hash: Hash,
+    pub nonce: Hash,
+}
+
+impl TypedData for OwnershipChallenge {
+    fn encode_type() -> String {
+        "OwnershipChallenge(bytes address,uint64 blockIndex,bytes32 blockHash,bytes32 nonce)"
+            .to_string()
+    }
+
+    fn encode_data(&self) -> Vec<u8> {
+        StructEncoder::new()
+            .public_key(&self.address)
+            .u64(self.block_index)
+            .hash(&self.block_hash)
+            .hash(&self.nonce)
+            .finish()
+    }
+}
+
+impl OwnershipChallenge {
+    pub fn new(address: &PublicKey, block: &Block, nonce: &Hash) -> OwnershipChallenge {
+        OwnershipChallenge {
+            address: *address,
+            block_index: block.index(),
+            block_hash: block.hash(),
+            nonce: *nonce,
+        }
+    }
+
+    /// answer the challenge with the key of `address`
+    pub fn prove(
+        self,
+        domain: &Domain,
+        signer: &dyn Signer,
+    ) -> Result<OwnershipProof, OwnershipError> {
+        if signer.public_key() != self.address {
+            return Err(OwnershipError::WrongSigner);
+        }
+        let signature = signer
+            .sign_hash(&typed_data_hash(domain, &self))
+            .map_err(OwnershipError::Signer)?;
+        Ok(OwnershipProof {
+            challenge: self,
+            signature,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnershipProof {
+    pub challenge: OwnershipChallenge,
+    pub signature: Signature,
+}
+
+impl OwnershipProof {
+    /// signature by the challenged address and anchor block part of `blockchain`
+    pub fn verify(&self, domain: &Domain, blockchain: &Blockchain) -> Result<(), OwnershipError> {
+        let challenge = &self.challenge;
+        verify_typed_data(domain, challenge, &challenge.address, &self.signature)
+            .map_err(OwnershipError::Crypto)?;
+        let block = blockchain
+            .get_block_by_index(challenge.block_index)
+            .map_err(OwnershipError::Ledger)?
+            .ok_or(OwnershipError::UnknownBlock(challenge.block_index))?;
+        if block.hash() != challenge.block_hash {
+            return Err(OwnershipError::ForkedBlock(challenge.block_index));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keypair;
+    use crate::signer::LocalSigner;
+    use crate::transaction::Transaction;
+
+    #[test]
+    fn test_prove_and_verify_ownership() {
+        let domain = Domain {
+            name: "airdrop".to_string(),
+            version: "1".to_string(),
+            chain_id: 1,
+        };
+        let (private_key, address) = generate_keypair();
+        let holder = LocalSigner::new(&private_key).unwrap();
+        let genesis =
+            Transaction::new_and_sign(&address, &address.address(), 10, 0, &private_key).unwrap();
+        let blockchain = Blockchain::new(genesis, 0).unwrap();
+        let block = blockchain.last_block().unwrap();
+        let nonce = Hash::from(b"claim 1".as_ref());
+
+        let challenge = OwnershipChallenge::new(&address, block, &nonce);
+        let impostor = LocalSigner::new(&generate_keypair().0).unwrap();
+        assert!(challenge.clone().prove(&domain, &impostor).is_err());
+
+        let proof = challenge.clone().prove(&domain, &holder).unwrap();
+        proof.verify(&domain, &blockchain).unwrap();
+
+        let mut replayed = proof.clone();
+        replayed.challenge.address = impostor.public_key();
+        assert!(replayed.verify(&domain, &blockchain).is_err());
+
+        let other_domain = Domain {
+            name: "linking".to_string(),
+            ..domain.clone()
+        };
+        assert!(proof.verify(&other_domain, &blockchain).is_err());
+
+        let forked = OwnershipChallenge {
+            block_hash: Hash::default(),
+            ..challenge
+        };
+        let proof = forked.prove(&domain, &holder).unwrap();
+        assert_eq!(
+            proof.verify(&domain, &blockchain),
+            Err(OwnershipError::ForkedBlock(0))
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::address::Address;
+use crate::blockchain::Blockchain;
+use crate::crypto::PublicKey;
+use crate::error::TxError;
+use crate::mempool::MempoolError;
+use crate::transaction::{Transaction, TransactionKind};
+
+/// Local admission rule applied on top of consensus validity. Rejected
+/// transactions are still valid in blocks produced by other nodes, so rules
+/// can be tuned without a hard fork.
+pub trait TransactionPolicy: Send {
+    fn check(&self, tx: &Transaction, blockchain: &Blockchain) -> Result<(), PolicyError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyError {
+    BelowMinimumAmount {
+        amount: u64,
+        minimum: u64,
+    },
+    /// the transfer leaves an account holding less than the dust threshold
+    Dust {
+        balance: u64,
+        threshold: u64,
+    },
+    /// the transaction sends from or to a locally blocked address
+    BlockedAddress(Address),
+    /// admitting the transaction would exceed a memory ceiling, in bytes
+    MemoryLimit {
+        usage: usize,
+        limit: usize,
+    },
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PolicyError::BelowMinimumAmount { amount, minimum } => {
+                write!(f, "Amount {} is below the minimum of {}", amount, minimum)
+            }
+            PolicyError::Dust { balance, threshold } => write!(
+                f,
+                "Transfer leaves a dust balance of {}, threshold is {}",
+                balance, threshold
+            ),
+            PolicyError::BlockedAddress(address) => {
+                write!(f, "Address {:x} is blocked", address)
+            }
+            PolicyError::MemoryLimit { usage, limit } => {
+                write!(f, "Memory usage of {} bytes exceeds {}", usage, limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+/// Why a transaction was not admitted: a local policy refused it or it is
+/// invalid under consensus rules
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdmissionError {
+    Policy(PolicyError),
+    Invalid(TxError),
+    /// no room in the mempool, or an underpriced replacement
+    Mempool(MempoolError),
+}
+
+impl fmt::Display for AdmissionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AdmissionError::Policy(e) => write!(f, "Rejected by policy: {}", e),
+            AdmissionError::Invalid(e) => write!(f, "Invalid transaction: {}", e),
+            AdmissionError::Mempool(e) => write!(f, "Not queued: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AdmissionError {}
+
+impl From<PolicyError> for AdmissionError {
+    fn from(e: PolicyError) -> Self {
+        AdmissionError::Policy(e)
+    }
+}
+
+/// How the fee of a transaction built by the node or a wallet is chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeePolicy {
+    Fixed(u64),
+    /// the highest fee of the transactions ready for the next block, and at
+    /// least `minimum`, to get ahead of them when blocks are full
+    Priority {
+        minimum: u64,
+    },
+}
+
+impl Default for FeePolicy {
+    fn default() -> Self {
+        FeePolicy::Fixed(0)
+    }
+}
+
+impl FeePolicy {
+    pub fn fee(&self, blockchain: &Blockchain) -> u64 {
+        match *self {
+            FeePolicy::Fixed(fee) => fee,
+            FeePolicy::Priority { minimum } => {
+                let nonce_of = |sender: &PublicKey| {
+                    blockchain
+                        .get_account(&sender.address())
+                        .map_or(0, |a| a.nonce())
+                };
+                blockchain
+                    .pending_block()
+                    .transactions()
+                    .iter()
+                    .chain(blockchain.mempool().ready(nonce_of))
+                    .map(|tx| tx.fee())
+                    .fold(minimum, u64::max)
+            }
+        }
+    }
+}
+
+/// Minimum amount per transaction, and no account left with a non zero
+/// balance below `dust_threshold` after a transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DustPolicy {
+    pub min_amount: u64,
+    pub dust_threshold: u64,
+}
+
+impl TransactionPolicy for DustPolicy {
+    fn check(&self, tx: &Transaction, blockchain: &Blockchain) -> Result<(), PolicyError> {
+        // grants and validations only set conditions, nothing is moved
+        let moves_funds = !matches!(
+            tx.kind(),
+            TransactionKind::GrantFees { .. } | TransactionKind::SetValidation(_)
+        );
+        if moves_funds && tx.amount() < self.min_amount {
+            return Err(PolicyError::BelowMinimumAmount {
+                amount: tx.amount(),
+                minimum: self.min_amount,
+            });
+        }
+        let balance_of = |address: &Address| {
+            blockchain
+                .get_account(address)
+                .map_or(0, |account| account.balance())
+        };
+        let amount = if moves_funds { tx.amount() } else { 0 };
+        // a granted fee is paid by the granter
+        let fee = if tx.fee_granter().is_some() {
+            0
+        } else {
+            tx.fee()
+        };
+        let spent = amount.saturating_add(fee);
+        let mut balances = vec![balance_of(&tx.from().address()).saturating_sub(spent)];
+        if let TransactionKind::Transfer { to } = tx.kind() {
+            if to != tx.from().address() {
+                balances.push(balance_of(&to).saturating_add(tx.amount()));
+            }
+        }
+        match balances
+            .into_iter()
+            .find(|balance| *balance > 0 && *balance < self.dust_threshold)
+        {
+            Some(balance) => Err(PolicyError::Dust {
+                balance,
+                threshold: self.dust_threshold,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Refuses transactions touching any of the blocked addresses
+#[derive(Debug, Clone, Default)]
+pub struct AddressFilter {
+    blocked: HashSet<Address>,
+}
+
+impl AddressFilter {
+    pub fn new() -> AddressFilter {
+        AddressFilter::default()
+    }
+
+    pub fn block(&mut self, address: &Address) {
+        self.blocked.insert(*address);
+    }
+
+    pub fn unblock(&mut self, address: &Address) {
+        self.blocked.remove(address);
+    }
+
+    pub fn is_blocked(&self, address: &Address) -> bool {
+        self.blocked.contains(address)
+    }
+}
+
+impl TransactionPolicy for AddressFilter {
+    fn check(&self, tx: &Transaction, _blockchain: &Blockchain) -> Result<(), PolicyError> {
+        match [Some(tx.from().address()), tx.to()]
+            .into_iter()
+            .flatten()
+            .find(|address| self.is_blocked(address))
+        {
+            Some(address) => Err(PolicyError::BlockedAddress(address)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keypair_from_secret;
+    use crate::fixture::test_chain;
+
+    #[test]
+    fn test_dust_policy_is_local() {
+        let (private_key, public_key, mut blockchain) = test_chain();
+        let (_, other) = generate_keypair_from_secret(b"other");
+        blockchain.add_policy(Box::new(DustPolicy {
+            min_amount: 5,
+            dust_threshold: 20,
+        }));
+
+        let small =
+            Transaction::new_and_sign(&public_key, &other.address(), 4, 0, &private_key).unwrap();
+        assert_eq!(
+            blockchain.admit_transaction(small),
+            Err(AdmissionError::Policy(PolicyError::BelowMinimumAmount {
+                amount: 4,
+                minimum: 5
+            }))
+        );
+        let dust =
+            Transaction::new_and_sign(&public_key, &other.address(), 10, 0, &private_key).unwrap();
+        assert_eq!(
+            blockchain.admit_transaction(dust.clone()),
+            Err(AdmissionError::Policy(PolicyError::Dust {
+                balance: 10,
+                threshold: 20
+            }))
+        );
+        let bad_nonce =
+            Transaction::new_and_sign(&public_key, &other.address(), 50, 1, &private_key).unwrap();
+        assert!(matches!(
+            blockchain.admit_transaction(bad_nonce),
+            Err(AdmissionError::Invalid(_))
+        ));
+
+        // still valid under consensus rules
+        blockchain.add_transaction(dust).unwrap();
+    }
+
+    #[test]
+    fn test_address_filter() {
+        let (private_key, public_key, mut blockchain) = test_chain();
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let mut filter = AddressFilter::new();
+        filter.block(&other.address());
+        blockchain.add_policy(Box::new(filter));
+
+        let tx =
+            Transaction::new_and_sign(&public_key, &other.address(), 10, 0, &private_key).unwrap();
+        assert_eq!(
+            blockchain.admit_transaction(tx),
+            Err(AdmissionError::Policy(PolicyError::BlockedAddress(
+                other.address()
+            )))
+        );
+        let tx = Transaction::new_and_sign(&public_key, &public_key.address(), 10, 0, &private_key)
+            .unwrap();
+        blockchain.admit_transaction(tx).unwrap();
+    }
+}
@@ -0,0 +1,261 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::blockchain::Blockchain;
+use crate::error::LedgerError;
+use crate::miner::{CancelToken, Miner};
+use crate::notifier::ChainEvent;
+
+/// When a devnet producer finalizes the pending block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProducerConfig {
+    /// produce a block at this interval, `None` to only react to
+    /// `max_pending`
+    pub interval: Option<Duration>,
+    /// produce as soon as the pending block holds this many transactions,
+    /// 1 mines every transaction instantly
+    pub max_pending: Option<usize>,
+    /// let the interval pass without producing empty blocks
+    pub skip_empty: bool,
+}
+
+impl Default for ProducerConfig {
+    fn default() -> Self {
+        ProducerConfig {
+            interval: Some(Duration::from_secs(1)),
+            max_pending: None,
+            skip_empty: false,
+        }
+    }
+}
+
+impl ProducerConfig {
+    /// instamine: one block per transaction, nothing when idle
+    pub fn instamine() -> ProducerConfig {
+        ProducerConfig {
+            interval: None,
+            max_pending: Some(1),
+            skip_empty: true,
+        }
+    }
+
+    pub fn should_produce(&self, pending: usize, since_last_block: Duration) -> bool {
+        if self.max_pending.is_some_and(|max| pending >= max.max(1)) {
+            return true;
+        }
+        let interval_elapsed = self
+            .interval
+            .is_some_and(|interval| since_last_block >= interval);
+        interval_elapsed && (pending > 0 || !self.skip_empty)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProducerError {
+    /// the pending block could not be finalized, the task stopped
+    Ledger(LedgerError),
+    Panicked,
+}
+
+impl fmt::Display for ProducerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProducerError::Ledger(e) => write!(f, "{}", e),
+            ProducerError::Panicked => write!(f, "Block producer panicked"),
+        }
+    }
+}
+
+impl std::error::Error for ProducerError {}
+
+/// Background task finalizing the pending block of a shared chain according
+/// to a `ProducerConfig` that can be changed while it runs. The task sleeps
+/// until a transaction is added, the interval elapses or the config
+/// changes, and mines without holding the chain.
+pub struct BlockProducer {
+    config: Arc<Mutex<ProducerConfig>>,
+    produced: Arc<AtomicU64>,
+    cancel: CancelToken,
+    wake: Sender<()>,
+    handle: Option<JoinHandle<Result<(), LedgerError>>>,
+}
+
+impl BlockProducer {
+    pub fn start(blockchain: Arc<Mutex<Blockchain>>, config: ProducerConfig) -> BlockProducer {
+        let config = Arc::new(Mutex::new(config));
+        let produced = Arc::new(AtomicU64::new(0));
+        let cancel = CancelToken::new();
+        let (wake, woken) = mpsc::channel();
+        {
+            let wake = wake.clone();
+            let mut blockchain = blockchain.lock().unwrap_or_else(|e| e.into_inner());
+            blockchain
+                .notifier_mut()
+                .register(Box::new(move |event: &ChainEvent| {
+                    if let ChainEvent::TransactionAdded { .. } = event {
+                        // a stopped producer no longer listens
+                        let _ = wake.send(());
+                    }
+                }));
+        }
+        let handle = {
+            let (config, produced, cancel) = (config.clone(), produced.clone(), cancel.clone());
+            thread::spawn(move || produce(&blockchain, &config, &produced, &cancel, &woken))
+        };
+        BlockProducer {
+            config,
+            produced,
+            cancel,
+            wake,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn config(&self) -> ProducerConfig {
+        *self.config.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set_config(&self, config: ProducerConfig) {
+        *self.config.lock().unwrap_or_else(|e| e.into_inner()) = config;
+        let _ = self.wake.send(());
+    }
+
+    pub fn blocks_produced(&self) -> u64 {
+        self.produced.load(Ordering::Relaxed)
+    }
+
+    /// stop the task, returning the error that ended it early if any
+    pub fn stop(mut self) -> Result<(), ProducerError> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<(), ProducerError> {
+        self.cancel.cancel();
+        let _ = self.wake.send(());
+        match self.handle.take() {
+            Some(handle) => match handle.join() {
+                Ok(result) => result.map_err(ProducerError::Ledger),
+                Err(_) => Err(ProducerError::Panicked),
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+/// Loop of the producer task. The pending block is sealed under the chain
+/// lock and mined without it, a template gone stale in the meantime is
+/// dropped and the decision taken again.
+fn produce(
+    blockchain: &Mutex<Blockchain>,
+    config: &Mutex<ProducerConfig>,
+    produced: &AtomicU64,
+    cancel: &CancelToken,
+    woken: &Receiver<()>,
+) -> Result<(), LedgerError> {
+    let miner = Miner::with_available_parallelism();
+    let mut last_block = Instant::now();
+    while !cancel.is_cancelled() {
+        let config = *config.lock().unwrap_or_else(|e| e.into_inner());
+        let mut chain = blockchain.lock().unwrap_or_else(|e| e.into_inner());
+        let pending = chain.ready_transactions();
+        if !config.should_produce(pending, last_block.elapsed()) {
+            drop(chain);
+            // past the interval only a transaction changes the decision
+            let timeout = config
+                .interval
+                .and_then(|interval| interval.checked_sub(last_block.elapsed()))
+                .filter(|timeout| !timeout.is_zero());
+            match timeout {
+                Some(timeout) => {
+                    let _ = woken.recv_timeout(timeout);
+                }
+                None => {
+                    let _ = woken.recv();
+                }
+            }
+            while woken.try_recv().is_ok() {}
+            continue;
+        }
+        let mut template = chain.seal_pending_block()?;
+        drop(chain);
+        if !template.mine(&miner, cancel) {
+            break;
+        }
+        let mut chain = blockchain.lock().unwrap_or_else(|e| e.into_inner());
+        match chain.mint_sealed_block(template) {
+            Ok(()) => {
+                produced.fetch_add(1, Ordering::Relaxed);
+                last_block = Instant::now();
+            }
+            Err(LedgerError::StaleTemplate) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+impl Drop for BlockProducer {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keypair_from_secret;
+    use crate::transaction::Transaction;
+
+    #[test]
+    fn test_should_produce() {
+        let second = Duration::from_secs(1);
+        let config = ProducerConfig {
+            interval: Some(second),
+            max_pending: Some(3),
+            skip_empty: true,
+        };
+        assert!(!config.should_produce(0, second));
+        assert!(config.should_produce(1, second));
+        assert!(!config.should_produce(2, second / 2));
+        assert!(config.should_produce(3, Duration::ZERO));
+        assert!(ProducerConfig::default().should_produce(0, second));
+        assert!(!ProducerConfig::instamine().should_produce(0, 10 * second));
+    }
+
+    #[test]
+    fn test_instamine() {
+        let (private_key, public_key) = generate_keypair_from_secret(b"producer");
+        let genesis =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 1000, 0, &private_key)
+                .unwrap();
+        let mut blockchain = Blockchain::new(genesis, 0).unwrap();
+        let (sender, new_blocks) = mpsc::channel();
+        blockchain
+            .notifier_mut()
+            .register(Box::new(move |event: &ChainEvent| {
+                if let ChainEvent::NewBlock { index, .. } = event {
+                    let _ = sender.send(*index);
+                }
+            }));
+        let blockchain = Arc::new(Mutex::new(blockchain));
+        let producer = BlockProducer::start(blockchain.clone(), ProducerConfig::instamine());
+
+        let tx = Transaction::new_and_sign(&public_key, &public_key.address(), 10, 0, &private_key)
+            .unwrap();
+        blockchain.lock().unwrap().add_transaction(tx).unwrap();
+        assert_eq!(new_blocks.recv_timeout(Duration::from_secs(5)), Ok(1));
+        producer.set_config(ProducerConfig {
+            max_pending: None,
+            ..ProducerConfig::instamine()
+        });
+        producer.stop().unwrap();
+
+        let blockchain = blockchain.lock().unwrap();
+        assert_eq!(blockchain.last_block().unwrap().index(), 1);
+        assert!(blockchain.pending_block().transactions().is_empty());
+    }
+}
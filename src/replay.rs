@@ -0,0 +1,104 @@
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+use crate::commitment::MerkleStateCommitment;
+use crate::consensus::ConsensusConfig;
+use crate::crypto::Hash;
+use crate::error::LedgerError;
+use crate::state::{AccountDiff, StateSnapshot};
+
+/// First block of a replay whose execution does not reach the state root
+/// committed to by its header
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub index: u64,
+    /// state root of the header
+    pub expected: Hash,
+    pub computed: Hash,
+    /// first account off the reference state, known when the divergent
+    /// block is the last one replayed
+    pub first_diff: Option<AccountDiff>,
+}
+
+/// Re-execute `blocks`, from genesis on, to find where a node stopped
+/// agreeing with the chain it follows. The replay runs on a chain of its
+/// own, in memory and without clock or mempool, and accounts are kept
+/// sorted, so two replays of the same blocks always reach the same state.
+/// `reference` is the state expected after the last block, e.g. a snapshot
+/// of the node producing it. Failures other than a state root mismatch are
+/// returned as errors.
+pub fn find_divergence(
+    blocks: Vec<Block>,
+    consensus: ConsensusConfig,
+    reference: Option<&StateSnapshot>,
+) -> Result<Option<Divergence>, LedgerError> {
+    let commitment = Box::new(MerkleStateCommitment::new());
+    match Blockchain::replay(blocks, consensus, commitment, reference) {
+        Ok(_) => Ok(None),
+        Err(LedgerError::InvalidStateRoot {
+            index,
+            expected,
+            computed,
+            first_diff,
+        }) => Ok(Some(Divergence {
+            index,
+            expected,
+            computed,
+            first_diff: first_diff.map(|diff| *diff),
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keypair_from_secret;
+    use crate::transaction::Transaction;
+
+    #[test]
+    fn test_find_divergence() {
+        let (private_key, public_key) = generate_keypair_from_secret(b"replay");
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let genesis =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 1000, 0, &private_key)
+                .unwrap();
+        let mut blockchain = Blockchain::new(genesis, 0).unwrap();
+        let transfer = |nonce| {
+            Transaction::new_and_sign(&public_key, &other.address(), 10, nonce, &private_key)
+                .unwrap()
+        };
+        for nonce in 0..3 {
+            blockchain.add_transaction(transfer(nonce)).unwrap();
+            blockchain.finalize_and_mint_pending_block().unwrap();
+        }
+        let blocks = blockchain.blocks().unwrap();
+        let consensus = ConsensusConfig::default();
+        assert_eq!(find_divergence(blocks.clone(), consensus, None), Ok(None));
+
+        // a block committing to the state before its own transaction, as
+        // produced by a node skipping it
+        let reference = blockchain.state_snapshot();
+        let mut builder = blockchain.pending_block().clone();
+        builder.add_transaction(transfer(3)).unwrap();
+        let forged = builder.build_at(blockchain.last_block().unwrap().timestamp());
+        let mut diverging = blocks.clone();
+        diverging.push(forged.clone());
+        let divergence = find_divergence(diverging, consensus, Some(&reference))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (divergence.index, divergence.expected),
+            (4, forged.state_root())
+        );
+        assert_ne!(divergence.computed, divergence.expected);
+        let diff = divergence.first_diff.unwrap();
+        assert_eq!(diff.address, public_key.address());
+        assert_eq!(diff.left.map(|account| account.balance()), Some(970));
+        assert_eq!(diff.right.map(|account| account.balance()), Some(960));
+
+        assert_eq!(
+            find_divergence(blocks[1..].to_vec(), consensus, None),
+            Err(LedgerError::InvalidGenesis)
+        );
+    }
+}
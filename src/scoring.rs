@@ -0,0 +1,147 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::account::Account;
+use crate::address::Address;
+use crate::crypto::PublicKey;
+use crate::transaction::Transaction;
+
+/// Read only access to the account state for scorers
+#[derive(Clone, Copy)]
+pub struct AccountView<'a> {
+    accounts: &'a BTreeMap<Address, Account>,
+}
+
+impl<'a> AccountView<'a> {
+    pub(crate) fn new(accounts: &'a BTreeMap<Address, Account>) -> AccountView<'a> {
+        AccountView { accounts }
+    }
+
+    pub fn get_account(&self, address: &Address) -> Option<&'a Account> {
+        self.accounts.get(address)
+    }
+
+    pub fn balance(&self, address: &Address) -> u64 {
+        self.get_account(address).map_or(0, |a| a.balance())
+    }
+
+    pub fn nonce(&self, address: &Address) -> u64 {
+        self.get_account(address).map_or(0, |a| a.nonce())
+    }
+}
+
+/// Operator defined priority of a transaction, higher scores are included
+/// first. Closures with the same signature are scorers too.
+pub trait TransactionScorer: Send {
+    fn score(&self, tx: &Transaction, accounts: &AccountView) -> u64;
+}
+
+impl<F: Fn(&Transaction, &AccountView) -> u64 + Send> TransactionScorer for F {
+    fn score(&self, tx: &Transaction, accounts: &AccountView) -> u64 {
+        self(tx, accounts)
+    }
+}
+
+/// gives every transaction the same score, keeping the arrival order
+pub struct ArrivalOrder;
+
+impl TransactionScorer for ArrivalOrder {
+    fn score(&self, _tx: &Transaction, _accounts: &AccountView) -> u64 {
+        0
+    }
+}
+
+/// scores a transaction by the fee it pays, the highest fees first
+pub struct HighestFee;
+
+impl TransactionScorer for HighestFee {
+    fn score(&self, tx: &Transaction, _accounts: &AccountView) -> u64 {
+        tx.fee()
+    }
+}
+
+/// Order transactions by descending score while keeping the transactions of
+/// each sender in nonce order, ties go to the sender seen first
+pub fn order_by_score(
+    transactions: Vec<Transaction>,
+    scorer: &dyn TransactionScorer,
+    accounts: &AccountView,
+) -> Vec<Transaction> {
+    let mut senders: HashMap<PublicKey, usize> = HashMap::new();
+    let mut queues: Vec<Vec<Transaction>> = vec![];
+    for tx in transactions {
+        let queue = *senders.entry(tx.from()).or_insert_with(|| {
+            queues.push(vec![]);
+            queues.len() - 1
+        });
+        queues[queue].push(tx);
+    }
+    let mut queues: Vec<VecDeque<Transaction>> = queues
+        .into_iter()
+        .map(|mut queue| {
+            queue.sort_by_key(|tx| tx.nonce());
+            queue.into()
+        })
+        .collect();
+
+    let mut ordered = vec![];
+    loop {
+        let best = queues
+            .iter()
+            .enumerate()
+            .filter_map(|(i, queue)| queue.front().map(|tx| (i, scorer.score(tx, accounts))))
+            .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)));
+        match best.and_then(|(i, _)| queues[i].pop_front()) {
+            Some(tx) => ordered.push(tx),
+            None => break,
+        }
+    }
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keypair_from_secret;
+
+    #[test]
+    fn test_order_keeps_nonce_order() {
+        let (a_key, a) = generate_keypair_from_secret(b"a");
+        let (b_key, b) = generate_keypair_from_secret(b"b");
+        let a1 = Transaction::new_and_sign(&a, &b.address(), 100, 1, &a_key).unwrap();
+        let a0 = Transaction::new_and_sign(&a, &b.address(), 1, 0, &a_key).unwrap();
+        let b0 = Transaction::new_and_sign(&b, &a.address(), 50, 0, &b_key).unwrap();
+        let accounts = BTreeMap::new();
+        let view = AccountView::new(&accounts);
+
+        let by_amount = |tx: &Transaction, _: &AccountView| tx.amount();
+        let ordered = order_by_score(vec![a1.clone(), a0.clone(), b0.clone()], &by_amount, &view);
+        assert_eq!(ordered, vec![b0.clone(), a0.clone(), a1.clone()]);
+
+        let ordered = order_by_score(
+            vec![a1.clone(), b0.clone(), a0.clone()],
+            &ArrivalOrder,
+            &view,
+        );
+        assert_eq!(ordered, vec![a0, a1, b0]);
+    }
+
+    #[test]
+    fn test_order_by_fee() {
+        let (a_key, a) = generate_keypair_from_secret(b"a");
+        let (b_key, b) = generate_keypair_from_secret(b"b");
+        let with_fee = |from, key, nonce, fee| {
+            let mut tx = Transaction::new(from, &from.address(), 1, nonce);
+            tx.set_fee(fee);
+            tx.sign(key).unwrap();
+            tx
+        };
+        let a0 = with_fee(&a, &a_key, 0, 1);
+        let a1 = with_fee(&a, &a_key, 1, 10);
+        let b0 = with_fee(&b, &b_key, 0, 5);
+        let accounts = BTreeMap::new();
+        let view = AccountView::new(&accounts);
+
+        let ordered = order_by_score(vec![a1.clone(), a0.clone(), b0.clone()], &HighestFee, &view);
+        assert_eq!(ordered, vec![b0, a0, a1]);
+    }
+}
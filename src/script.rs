@@ -0,0 +1,390 @@
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{verify_signature, Hash, PublicKey, Signature};
+
+/// most values on the stack at once
+pub const MAX_STACK_SIZE: usize = 64;
+/// largest value that can be pushed, fits a signature and a key
+pub const MAX_ELEMENT_SIZE: usize = 128;
+/// gas of a signature check, every other operation costs 1
+pub const CHECK_SIG_GAS: u64 = 100;
+
+/// Operation of a predicate script. Scripts run on a stack of byte strings
+/// seeded with the witness, and hold if they end with a single true value,
+/// so no value can be slipped into a witness without failing it. A value is
+/// true when any of its bytes is not zero.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Op {
+    Push(Vec<u8>),
+    /// push the 8 bytes little endian encoding of a number
+    PushNumber(u64),
+    Dup,
+    Drop,
+    Swap,
+    /// replace the two top values by whether they are equal
+    Equal,
+    /// fail unless the top value, popped, is true
+    Verify,
+    /// replace the top value by its hash
+    Sha256,
+    /// pop a public key then a signature and push whether the signature is
+    /// valid for the hash of the context
+    CheckSig,
+    /// pop a block index and fail unless the context is at or past it
+    CheckLockTime,
+    /// push what the transaction takes from the account, e.g. to require
+    /// a second key above some amount
+    PushSpent,
+    /// pop a number then another and push whether the second is at most
+    /// the first
+    LessOrEqual,
+    /// run the next operations up to `Else` or `EndIf` only if the top
+    /// value, popped, is true
+    If,
+    Else,
+    EndIf,
+}
+
+impl Op {
+    fn tag(&self) -> u8 {
+        match self {
+            Op::Push(_) => 0,
+            Op::PushNumber(_) => 1,
+            Op::Dup => 2,
+            Op::Drop => 3,
+            Op::Swap => 4,
+            Op::Equal => 5,
+            Op::Verify => 6,
+            Op::Sha256 => 7,
+            Op::CheckSig => 8,
+            Op::CheckLockTime => 9,
+            Op::If => 10,
+            Op::Else => 11,
+            Op::EndIf => 12,
+            Op::PushSpent => 13,
+            Op::LessOrEqual => 14,
+        }
+    }
+}
+
+pub type Script = Vec<Op>;
+
+/// bytes of `script` covered by the signature of a transaction carrying it,
+/// integers big endian
+pub(crate) fn signing_bytes(script: &[Op]) -> Vec<u8> {
+    let mut bytes = (script.len() as u64).to_be_bytes().to_vec();
+    for op in script {
+        bytes.push(op.tag());
+        match op {
+            Op::Push(value) => {
+                bytes.extend_from_slice(&(value.len() as u64).to_be_bytes());
+                bytes.extend_from_slice(value);
+            }
+            Op::PushNumber(number) => bytes.extend_from_slice(&number.to_be_bytes()),
+            _ => {}
+        }
+    }
+    bytes
+}
+
+/// What a script is evaluated against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptContext {
+    /// hash signatures checked by `CheckSig` must sign
+    pub hash: Hash,
+    pub block_index: u64,
+    /// amount and fee paid by the sender
+    pub spent: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptError {
+    OutOfGas,
+    StackUnderflow,
+    StackOverflow,
+    ElementTooLarge(usize),
+    /// a value used as a number is not 8 bytes long
+    InvalidNumber,
+    VerifyFailed,
+    /// the lock time is still ahead of the block index
+    Locked(u64),
+    UnbalancedConditional,
+    /// the script ended without a true value on top of the stack
+    Rejected,
+    /// values left under the result, e.g. added to the witness
+    UncleanStack(usize),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::OutOfGas => write!(f, "Script ran out of gas"),
+            ScriptError::StackUnderflow => write!(f, "Script stack underflow"),
+            ScriptError::StackOverflow => write!(f, "Script stack overflow"),
+            ScriptError::ElementTooLarge(size) => write!(
+                f,
+                "Script value of {} bytes, maximum is {}",
+                size, MAX_ELEMENT_SIZE
+            ),
+            ScriptError::InvalidNumber => write!(f, "Script value is not a number"),
+            ScriptError::VerifyFailed => write!(f, "Script verification failed"),
+            ScriptError::Locked(index) => write!(f, "Script locked until block {}", index),
+            ScriptError::UnbalancedConditional => write!(f, "Unbalanced script conditional"),
+            ScriptError::Rejected => write!(f, "Script rejected"),
+            ScriptError::UncleanStack(count) => {
+                write!(f, "Script left {} values under its result", count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+fn is_true(value: &[u8]) -> bool {
+    value.iter().any(|byte| *byte != 0)
+}
+
+fn from_bool(value: bool) -> Vec<u8> {
+    vec![value as u8]
+}
+
+struct Stack(Vec<Vec<u8>>);
+
+impl Stack {
+    fn push(&mut self, value: Vec<u8>) -> Result<(), ScriptError> {
+        if value.len() > MAX_ELEMENT_SIZE {
+            return Err(ScriptError::ElementTooLarge(value.len()));
+        }
+        if self.0.len() >= MAX_STACK_SIZE {
+            return Err(ScriptError::StackOverflow);
+        }
+        self.0.push(value);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Vec<u8>, ScriptError> {
+        self.0.pop().ok_or(ScriptError::StackUnderflow)
+    }
+
+    fn pop_number(&mut self) -> Result<u64, ScriptError> {
+        let bytes = self.pop()?;
+        let bytes = bytes.try_into().map_err(|_| ScriptError::InvalidNumber)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+fn check_sig(public_key: &[u8], signature: &[u8], hash: &Hash) -> bool {
+    match (
+        PublicKey::try_from(public_key),
+        Signature::try_from(signature),
+    ) {
+        (Ok(public_key), Ok(signature)) => verify_signature(&public_key, hash, &signature).is_ok(),
+        _ => false,
+    }
+}
+
+/// Run `script` on a stack holding `witness`, the last value on top,
+/// spending at most `gas`. Evaluation only depends on its inputs so every
+/// node reaches the same result.
+pub fn evaluate(
+    script: &[Op],
+    witness: &[Vec<u8>],
+    context: &ScriptContext,
+    gas: u64,
+) -> Result<(), ScriptError> {
+    let mut stack = Stack(Vec::new());
+    for value in witness {
+        stack.push(value.clone())?;
+    }
+    let mut remaining = gas;
+    // whether each enclosing branch runs
+    let mut branches: Vec<bool> = Vec::new();
+    for op in script {
+        let cost = if *op == Op::CheckSig {
+            CHECK_SIG_GAS
+        } else {
+            1
+        };
+        remaining = remaining.checked_sub(cost).ok_or(ScriptError::OutOfGas)?;
+        let running = branches.iter().all(|runs| *runs);
+        match op {
+            Op::If => {
+                let runs = running && is_true(&stack.pop()?);
+                branches.push(runs);
+                continue;
+            }
+            Op::Else => {
+                let runs = branches.pop().ok_or(ScriptError::UnbalancedConditional)?;
+                let enclosing = branches.iter().all(|runs| *runs);
+                branches.push(enclosing && !runs);
+                continue;
+            }
+            Op::EndIf => {
+                branches.pop().ok_or(ScriptError::UnbalancedConditional)?;
+                continue;
+            }
+            _ if !running => continue,
+            _ => {}
+        }
+        match op {
+            Op::Push(value) => stack.push(value.clone())?,
+            Op::PushNumber(number) => stack.push(number.to_le_bytes().to_vec())?,
+            Op::Dup => {
+                let value = stack.pop()?;
+                stack.push(value.clone())?;
+                stack.push(value)?;
+            }
+            Op::Drop => {
+                stack.pop()?;
+            }
+            Op::Swap => {
+                let (a, b) = (stack.pop()?, stack.pop()?);
+                stack.push(a)?;
+                stack.push(b)?;
+            }
+            Op::Equal => {
+                let (a, b) = (stack.pop()?, stack.pop()?);
+                stack.push(from_bool(a == b))?;
+            }
+            Op::Verify => {
+                if !is_true(&stack.pop()?) {
+                    return Err(ScriptError::VerifyFailed);
+                }
+            }
+            Op::Sha256 => {
+                let value = stack.pop()?;
+                stack.push(Hash::from(value.as_slice()).as_ref().to_vec())?;
+            }
+            Op::CheckSig => {
+                let (public_key, signature) = (stack.pop()?, stack.pop()?);
+                stack.push(from_bool(check_sig(&public_key, &signature, &context.hash)))?;
+            }
+            Op::CheckLockTime => {
+                let index = stack.pop_number()?;
+                if context.block_index < index {
+                    return Err(ScriptError::Locked(index));
+                }
+            }
+            Op::PushSpent => stack.push(context.spent.to_le_bytes().to_vec())?,
+            Op::LessOrEqual => {
+                let (b, a) = (stack.pop_number()?, stack.pop_number()?);
+                stack.push(from_bool(a <= b))?;
+            }
+            Op::If | Op::Else | Op::EndIf => {}
+        }
+    }
+    if !branches.is_empty() {
+        return Err(ScriptError::UnbalancedConditional);
+    }
+    match stack.0.as_slice() {
+        [value] if is_true(value) => Ok(()),
+        [.., value] if is_true(value) => Err(ScriptError::UncleanStack(stack.0.len() - 1)),
+        _ => Err(ScriptError::Rejected),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{generate_keypair_from_secret, sign_hash};
+
+    /// pays `receiver` against the preimage of `hash_lock`, or refunds
+    /// `sender` from block `timeout` on
+    fn escrow(receiver: &PublicKey, sender: &PublicKey, hash_lock: Hash, timeout: u64) -> Script {
+        vec![
+            Op::If,
+            Op::Sha256,
+            Op::Push(hash_lock.as_ref().to_vec()),
+            Op::Equal,
+            Op::Verify,
+            Op::Push(receiver.as_ref().to_vec()),
+            Op::CheckSig,
+            Op::Else,
+            Op::PushNumber(timeout),
+            Op::CheckLockTime,
+            Op::Push(sender.as_ref().to_vec()),
+            Op::CheckSig,
+            Op::EndIf,
+        ]
+    }
+
+    #[test]
+    fn test_hash_and_time_locked_escrow() {
+        let (receiver_key, receiver) = generate_keypair_from_secret(b"receiver");
+        let (sender_key, sender) = generate_keypair_from_secret(b"sender");
+        let preimage = b"secret".to_vec();
+        let script = escrow(&receiver, &sender, Hash::from(preimage.as_slice()), 10);
+        let context = ScriptContext {
+            hash: Hash::from(&b"claim"[..]),
+            block_index: 5,
+            spent: 0,
+        };
+        let signature = |key| sign_hash(&context.hash, key).unwrap().as_ref().to_vec();
+
+        let claim = [signature(&receiver_key), preimage, vec![1]];
+        evaluate(&script, &claim, &context, 1000).unwrap();
+        let wrong_preimage = [signature(&receiver_key), b"guess".to_vec(), vec![1]];
+        assert_eq!(
+            evaluate(&script, &wrong_preimage, &context, 1000),
+            Err(ScriptError::VerifyFailed)
+        );
+
+        let refund = [signature(&sender_key), vec![0]];
+        assert_eq!(
+            evaluate(&script, &refund, &context, 1000),
+            Err(ScriptError::Locked(10))
+        );
+        let later = ScriptContext {
+            block_index: 10,
+            ..context
+        };
+        evaluate(&script, &refund, &later, 1000).unwrap();
+        let stolen = [signature(&receiver_key), vec![0]];
+        assert_eq!(
+            evaluate(&script, &stolen, &later, 1000),
+            Err(ScriptError::Rejected)
+        );
+        assert_eq!(
+            evaluate(&script, &refund, &later, 50),
+            Err(ScriptError::OutOfGas)
+        );
+    }
+
+    #[test]
+    fn test_reject_malformed_scripts() {
+        let context = ScriptContext {
+            hash: Hash::default(),
+            block_index: 0,
+            spent: 0,
+        };
+        assert_eq!(
+            evaluate(&[Op::If, Op::PushNumber(1)], &[vec![1]], &context, 10),
+            Err(ScriptError::UnbalancedConditional)
+        );
+        assert_eq!(
+            evaluate(&[Op::EndIf], &[], &context, 10),
+            Err(ScriptError::UnbalancedConditional)
+        );
+        assert_eq!(
+            evaluate(&[Op::Drop], &[], &context, 10),
+            Err(ScriptError::StackUnderflow)
+        );
+        assert_eq!(
+            evaluate(&[Op::Push(vec![1; 129])], &[], &context, 10),
+            Err(ScriptError::ElementTooLarge(129))
+        );
+        assert_eq!(
+            evaluate(&vec![Op::Dup; MAX_STACK_SIZE], &[vec![1]], &context, 100),
+            Err(ScriptError::StackOverflow)
+        );
+        assert_eq!(
+            evaluate(&[Op::PushNumber(1)], &[vec![1]], &context, 10),
+            Err(ScriptError::UncleanStack(1))
+        );
+        evaluate(&[Op::Dup, Op::Equal], &[vec![0]], &context, 10).unwrap();
+    }
+}
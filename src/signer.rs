@@ -0,0 +1,93 @@
+use std::fmt;
+
+use crate::crypto::{message_hash, sign_hash, CryptoError, Hash, PrivateKey, PublicKey, Signature};
+use crate::hardware::DeviceError;
+use crate::transaction::Transaction;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignerError {
+    Crypto(CryptoError),
+    Device(DeviceError),
+    /// the transaction is from another key than the one of the signer
+    WrongSigner,
+}
+
+impl fmt::Display for SignerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignerError::Crypto(e) => write!(f, "{}", e),
+            SignerError::Device(e) => write!(f, "{}", e),
+            SignerError::WrongSigner => write!(f, "Transaction is not from this signer"),
+        }
+    }
+}
+
+impl std::error::Error for SignerError {}
+
+impl From<CryptoError> for SignerError {
+    fn from(e: CryptoError) -> Self {
+        SignerError::Crypto(e)
+    }
+}
+
+impl From<DeviceError> for SignerError {
+    fn from(e: DeviceError) -> Self {
+        SignerError::Device(e)
+    }
+}
+
+pub trait Signer {
+    fn public_key(&self) -> PublicKey;
+
+    fn sign_hash(&self, hash: &Hash) -> Result<Signature, SignerError>;
+
+    /// domain separated off-chain message signature, see `verify_message`
+    fn sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        self.sign_hash(&message_hash(message))
+    }
+
+    /// Signers able to display the transaction (hardware devices) should
+    /// override this instead of blind signing the hash
+    fn sign_transaction(&self, tx: &mut Transaction) -> Result<(), SignerError> {
+        if tx.from() != self.public_key() {
+            return Err(SignerError::WrongSigner);
+        }
+        let signature = self.sign_hash(&tx.signing_hash())?;
+        tx.set_signature(&signature);
+        Ok(())
+    }
+}
+
+/// Signer holding its private key in memory
+#[derive(Debug, Clone)]
+pub struct LocalSigner {
+    private_key: PrivateKey,
+    public_key: PublicKey,
+}
+
+impl LocalSigner {
+    pub fn new(private_key: &PrivateKey) -> Result<LocalSigner, CryptoError> {
+        Ok(LocalSigner {
+            private_key: *private_key,
+            public_key: PublicKey::try_from(private_key)?,
+        })
+    }
+
+    /// pair returned by the `generate_keypair*` functions
+    pub(crate) fn from_keypair((private_key, public_key): (PrivateKey, PublicKey)) -> LocalSigner {
+        LocalSigner {
+            private_key,
+            public_key,
+        }
+    }
+}
+
+impl Signer for LocalSigner {
+    fn public_key(&self) -> PublicKey {
+        self.public_key
+    }
+
+    fn sign_hash(&self, hash: &Hash) -> Result<Signature, SignerError> {
+        Ok(sign_hash(hash, &self.private_key)?)
+    }
+}
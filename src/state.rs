@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::account::Account;
+use crate::address::Address;
+use crate::blockchain::Blockchain;
+use crate::error::StateError;
+
+/// Copy of the account state at some point, comparable with the state of
+/// another node or of a backup
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    block_index: u64,
+    total_supply: u64,
+    accounts: BTreeMap<Address, Account>,
+}
+
+/// Account present in at least one of the compared snapshots, with the
+/// state on each side
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountDiff {
+    pub address: Address,
+    pub left: Option<Account>,
+    pub right: Option<Account>,
+}
+
+/// reports the left side as expected and the right side as what was found
+impl fmt::Display for AccountDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let describe = |account: &Option<Account>| match account {
+            Some(account) => format!("balance {}, nonce {}", account.balance(), account.nonce()),
+            None => "no account".to_string(),
+        };
+        write!(
+            f,
+            "account {:x}: expected {}, got {}",
+            self.address,
+            describe(&self.left),
+            describe(&self.right)
+        )
+    }
+}
+
+impl StateSnapshot {
+    pub fn block_index(&self) -> u64 {
+        self.block_index
+    }
+
+    pub fn total_supply(&self) -> u64 {
+        self.total_supply
+    }
+
+    pub fn get_account(&self, address: &Address) -> Option<&Account> {
+        self.accounts.get(address)
+    }
+
+    /// Accounts whose balance or nonce differ, sorted by address
+    pub fn diff_state(&self, other: &StateSnapshot) -> Vec<AccountDiff> {
+        let mut diffs: Vec<AccountDiff> = self
+            .accounts
+            .keys()
+            .chain(
+                other
+                    .accounts
+                    .keys()
+                    .filter(|a| !self.accounts.contains_key(a)),
+            )
+            .filter_map(|address| {
+                let left = self.accounts.get(address);
+                let right = other.accounts.get(address);
+                if left == right {
+                    return None;
+                }
+                Some(AccountDiff {
+                    address: *address,
+                    left: left.cloned(),
+                    right: right.cloned(),
+                })
+            })
+            .collect();
+        diffs.sort_by_key(|diff| diff.address);
+        diffs
+    }
+
+    /// Compare with the `expected` state, the error names the first
+    /// differing account with both versions of it
+    pub fn check_matches(&self, expected: &StateSnapshot) -> Result<(), StateError> {
+        let diffs = expected.diff_state(self);
+        if let Some(first) = diffs.first() {
+            return Err(StateError::AccountsDiffer {
+                count: diffs.len(),
+                first: Box::new(first.clone()),
+            });
+        }
+        if self.total_supply != expected.total_supply {
+            return Err(StateError::TotalSupply {
+                expected: expected.total_supply,
+                got: self.total_supply,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Blockchain {
+    /// state including the transactions of the pending block
+    pub fn state_snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            block_index: self.pending_block().index(),
+            total_supply: self.total_supply(),
+            accounts: self
+                .accounts()
+                .map(|account| (account.address(), account.clone()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{generate_keypair_from_secret, PublicKey};
+    use crate::transaction::Transaction;
+
+    #[test]
+    fn test_diff_state() {
+        let (private_key, public_key) = generate_keypair_from_secret(b"state");
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let genesis =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 1000, 0, &private_key)
+                .unwrap();
+        let mut blockchain = Blockchain::new(genesis, 0).unwrap();
+        let before = blockchain.state_snapshot();
+        assert!(before.diff_state(&before).is_empty());
+
+        let tx =
+            Transaction::new_and_sign(&public_key, &other.address(), 10, 0, &private_key).unwrap();
+        blockchain.add_transaction(tx).unwrap();
+        let after = blockchain.state_snapshot();
+
+        let diffs = before.diff_state(&after);
+        assert_eq!(diffs.len(), 2);
+        let created = diffs.iter().find(|d| d.address == other.address()).unwrap();
+        assert_eq!(created.left, None);
+        assert_eq!(created.right.as_ref().unwrap().balance(), 10);
+        assert_eq!(
+            before.check_matches(&after),
+            Err(StateError::AccountsDiffer {
+                count: 2,
+                first: Box::new(after.diff_state(&before).remove(0))
+            })
+        );
+        assert_eq!(
+            created.to_string(),
+            format!(
+                "account {}: expected no account, got balance 10, nonce 0",
+                hex::encode(other.address())
+            )
+        );
+    }
+
+    #[test]
+    fn test_snapshot_encoding_is_canonical() {
+        let (private_key, public_key) = generate_keypair_from_secret(b"state");
+        let genesis =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 1000, 0, &private_key)
+                .unwrap();
+        let others: Vec<_> = (0u8..8)
+            .map(|i| generate_keypair_from_secret(&[i]).1)
+            .collect();
+        let mut forward = Blockchain::new(genesis.clone(), 0).unwrap();
+        let mut backward = Blockchain::new(genesis, 0).unwrap();
+        for (nonce, other) in others.iter().enumerate() {
+            let send = |to: &PublicKey| {
+                Transaction::new_and_sign(
+                    &public_key,
+                    &to.address(),
+                    10,
+                    nonce as u64,
+                    &private_key,
+                )
+                .unwrap()
+            };
+            forward.add_transaction(send(other)).unwrap();
+            backward
+                .add_transaction(send(&others[others.len() - 1 - nonce]))
+                .unwrap();
+        }
+
+        let addresses: Vec<_> = forward.accounts().map(|a| a.address()).collect();
+        assert!(addresses.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(
+            crate::encoding::encode(&forward.state_snapshot()).unwrap(),
+            crate::encoding::encode(&backward.state_snapshot()).unwrap()
+        );
+    }
+}
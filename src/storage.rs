@@ -0,0 +1,427 @@
+use crate::account::Account;
+use crate::address::Address;
+use crate::block::Block;
+use crate::crypto::Hash;
+#[cfg(feature = "sled")]
+use crate::encoding;
+use crate::error::StorageError;
+#[cfg(feature = "sled")]
+use sled::transaction::{ConflictableTransactionError, TransactionError, Transactional};
+use std::collections::HashMap;
+
+/// Writes applied together by `Storage::commit`, all of them or none. The
+/// blocks are truncated first, then the blocks and accounts written, the
+/// accounts removed and the head moved last.
+#[derive(Debug, Clone, Default)]
+pub struct StorageBatch<'a> {
+    truncate: Option<u64>,
+    blocks: Vec<&'a Block>,
+    accounts: Vec<&'a Account>,
+    removed_accounts: Vec<Address>,
+    head: Option<u64>,
+}
+
+impl<'a> StorageBatch<'a> {
+    pub fn new() -> StorageBatch<'a> {
+        StorageBatch::default()
+    }
+
+    /// remove the blocks from index `height` on, replaced by the blocks of a
+    /// fork the chain switches to
+    pub fn truncate_blocks(&mut self, height: u64) {
+        self.truncate = Some(height);
+    }
+
+    /// store `block` under its index and its hash
+    pub fn put_block(&mut self, block: &'a Block) {
+        self.blocks.push(block);
+    }
+
+    pub fn put_account(&mut self, account: &'a Account) {
+        self.accounts.push(account);
+    }
+
+    pub fn remove_account(&mut self, address: Address) {
+        self.removed_accounts.push(address);
+    }
+
+    /// index of the last block of the chain once the batch is applied
+    pub fn set_head(&mut self, index: u64) {
+        self.head = Some(index);
+    }
+
+    /// number of records the batch writes or removes
+    pub fn len(&self) -> usize {
+        self.truncate.iter().count()
+            + self.blocks.len()
+            + self.accounts.len()
+            + self.removed_accounts.len()
+            + self.head.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Where a `Blockchain` keeps its blocks and the state as of its last
+/// block. Only the chain tip, the accounts and the indexes stay in memory.
+pub trait Storage: Send {
+    /// apply every write of `batch` or, on error, none of them
+    fn commit(&mut self, batch: &StorageBatch) -> Result<(), StorageError>;
+
+    fn block_by_index(&self, index: u64) -> Result<Option<Block>, StorageError>;
+
+    fn block_by_hash(&self, hash: &Hash) -> Result<Option<Block>, StorageError>;
+
+    fn account(&self, address: &Address) -> Result<Option<Account>, StorageError>;
+
+    /// index of the last block of the chain
+    fn head(&self) -> Result<Option<u64>, StorageError>;
+}
+
+/// Keeps everything in memory, nothing survives a restart
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    blocks: Vec<Block>,
+    block_hashes: HashMap<Hash, u64>,
+    accounts: HashMap<Address, Account>,
+    head: Option<u64>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn commit(&mut self, batch: &StorageBatch) -> Result<(), StorageError> {
+        // checked before the first write so a failing batch changes nothing
+        let height = batch.truncate.map_or(self.blocks.len(), |height| {
+            self.blocks.len().min(height as usize)
+        });
+        for (offset, block) in batch.blocks.iter().enumerate() {
+            if block.index() as usize != height + offset {
+                return Err(StorageError::UnexpectedBlock(block.index()));
+            }
+        }
+        for block in self.blocks.drain(height..) {
+            self.block_hashes.remove(&block.hash());
+        }
+        for block in &batch.blocks {
+            self.blocks.push((*block).clone());
+            self.block_hashes.insert(block.hash(), block.index());
+        }
+        for account in &batch.accounts {
+            self.accounts.insert(account.address(), (*account).clone());
+        }
+        for address in &batch.removed_accounts {
+            self.accounts.remove(address);
+        }
+        if let Some(head) = batch.head {
+            self.head = Some(head);
+        }
+        Ok(())
+    }
+
+    fn block_by_index(&self, index: u64) -> Result<Option<Block>, StorageError> {
+        Ok(self.blocks.get(index as usize).cloned())
+    }
+
+    fn block_by_hash(&self, hash: &Hash) -> Result<Option<Block>, StorageError> {
+        match self.block_hashes.get(hash) {
+            Some(index) => self.block_by_index(*index),
+            None => Ok(None),
+        }
+    }
+
+    fn account(&self, address: &Address) -> Result<Option<Account>, StorageError> {
+        Ok(self.accounts.get(address).cloned())
+    }
+
+    fn head(&self) -> Result<Option<u64>, StorageError> {
+        Ok(self.head)
+    }
+}
+
+/// Durable storage in a sled database, one tree per kind of record
+#[cfg(feature = "sled")]
+pub struct SledStorage {
+    db: sled::Db,
+    blocks: sled::Tree,
+    block_hashes: sled::Tree,
+    accounts: sled::Tree,
+}
+
+#[cfg(feature = "sled")]
+const HEAD_KEY: &[u8] = b"head";
+
+#[cfg(feature = "sled")]
+impl SledStorage {
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<SledStorage, StorageError> {
+        let db = sled::open(path)?;
+        let tree = |name: &str| db.open_tree(name);
+        Ok(SledStorage {
+            blocks: tree("blocks")?,
+            block_hashes: tree("block_hashes")?,
+            accounts: tree("accounts")?,
+            db,
+        })
+    }
+}
+
+#[cfg(feature = "sled")]
+fn decode_index(bytes: &[u8]) -> Result<u64, StorageError> {
+    let bytes = bytes.try_into().map_err(|_| StorageError::InvalidIndex)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+#[cfg(feature = "sled")]
+impl Storage for SledStorage {
+    /// One transaction over every tree, flushed so the head never points
+    /// past what is on disk. Records are encoded and the truncated blocks
+    /// looked up beforehand, only the writes and the continuity check are
+    /// retried on conflict.
+    fn commit(&mut self, batch: &StorageBatch) -> Result<(), StorageError> {
+        let mut removed = vec![];
+        if let Some(height) = batch.truncate {
+            for entry in self.blocks.range(height.to_be_bytes()..) {
+                let (index, data) = entry?;
+                let block: Block = encoding::decode(&data)?;
+                removed.push((index, block.hash()));
+            }
+        }
+        let blocks = batch
+            .blocks
+            .iter()
+            .map(|block| {
+                let data = encoding::encode(*block)?;
+                Ok((block.index().to_be_bytes(), block.hash(), data))
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        let accounts = batch
+            .accounts
+            .iter()
+            .map(|account| {
+                let data = encoding::encode(*account)?;
+                Ok((account.address(), data))
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+
+        (&self.blocks, &self.block_hashes, &self.accounts, &*self.db)
+            .transaction(|(blocks_tree, hashes_tree, accounts_tree, meta)| {
+                for (index, hash) in &removed {
+                    blocks_tree.remove(index.clone())?;
+                    hashes_tree.remove(hash.as_ref())?;
+                }
+                // the stored blocks are contiguous from 0, so once truncated
+                // the first one written must be free and follow a stored one
+                if let Some(first) = batch.blocks.first().map(|block| block.index()) {
+                    let follows = match first.checked_sub(1) {
+                        Some(previous) => blocks_tree.get(previous.to_be_bytes())?.is_some(),
+                        None => true,
+                    };
+                    if !follows || blocks_tree.get(first.to_be_bytes())?.is_some() {
+                        return Err(ConflictableTransactionError::Abort(
+                            StorageError::UnexpectedBlock(first),
+                        ));
+                    }
+                }
+                for (offset, block) in batch.blocks.iter().enumerate() {
+                    if block.index() != batch.blocks[0].index() + offset as u64 {
+                        return Err(ConflictableTransactionError::Abort(
+                            StorageError::UnexpectedBlock(block.index()),
+                        ));
+                    }
+                }
+                for (index, hash, data) in &blocks {
+                    blocks_tree.insert(&index[..], data.as_slice())?;
+                    hashes_tree.insert(hash.as_ref(), &index[..])?;
+                }
+                for (address, data) in &accounts {
+                    accounts_tree.insert(address.as_ref(), data.as_slice())?;
+                }
+                for address in &batch.removed_accounts {
+                    accounts_tree.remove(address.as_ref())?;
+                }
+                if let Some(head) = batch.head {
+                    meta.insert(HEAD_KEY, &head.to_be_bytes()[..])?;
+                }
+                Ok(())
+            })
+            .map_err(|e: TransactionError<StorageError>| match e {
+                TransactionError::Abort(e) => e,
+                TransactionError::Storage(e) => StorageError::Sled(e),
+            })?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn block_by_index(&self, index: u64) -> Result<Option<Block>, StorageError> {
+        match self.blocks.get(index.to_be_bytes())? {
+            Some(data) => Ok(Some(encoding::decode(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn block_by_hash(&self, hash: &Hash) -> Result<Option<Block>, StorageError> {
+        match self.block_hashes.get(hash.as_ref())? {
+            Some(index) => self.block_by_index(decode_index(&index)?),
+            None => Ok(None),
+        }
+    }
+
+    fn account(&self, address: &Address) -> Result<Option<Account>, StorageError> {
+        match self.accounts.get(address.as_ref())? {
+            Some(data) => Ok(Some(encoding::decode(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn head(&self) -> Result<Option<u64>, StorageError> {
+        match self.db.get(HEAD_KEY)? {
+            Some(index) => Ok(Some(decode_index(&index)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::consensus::ConsensusConfig;
+    use crate::crypto::generate_keypair_from_secret;
+    use crate::transaction::Transaction;
+
+    fn build_chain(storage: Box<dyn Storage>) -> Blockchain {
+        let (private_key, public_key) = generate_keypair_from_secret(b"storage");
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let genesis =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 1000, 0, &private_key)
+                .unwrap();
+        let mut blockchain = Blockchain::new(genesis, 0).unwrap();
+        blockchain.set_storage(storage).unwrap();
+        for nonce in 0..3 {
+            let tx =
+                Transaction::new_and_sign(&public_key, &other.address(), 10, nonce, &private_key)
+                    .unwrap();
+            blockchain.add_transaction(tx).unwrap();
+            blockchain.finalize_and_mint_pending_block().unwrap();
+        }
+        blockchain
+    }
+
+    #[test]
+    fn test_reopen_from_storage() {
+        let blockchain = build_chain(Box::new(MemoryStorage::new()));
+        let blocks = blockchain.blocks().unwrap();
+        let block = &blocks[2];
+        assert_eq!(
+            blockchain
+                .get_block_by_hash(&block.hash())
+                .unwrap()
+                .as_ref(),
+            Some(block)
+        );
+        let state_root = blockchain.state_root();
+
+        let reopened =
+            Blockchain::open(blockchain.into_storage(), ConsensusConfig::default()).unwrap();
+        assert_eq!(reopened.blocks().unwrap(), blocks);
+        assert_eq!(reopened.state_root(), state_root);
+        assert!(
+            Blockchain::open(Box::new(MemoryStorage::new()), ConsensusConfig::default()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_reject_tampered_state() {
+        let blockchain = build_chain(Box::new(MemoryStorage::new()));
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let mut storage = blockchain.into_storage();
+        let account = Account::new(&other.address());
+        let mut batch = StorageBatch::new();
+        batch.put_account(&account);
+        storage.commit(&batch).unwrap();
+        assert_eq!(
+            Blockchain::open(storage, ConsensusConfig::default())
+                .err()
+                .map(|e| e.to_string()),
+            Some(format!(
+                "Stored state does not match the replayed chain: account {}: \
+                 expected balance 30, nonce 0, got balance 0, nonce 0",
+                hex::encode(other.address())
+            ))
+        );
+    }
+
+    fn assert_rejects_gaps(storage: Box<dyn Storage>) {
+        let blockchain = build_chain(storage);
+        let blocks = blockchain.blocks().unwrap();
+        let mut storage = blockchain.into_storage();
+
+        let mut batch = StorageBatch::new();
+        batch.put_block(&blocks[2]);
+        assert_eq!(
+            storage.commit(&batch).err(),
+            Some(StorageError::UnexpectedBlock(2))
+        );
+        let mut batch = StorageBatch::new();
+        batch.truncate_blocks(1);
+        batch.put_block(&blocks[2]);
+        assert_eq!(
+            storage.commit(&batch).err(),
+            Some(StorageError::UnexpectedBlock(2))
+        );
+        let mut batch = StorageBatch::new();
+        batch.truncate_blocks(2);
+        batch.put_block(&blocks[2]);
+        batch.put_block(&blocks[1]);
+        assert_eq!(
+            storage.commit(&batch).err(),
+            Some(StorageError::UnexpectedBlock(1))
+        );
+        // a rejected batch writes nothing
+        for block in &blocks {
+            assert_eq!(
+                storage.block_by_index(block.index()).unwrap().as_ref(),
+                Some(block)
+            );
+        }
+        assert!(Blockchain::open(storage, ConsensusConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_reject_block_gaps() {
+        assert_rejects_gaps(Box::new(MemoryStorage::new()));
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn test_sled_reject_block_gaps() {
+        let path =
+            std::env::temp_dir().join(format!("core-ledger-sled-gaps-{}", std::process::id()));
+        assert_rejects_gaps(Box::new(SledStorage::open(&path).unwrap()));
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn test_sled_storage_survives_restart() {
+        let path = std::env::temp_dir().join(format!("core-ledger-sled-{}", std::process::id()));
+        let blockchain = build_chain(Box::new(SledStorage::open(&path).unwrap()));
+        let blocks = blockchain.blocks().unwrap();
+        drop(blockchain);
+
+        let reopened = Blockchain::open(
+            Box::new(SledStorage::open(&path).unwrap()),
+            ConsensusConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(reopened.blocks().unwrap(), blocks);
+        drop(reopened);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
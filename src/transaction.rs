@@ -1,38 +1,202 @@
-use crate::crypto::{sign_hash, verify_signature, Hash, PrivateKey, PublicKey, Signature};
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
 
+use crate::address::Address;
+use crate::crypto::{
+    sign_hash, verify_signature, CryptoError, Hash, HashWriter, PrivateKey, PublicKey, Signature,
+};
+use crate::encoding;
+use crate::validation::Validation;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// version byte prefixed to the canonical encoding
+pub const TRANSACTION_VERSION: u8 = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    Empty,
+    UnknownVersion(u8),
+    Malformed(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Empty => write!(f, "Empty transaction encoding"),
+            DecodeError::UnknownVersion(version) => {
+                write!(f, "Unknown transaction version {}", version)
+            }
+            DecodeError::Malformed(e) => write!(f, "Malformed transaction: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// A decoded transaction, or the raw encoding of a version this node does
+/// not understand, which can still be relayed as is
 #[derive(Debug, Clone, PartialEq)]
+pub enum VersionedTransaction {
+    Known(Box<Transaction>),
+    Opaque { version: u8, bytes: Vec<u8> },
+}
+
+impl VersionedTransaction {
+    pub fn decode(bytes: &[u8]) -> Result<VersionedTransaction, DecodeError> {
+        match Transaction::from_bytes(bytes) {
+            Ok(tx) => Ok(VersionedTransaction::Known(Box::new(tx))),
+            Err(DecodeError::UnknownVersion(version)) => Ok(VersionedTransaction::Opaque {
+                version,
+                bytes: bytes.to_vec(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn version(&self) -> u8 {
+        match self {
+            VersionedTransaction::Known(_) => TRANSACTION_VERSION,
+            VersionedTransaction::Opaque { version, .. } => *version,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            VersionedTransaction::Known(tx) => tx.to_bytes(),
+            VersionedTransaction::Opaque { bytes, .. } => bytes.clone(),
+        }
+    }
+}
+
+/// Identifier covering the whole signed transaction, signature included
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TxId(Hash);
+
+impl TxId {
+    pub(crate) fn from_hash(hash: Hash) -> TxId {
+        TxId(hash)
+    }
+
+    pub fn hash(&self) -> Hash {
+        self.0
+    }
+}
+
+impl AsRef<[u8]> for TxId {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionKind {
+    Transfer {
+        to: Address,
+    },
+    /// destroys `amount` from the sender balance and the total supply
+    Burn,
+    /// Credits `amount`, the block reward and the fees of the block, to
+    /// `from`. Unsigned, only valid as the last transaction of a block with
+    /// the block index as nonce.
+    Coinbase,
+    /// Lets `grantee` pay up to `amount` of fees from the sender balance in
+    /// transactions naming it as fee granter, until block `expires_at`. A
+    /// grant replaces the previous one of the same pair, an expired one
+    /// revokes it.
+    GrantFees {
+        grantee: PublicKey,
+        expires_at: u64,
+    },
+    /// replaces the conditions later transactions of the sender must meet,
+    /// with a zero `amount`
+    SetValidation(Validation),
+}
+
+impl TransactionKind {
+    fn tag(&self) -> u8 {
+        match self {
+            TransactionKind::Transfer { .. } => 0,
+            TransactionKind::Burn => 1,
+            TransactionKind::Coinbase => 2,
+            TransactionKind::GrantFees { .. } => 3,
+            TransactionKind::SetValidation(_) => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Transaction {
     from: PublicKey,
-    to: PublicKey,
+    kind: TransactionKind,
     amount: u64,
     nonce: u64,
+    /// paid by the sender on top of `amount` to the producer of the block
+    fee: u64,
+    /// account paying the fee instead of the sender, under a fee grant
+    fee_granter: Option<PublicKey>,
+    /// chosen by the recipient to match the payment, e.g. to an invoice
+    reference: Option<Hash>,
     signature: Signature,
+    /// signatures of the same hash by cosigners of the sender validation,
+    /// in ascending byte order
+    cosignatures: Vec<Signature>,
+    /// values the validation predicate of the sender starts from, e.g.
+    /// signatures or a preimage, covered by the id but not the signature
+    witness: Vec<Vec<u8>>,
 }
 
 impl Transaction {
-    pub fn new(from: &PublicKey, to: &PublicKey, amount: u64, nonce: u64) -> Transaction {
+    pub fn new(from: &PublicKey, to: &Address, amount: u64, nonce: u64) -> Transaction {
+        Transaction::with_kind(from, TransactionKind::Transfer { to: *to }, amount, nonce)
+    }
+
+    pub fn new_burn(from: &PublicKey, amount: u64, nonce: u64) -> Transaction {
+        Transaction::with_kind(from, TransactionKind::Burn, amount, nonce)
+    }
+
+    pub fn with_kind(
+        from: &PublicKey,
+        kind: TransactionKind,
+        amount: u64,
+        nonce: u64,
+    ) -> Transaction {
         Transaction {
-            from: from.clone(),
-            to: to.clone(),
+            from: *from,
+            kind,
             amount,
             nonce,
+            fee: 0,
+            fee_granter: None,
+            reference: None,
             signature: Signature::default(),
+            cosignatures: vec![],
+            witness: vec![],
         }
     }
 
+    /// coinbase of block `index` crediting `amount` to `beneficiary`
+    pub fn new_coinbase(beneficiary: &PublicKey, amount: u64, index: u64) -> Transaction {
+        Transaction::with_kind(beneficiary, TransactionKind::Coinbase, amount, index)
+    }
+
     pub fn new_signed(
         from: &PublicKey,
-        to: &PublicKey,
+        to: &Address,
         amount: u64,
         nonce: u64,
         signature: &Signature,
-    ) -> Result<Transaction, String> {
+    ) -> Result<Transaction, CryptoError> {
         let tx = Transaction {
-            from: from.clone(),
-            to: to.clone(),
+            from: *from,
+            kind: TransactionKind::Transfer { to: *to },
             amount,
             nonce,
-            signature: signature.clone(),
+            fee: 0,
+            fee_granter: None,
+            reference: None,
+            signature: *signature,
+            cosignatures: vec![],
+            witness: vec![],
         };
         tx.verify()?;
         Ok(tx)
@@ -40,22 +204,41 @@ impl Transaction {
 
     pub fn new_and_sign(
         from: &PublicKey,
-        to: &PublicKey,
+        to: &Address,
         amount: u64,
         nonce: u64,
         private_key: &PrivateKey,
-    ) -> Result<Transaction, String> {
+    ) -> Result<Transaction, CryptoError> {
         let mut tx = Transaction::new(from, to, amount, nonce);
         tx.sign(private_key)?;
         Ok(tx)
     }
 
     pub fn from(&self) -> PublicKey {
-        self.from.clone()
+        self.from
     }
 
-    pub fn to(&self) -> PublicKey {
-        self.to.clone()
+    pub fn kind(&self) -> TransactionKind {
+        self.kind.clone()
+    }
+
+    /// recipient of a transfer, `None` for burns and coinbases
+    pub fn to(&self) -> Option<Address> {
+        match self.kind {
+            TransactionKind::Transfer { to } => Some(to),
+            TransactionKind::Burn
+            | TransactionKind::Coinbase
+            | TransactionKind::GrantFees { .. }
+            | TransactionKind::SetValidation(_) => None,
+        }
+    }
+
+    pub fn is_burn(&self) -> bool {
+        self.kind == TransactionKind::Burn
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        self.kind == TransactionKind::Coinbase
     }
 
     pub fn amount(&self) -> u64 {
@@ -66,28 +249,292 @@ impl Transaction {
         self.nonce
     }
 
+    pub fn fee(&self) -> u64 {
+        self.fee
+    }
+
+    /// covered by the signature, set it before signing
+    pub fn set_fee(&mut self, fee: u64) {
+        self.fee = fee;
+    }
+
+    pub fn fee_granter(&self) -> Option<PublicKey> {
+        self.fee_granter
+    }
+
+    /// have the fee paid by `granter` out of its grant to the sender, set it
+    /// before signing
+    pub fn set_fee_granter(&mut self, granter: Option<PublicKey>) {
+        self.fee_granter = granter;
+    }
+
+    pub fn reference(&self) -> Option<Hash> {
+        self.reference
+    }
+
+    /// covered by the signature, set it before signing
+    pub fn set_reference(&mut self, reference: Option<Hash>) {
+        self.reference = reference;
+    }
+
+    /// account paying the fee, the granter or the sender
+    pub fn fee_payer(&self) -> PublicKey {
+        self.fee_granter.unwrap_or(self.from)
+    }
+
     pub fn signature(&self) -> Signature {
-        self.signature.clone()
+        self.signature
     }
 
-    pub fn hash(&self) -> Hash {
-        let data = [
+    /// hash committed to by the signature
+    pub fn signing_hash(&self) -> Hash {
+        let kind = match &self.kind {
+            TransactionKind::Transfer { to } => to.as_ref().to_vec(),
+            TransactionKind::Burn | TransactionKind::Coinbase => vec![],
+            TransactionKind::GrantFees {
+                grantee,
+                expires_at,
+            } => [grantee.as_ref(), &expires_at.to_be_bytes()].concat(),
+            TransactionKind::SetValidation(validation) => validation.signing_bytes(),
+        };
+        let fee_granter = self.fee_granter.as_ref().map_or(&[][..], |g| g.as_ref());
+        let reference = self.reference.as_ref().map_or(&[][..], |r| r.as_ref());
+        Hash::from_parts(&[
             self.from.as_ref(),
-            self.to.as_ref(),
+            &[self.kind.tag()],
+            &kind,
             &self.amount.to_be_bytes(),
             &self.nonce.to_be_bytes(),
-        ];
-        Hash::from(data.concat().as_ref())
+            &self.fee.to_be_bytes(),
+            fee_granter,
+            reference,
+        ])
     }
 
-    pub fn sign(&mut self, private_key: &PrivateKey) -> Result<(), String> {
-        let hash = self.hash();
+    /// Used as key by the transaction index and in the transactions root,
+    /// hash of `to_bytes` streamed into the hasher
+    pub fn id(&self) -> TxId {
+        let mut writer = HashWriter::new();
+        writer.input(&[TRANSACTION_VERSION]);
+        // only fails for types serde cannot represent, not the case here
+        #[allow(clippy::expect_used)]
+        encoding::encode_into(&mut writer, self).expect("error serializing transaction");
+        TxId(writer.finish())
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.signature != Signature::default()
+    }
+
+    /// canonical binary encoding, the version byte followed by the fields,
+    /// also used to move unsigned transactions to an offline signer
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // only fails for types serde cannot represent, not the case here
+        #[allow(clippy::expect_used)]
+        let body = encoding::encode(self).expect("error serializing transaction");
+        [&[TRANSACTION_VERSION][..], &body].concat()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Transaction, DecodeError> {
+        let (version, body) = bytes.split_first().ok_or(DecodeError::Empty)?;
+        if *version != TRANSACTION_VERSION {
+            return Err(DecodeError::UnknownVersion(*version));
+        }
+        encoding::decode(body).map_err(|e| DecodeError::Malformed(e.to_string()))
+    }
+
+    /// `to_bytes` as hex, the form raw transactions are exchanged in
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_bytes())
+    }
+
+    pub fn from_hex(hex: &str) -> Result<Transaction, DecodeError> {
+        let bytes = hex::decode(hex.trim()).map_err(|e| DecodeError::Malformed(e.to_string()))?;
+        Transaction::from_bytes(&bytes)
+    }
+
+    pub fn set_signature(&mut self, signature: &Signature) {
+        self.signature = *signature;
+    }
+
+    pub fn sign(&mut self, private_key: &PrivateKey) -> Result<(), CryptoError> {
+        let hash = self.signing_hash();
         self.signature = sign_hash(&hash, private_key)?;
         Ok(())
     }
 
-    pub fn verify(&self) -> Result<(), String> {
-        let hash = self.hash();
+    pub fn cosignatures(&self) -> &[Signature] {
+        &self.cosignatures
+    }
+
+    /// Add a signature by a cosigner key of the sender validation, before
+    /// or after `sign`. Cosignatures are kept in ascending byte order, the
+    /// only one a validation accepts, so a relayer cannot reorder them into
+    /// another id.
+    pub fn cosign(&mut self, private_key: &PrivateKey) -> Result<(), CryptoError> {
+        let hash = self.signing_hash();
+        let signature = sign_hash(&hash, private_key)?;
+        let position = self
+            .cosignatures
+            .partition_point(|other| other.as_ref() < signature.as_ref());
+        self.cosignatures.insert(position, signature);
+        Ok(())
+    }
+
+    pub fn clear_cosignatures(&mut self) {
+        self.cosignatures.clear();
+    }
+
+    pub fn witness(&self) -> &[Vec<u8>] {
+        &self.witness
+    }
+
+    /// push `value` on top of the witness, the last value pushed is the
+    /// first the predicate pops
+    pub fn push_witness(&mut self, value: Vec<u8>) {
+        self.witness.push(value);
+    }
+
+    /// push a signature of the signing hash by `private_key`, for a
+    /// predicate checking it
+    pub fn push_witness_signature(&mut self, private_key: &PrivateKey) -> Result<(), CryptoError> {
+        let signature = sign_hash(&self.signing_hash(), private_key)?;
+        self.push_witness(signature.as_ref().to_vec());
+        Ok(())
+    }
+
+    pub fn clear_witness(&mut self) {
+        self.witness.clear();
+    }
+
+    pub fn verify(&self) -> Result<(), CryptoError> {
+        let hash = self.signing_hash();
         verify_signature(&self.from, &hash, &self.signature)
     }
 }
+
+/// Check the signatures of `transactions` but the coinbases, which are
+/// unsigned, spread over the rayon pool when the `parallel` feature is
+/// enabled. The error is the one of the first invalid transaction.
+pub fn verify_transactions(transactions: &[Transaction]) -> Result<(), CryptoError> {
+    let verify = |tx: &Transaction| {
+        if tx.is_coinbase() {
+            Ok(())
+        } else {
+            tx.verify()
+        }
+    };
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        transactions
+            .par_iter()
+            .map(verify)
+            .find_first(|result| result.is_err())
+            .unwrap_or(Ok(()))
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        transactions.iter().try_for_each(verify)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keypair;
+    use crate::error::TxError;
+
+    #[test]
+    fn test_encoding_round_trip() {
+        let (private_key, from) = generate_keypair();
+        let (_, to) = generate_keypair();
+        let tx = Transaction::new_and_sign(&from, &to.address(), 5, 1, &private_key).unwrap();
+        let bytes = tx.to_bytes();
+        assert_eq!(bytes[0], TRANSACTION_VERSION);
+        assert_eq!(Transaction::from_bytes(&bytes).unwrap(), tx);
+        assert_eq!(Transaction::from_hex(&tx.to_hex()).unwrap(), tx);
+        assert!(matches!(
+            Transaction::from_hex("0g"),
+            Err(DecodeError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_reject_malformed_encoding() {
+        let (private_key, from) = generate_keypair();
+        let tx = Transaction::new_and_sign(&from, &from.address(), 5, 1, &private_key).unwrap();
+        let bytes = tx.to_bytes();
+        for len in 0..bytes.len() {
+            assert!(Transaction::from_bytes(&bytes[..len]).is_err());
+        }
+        let trailing = [&bytes[..], &[0]].concat();
+        assert!(Transaction::from_bytes(&trailing).is_err());
+        // the sender key is right after the version byte
+        let mut invalid_key = bytes.clone();
+        invalid_key[1] = 5;
+        assert!(Transaction::from_bytes(&invalid_key).is_err());
+    }
+
+    #[test]
+    fn test_unknown_version_is_opaque() {
+        let (_, from) = generate_keypair();
+        let mut bytes = Transaction::new_burn(&from, 5, 0).to_bytes();
+        bytes[0] = TRANSACTION_VERSION + 1;
+        assert_eq!(
+            Transaction::from_bytes(&bytes),
+            Err(DecodeError::UnknownVersion(TRANSACTION_VERSION + 1))
+        );
+        let opaque = VersionedTransaction::decode(&bytes).unwrap();
+        assert_eq!(opaque.version(), TRANSACTION_VERSION + 1);
+        assert_eq!(opaque.to_bytes(), bytes);
+        assert_eq!(Transaction::from_bytes(&[]), Err(DecodeError::Empty));
+    }
+
+    #[test]
+    fn test_verify_transactions() {
+        let (private_key, from) = generate_keypair();
+        let mut transactions: Vec<_> = (0..64)
+            .map(|nonce| {
+                Transaction::new_and_sign(&from, &from.address(), 5, nonce, &private_key).unwrap()
+            })
+            .collect();
+        transactions.push(Transaction::new_coinbase(&from, 50, 1));
+        verify_transactions(&transactions).unwrap();
+
+        transactions[10].set_fee(1);
+        transactions[20] = Transaction::new(&from, &from.address(), 5, 20);
+        assert_eq!(
+            verify_transactions(&transactions),
+            transactions[10].verify()
+        );
+        assert!(verify_transactions(&transactions[11..]).is_err());
+    }
+
+    #[test]
+    fn test_cosignatures_are_sorted() {
+        let (private_key, from) = generate_keypair();
+        let keys = [generate_keypair(), generate_keypair()];
+        let validation = Validation {
+            cosigners: keys.iter().map(|(_, public_key)| *public_key).collect(),
+            threshold: 2,
+            ..Validation::default()
+        };
+        let mut tx = Transaction::new_and_sign(&from, &from.address(), 5, 0, &private_key).unwrap();
+        for (key, _) in keys.iter().rev() {
+            tx.cosign(key).unwrap();
+        }
+        let [first, second] = [tx.cosignatures[0], tx.cosignatures[1]];
+        assert!(first.as_ref() < second.as_ref());
+        validation.check(&tx, 0, 0).unwrap();
+
+        // the same signatures swapped by a relayer, another id
+        let mut swapped = tx.clone();
+        swapped.cosignatures.reverse();
+        assert_ne!(swapped.id(), tx.id());
+        assert_eq!(
+            validation.check(&swapped, 0, 0),
+            Err(TxError::UnsortedCosignatures)
+        );
+    }
+}
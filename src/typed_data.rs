@@ -0,0 +1,199 @@
+use crate::crypto::{
+    sign_hash, verify_signature, CryptoError, Hash, PrivateKey, PublicKey, Signature,
+};
+
+/// Prefix of typed data signing preimages, distinct from transactions and
+/// from `sign_message` (0x19 followed by 'C')
+const TYPED_DATA_PREFIX: &[u8] = &[0x19, 0x01];
+
+/// A struct that can be signed as typed structured data, in the spirit of
+/// EIP-712 with sha256 in place of keccak.
+pub trait TypedData {
+    /// `Name(type1 field1,type2 field2)` followed by the encodings of the
+    /// referenced struct types, sorted by name
+    fn encode_type() -> String
+    where
+        Self: Sized;
+
+    /// fields encoded in declaration order with `StructEncoder`
+    fn encode_data(&self) -> Vec<u8>;
+}
+
+pub fn type_hash<T: TypedData>() -> Hash {
+    Hash::from(T::encode_type().as_bytes())
+}
+
+pub fn hash_struct<T: TypedData>(value: &T) -> Hash {
+    let type_hash = type_hash::<T>();
+    Hash::from_parts(&[type_hash.as_ref(), &value.encode_data()])
+}
+
+/// Every field takes 32 bytes: integers are left padded, dynamic values
+/// (bytes, strings, public keys) are hashed and nested structs are replaced
+/// by their `hash_struct`
+#[derive(Default)]
+pub struct StructEncoder {
+    data: Vec<u8>,
+}
+
+impl StructEncoder {
+    pub fn new() -> StructEncoder {
+        StructEncoder::default()
+    }
+
+    pub fn u64(mut self, value: u64) -> StructEncoder {
+        self.data.extend_from_slice(&[0; 24]);
+        self.data.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn bool(self, value: bool) -> StructEncoder {
+        self.u64(value as u64)
+    }
+
+    pub fn hash(mut self, value: &Hash) -> StructEncoder {
+        self.data.extend_from_slice(value.as_ref());
+        self
+    }
+
+    pub fn bytes(self, value: &[u8]) -> StructEncoder {
+        self.hash(&Hash::from(value))
+    }
+
+    pub fn string(self, value: &str) -> StructEncoder {
+        self.bytes(value.as_bytes())
+    }
+
+    pub fn public_key(self, value: &PublicKey) -> StructEncoder {
+        self.bytes(value.as_ref())
+    }
+
+    pub fn typed<T: TypedData>(self, value: &T) -> StructEncoder {
+        self.hash(&hash_struct(value))
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Binds signatures to one application and one chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Domain {
+    pub name: String,
+    pub version: String,
+    pub chain_id: u64,
+}
+
+impl TypedData for Domain {
+    fn encode_type() -> String {
+        "Domain(string name,string version,uint64 chainId)".to_string()
+    }
+
+    fn encode_data(&self) -> Vec<u8> {
+        StructEncoder::new()
+            .string(&self.name)
+            .string(&self.version)
+            .u64(self.chain_id)
+            .finish()
+    }
+}
+
+impl Domain {
+    pub fn separator(&self) -> Hash {
+        hash_struct(self)
+    }
+}
+
+/// hash of the prefix, the domain separator and the message struct hash
+pub fn typed_data_hash<T: TypedData>(domain: &Domain, message: &T) -> Hash {
+    let separator = domain.separator();
+    let struct_hash = hash_struct(message);
+    Hash::from_parts(&[TYPED_DATA_PREFIX, separator.as_ref(), struct_hash.as_ref()])
+}
+
+pub fn sign_typed_data<T: TypedData>(
+    domain: &Domain,
+    message: &T,
+    private_key: &PrivateKey,
+) -> Result<Signature, CryptoError> {
+    sign_hash(&typed_data_hash(domain, message), private_key)
+}
+
+pub fn verify_typed_data<T: TypedData>(
+    domain: &Domain,
+    message: &T,
+    public_key: &PublicKey,
+    signature: &Signature,
+) -> Result<(), CryptoError> {
+    verify_signature(public_key, &typed_data_hash(domain, message), signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keypair;
+
+    struct Person {
+        name: String,
+        key: PublicKey,
+    }
+
+    struct Order {
+        buyer: Person,
+        amount: u64,
+    }
+
+    impl TypedData for Person {
+        fn encode_type() -> String {
+            "Person(string name,bytes key)".to_string()
+        }
+
+        fn encode_data(&self) -> Vec<u8> {
+            StructEncoder::new()
+                .string(&self.name)
+                .public_key(&self.key)
+                .finish()
+        }
+    }
+
+    impl TypedData for Order {
+        fn encode_type() -> String {
+            format!("Order(Person buyer,uint64 amount){}", Person::encode_type())
+        }
+
+        fn encode_data(&self) -> Vec<u8> {
+            StructEncoder::new()
+                .typed(&self.buyer)
+                .u64(self.amount)
+                .finish()
+        }
+    }
+
+    #[test]
+    fn test_sign_and_verify_typed_data() {
+        let (private_key, public_key) = generate_keypair();
+        let order = Order {
+            buyer: Person {
+                name: "alice".to_string(),
+                key: public_key,
+            },
+            amount: 10,
+        };
+        let domain = Domain {
+            name: "shop".to_string(),
+            version: "1".to_string(),
+            chain_id: 1,
+        };
+        assert_eq!(order.encode_data().len(), 64);
+
+        let signature = sign_typed_data(&domain, &order, &private_key).unwrap();
+        verify_typed_data(&domain, &order, &public_key, &signature).unwrap();
+
+        let other_chain = Domain {
+            chain_id: 2,
+            ..domain.clone()
+        };
+        assert!(verify_typed_data(&other_chain, &order, &public_key, &signature).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use std::fmt;
+
+use crate::crypto::{CryptoError, Hash, Signature};
+use crate::transaction::{DecodeError, Transaction};
+
+pub const UR_TRANSACTION: &str = "coreledger-tx";
+pub const UR_SIGNATURE: &str = "coreledger-sig";
+
+/// Split `payload` into parts of the form `UR:<TYPE>/<SEQ>-<TOTAL>/<CHECKSUM>/<DATA>`.
+/// Everything is uppercase hex so each part fits the QR alphanumeric mode.
+pub fn encode_ur(ur_type: &str, payload: &[u8], max_fragment_len: usize) -> Vec<String> {
+    let checksum = checksum(payload);
+    let fragments: Vec<&[u8]> = if payload.is_empty() {
+        vec![&[]]
+    } else {
+        payload.chunks(max_fragment_len.max(1)).collect()
+    };
+    let total = fragments.len();
+    fragments
+        .iter()
+        .enumerate()
+        .map(|(i, fragment)| {
+            format!(
+                "UR:{}/{}-{}/{}/{}",
+                ur_type.to_uppercase(),
+                i + 1,
+                total,
+                checksum,
+                hex::encode_upper(fragment)
+            )
+        })
+        .collect()
+}
+
+pub fn encode_transaction(tx: &Transaction, max_fragment_len: usize) -> Vec<String> {
+    encode_ur(UR_TRANSACTION, &tx.to_bytes(), max_fragment_len)
+}
+
+pub fn encode_signature(signature: &Signature, max_fragment_len: usize) -> Vec<String> {
+    encode_ur(UR_SIGNATURE, signature.as_ref(), max_fragment_len)
+}
+
+fn checksum(payload: &[u8]) -> String {
+    hex::encode_upper(&Hash::from(payload).as_ref()[..4])
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UrError {
+    Crypto(CryptoError),
+    Decode(DecodeError),
+    MissingScheme,
+    /// not of the form `UR:<TYPE>/<SEQ>-<TOTAL>/<CHECKSUM>/<DATA>`
+    InvalidPart,
+    InvalidSequence,
+    InvalidFragment,
+    /// a part with another type, checksum or part count than the first one
+    OtherPayload,
+    MissingParts,
+    InvalidChecksum,
+    /// the payload is of another type than the one requested
+    UnexpectedType(Option<String>),
+}
+
+impl fmt::Display for UrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UrError::Crypto(e) => write!(f, "{}", e),
+            UrError::Decode(e) => write!(f, "{}", e),
+            UrError::MissingScheme => write!(f, "Missing UR scheme"),
+            UrError::InvalidPart => write!(f, "Invalid UR part"),
+            UrError::InvalidSequence => write!(f, "Invalid UR sequence"),
+            UrError::InvalidFragment => write!(f, "Invalid UR fragment"),
+            UrError::OtherPayload => write!(f, "UR part belongs to another payload"),
+            UrError::MissingParts => write!(f, "Missing UR parts"),
+            UrError::InvalidChecksum => write!(f, "Invalid UR checksum"),
+            UrError::UnexpectedType(ur_type) => write!(
+                f,
+                "Unexpected UR type {}",
+                ur_type.as_deref().unwrap_or("none")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UrError {}
+
+struct UrPart {
+    ur_type: String,
+    seq: usize,
+    total: usize,
+    checksum: String,
+    fragment: Vec<u8>,
+}
+
+fn parse_part(part: &str) -> Result<UrPart, UrError> {
+    let part = part.trim().to_uppercase();
+    let rest = part.strip_prefix("UR:").ok_or(UrError::MissingScheme)?;
+    let fields: Vec<&str> = rest.split('/').collect();
+    if fields.len() != 4 {
+        return Err(UrError::InvalidPart);
+    }
+    let (seq, total) = fields[1].split_once('-').ok_or(UrError::InvalidSequence)?;
+    let seq: usize = seq.parse().map_err(|_| UrError::InvalidSequence)?;
+    let total: usize = total.parse().map_err(|_| UrError::InvalidSequence)?;
+    if seq == 0 || seq > total {
+        return Err(UrError::InvalidSequence);
+    }
+    Ok(UrPart {
+        ur_type: fields[0].to_lowercase(),
+        seq,
+        total,
+        checksum: fields[2].to_string(),
+        fragment: hex::decode(fields[3]).map_err(|_| UrError::InvalidFragment)?,
+    })
+}
+
+/// Collects UR parts in any order, e.g. frames scanned by a camera.
+#[derive(Default)]
+pub struct UrDecoder {
+    ur_type: Option<String>,
+    checksum: Option<String>,
+    fragments: Vec<Option<Vec<u8>>>,
+}
+
+impl UrDecoder {
+    pub fn new() -> UrDecoder {
+        UrDecoder::default()
+    }
+
+    /// Add a scanned part, duplicates are ignored
+    pub fn receive(&mut self, part: &str) -> Result<(), UrError> {
+        let part = parse_part(part)?;
+        match (&self.ur_type, &self.checksum) {
+            (Some(ur_type), Some(checksum)) => {
+                if *ur_type != part.ur_type
+                    || *checksum != part.checksum
+                    || self.fragments.len() != part.total
+                {
+                    return Err(UrError::OtherPayload);
+                }
+            }
+            _ => {
+                self.ur_type = Some(part.ur_type);
+                self.checksum = Some(part.checksum);
+                self.fragments = vec![None; part.total];
+            }
+        }
+        self.fragments[part.seq - 1].get_or_insert(part.fragment);
+        Ok(())
+    }
+
+    pub fn ur_type(&self) -> Option<&str> {
+        self.ur_type.as_deref()
+    }
+
+    /// number of parts received and expected
+    pub fn progress(&self) -> (usize, usize) {
+        let received = self.fragments.iter().filter(|f| f.is_some()).count();
+        (received, self.fragments.len())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        !self.fragments.is_empty() && self.fragments.iter().all(|f| f.is_some())
+    }
+
+    /// Reassemble the payload and check it against the checksum
+    pub fn assemble(&self) -> Result<Vec<u8>, UrError> {
+        if !self.is_complete() {
+            return Err(UrError::MissingParts);
+        }
+        let payload: Vec<u8> = self.fragments.iter().flatten().flatten().copied().collect();
+        if Some(checksum(&payload)) != self.checksum {
+            return Err(UrError::InvalidChecksum);
+        }
+        Ok(payload)
+    }
+
+    pub fn assemble_transaction(&self) -> Result<Transaction, UrError> {
+        if self.ur_type() != Some(UR_TRANSACTION) {
+            return Err(UrError::UnexpectedType(self.ur_type.clone()));
+        }
+        Transaction::from_bytes(&self.assemble()?).map_err(UrError::Decode)
+    }
+
+    pub fn assemble_signature(&self) -> Result<Signature, UrError> {
+        if self.ur_type() != Some(UR_SIGNATURE) {
+            return Err(UrError::UnexpectedType(self.ur_type.clone()));
+        }
+        Signature::try_from(self.assemble()?.as_ref()).map_err(UrError::Crypto)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keypair;
+
+    #[test]
+    fn test_transaction_round_trip_out_of_order() {
+        let (_, from) = generate_keypair();
+        let (_, to) = generate_keypair();
+        let tx = Transaction::new(&from, &to.address(), 42, 7);
+
+        let parts = encode_transaction(&tx, 16);
+        assert!(parts.len() > 1);
+        assert!(parts[0].starts_with("UR:CORELEDGER-TX/1-"));
+
+        let mut decoder = UrDecoder::new();
+        for part in parts.iter().rev() {
+            assert!(!decoder.is_complete());
+            decoder.receive(part).unwrap();
+        }
+        decoder.receive(&parts[0]).unwrap();
+        assert_eq!(decoder.assemble_transaction().unwrap(), tx);
+    }
+
+    #[test]
+    fn test_reject_mixed_payloads() {
+        let parts_a = encode_ur(UR_TRANSACTION, b"first payload", 4);
+        let parts_b = encode_ur(UR_TRANSACTION, b"other payload", 4);
+        let mut decoder = UrDecoder::new();
+        decoder.receive(&parts_a[0]).unwrap();
+        assert_eq!(decoder.receive(&parts_b[1]), Err(UrError::OtherPayload));
+    }
+}
@@ -0,0 +1,351 @@
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{verify_signature, PublicKey};
+use crate::error::TxError;
+use crate::script::{self, Script, ScriptContext};
+use crate::transaction::Transaction;
+
+/// most cosigners of an account
+pub const MAX_COSIGNERS: usize = 16;
+/// most operations of a validation predicate
+pub const MAX_PREDICATE_OPS: usize = 64;
+
+/// Conditions an account registers with a `TransactionKind::SetValidation`
+/// transaction, every later transaction it sends must meet all of them on
+/// top of carrying its signature. Changing them is a transaction like any
+/// other, so the current conditions must accept it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validation {
+    /// keys kept apart from the account key, `threshold` of them must
+    /// cosign every transaction: one for a second factor, M of N for a
+    /// multisig treasury
+    pub cosigners: Vec<PublicKey>,
+    pub threshold: u32,
+    /// most a transaction may take from the account, amount and fee, a
+    /// grant counts for its whole cap
+    pub spend_limit: Option<u64>,
+    /// Script the witness of the transaction is handed to, instead of
+    /// cosignatures matched against `cosigners`, evaluated against the
+    /// signing hash of the transaction and the index of the block including
+    /// it. Covers conditions cosigners cannot express, e.g. a key usable
+    /// only after some block or a hash lock.
+    pub predicate: Option<Script>,
+}
+
+impl Validation {
+    /// no condition, registering it removes the previous ones
+    pub fn is_empty(&self) -> bool {
+        self.cosigners.is_empty() && self.spend_limit.is_none() && self.predicate.is_none()
+    }
+
+    /// at most `MAX_COSIGNERS` distinct cosigners and a threshold they can
+    /// reach, of at least 1 if there are cosigners, or a predicate of at
+    /// most `MAX_PREDICATE_OPS` operations and no cosigners
+    pub fn is_valid(&self) -> bool {
+        if let Some(predicate) = &self.predicate {
+            return self.cosigners.is_empty()
+                && self.threshold == 0
+                && predicate.len() <= MAX_PREDICATE_OPS;
+        }
+        let distinct = self
+            .cosigners
+            .iter()
+            .enumerate()
+            .all(|(i, cosigner)| !self.cosigners[..i].contains(cosigner));
+        let threshold = self.threshold as usize;
+        distinct
+            && self.cosigners.len() <= MAX_COSIGNERS
+            && threshold <= self.cosigners.len()
+            && (threshold > 0 || self.cosigners.is_empty())
+    }
+
+    /// `tx` meets the conditions in the block at `block_index`, the
+    /// predicate spending at most `gas_limit`
+    pub fn check(&self, tx: &Transaction, block_index: u64, gas_limit: u64) -> Result<(), TxError> {
+        // a granted fee is paid by the granter
+        let fee = if tx.fee_granter().is_some() {
+            0
+        } else {
+            tx.fee()
+        };
+        let spent = tx.amount().saturating_add(fee);
+        match &self.predicate {
+            Some(predicate) => {
+                if !tx.cosignatures().is_empty() {
+                    return Err(TxError::TooManyCosignatures);
+                }
+                let context = ScriptContext {
+                    hash: tx.signing_hash(),
+                    block_index,
+                    spent,
+                };
+                script::evaluate(predicate, tx.witness(), &context, gas_limit)
+                    .map_err(TxError::PredicateFailed)?;
+            }
+            None if !tx.witness().is_empty() => return Err(TxError::UnexpectedWitness),
+            None => self.check_cosignatures(tx)?,
+        }
+        if let Some(limit) = self.spend_limit {
+            if spent > limit {
+                return Err(TxError::SpendLimitExceeded { limit, spent });
+            }
+        }
+        Ok(())
+    }
+
+    fn check_cosignatures(&self, tx: &Transaction) -> Result<(), TxError> {
+        let cosignatures = tx.cosignatures();
+        if cosignatures.len() > self.cosigners.len() {
+            return Err(TxError::TooManyCosignatures);
+        }
+        if cosignatures
+            .windows(2)
+            .any(|pair| pair[0].as_ref() > pair[1].as_ref())
+        {
+            return Err(TxError::UnsortedCosignatures);
+        }
+        // every signature must be by a distinct cosigner: the cosignatures
+        // are part of the id, nobody else may add one
+        let hash = tx.signing_hash();
+        let mut signed_by = vec![false; self.cosigners.len()];
+        for signature in cosignatures {
+            let cosigner = self.cosigners.iter().enumerate().position(|(i, cosigner)| {
+                !signed_by[i] && verify_signature(cosigner, &hash, signature).is_ok()
+            });
+            match cosigner {
+                Some(i) => signed_by[i] = true,
+                None => return Err(TxError::InvalidCosignature),
+            }
+        }
+        let signed = cosignatures.len();
+        if signed < self.threshold as usize {
+            return Err(TxError::NotEnoughCosignatures {
+                threshold: self.threshold,
+                signed,
+            });
+        }
+        Ok(())
+    }
+
+    /// bytes covered by the signature of the registering transaction
+    pub(crate) fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = (self.cosigners.len() as u64).to_be_bytes().to_vec();
+        for cosigner in &self.cosigners {
+            bytes.extend_from_slice(cosigner.as_ref());
+        }
+        bytes.extend_from_slice(&self.threshold.to_be_bytes());
+        match self.spend_limit {
+            Some(limit) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&limit.to_be_bytes());
+            }
+            None => bytes.push(0),
+        }
+        match &self.predicate {
+            Some(predicate) => {
+                bytes.push(1);
+                bytes.extend(script::signing_bytes(predicate));
+            }
+            None => bytes.push(0),
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::DEFAULT_PREDICATE_GAS_LIMIT;
+    use crate::crypto::generate_keypair_from_secret;
+    use crate::script::{Op, ScriptError};
+
+    #[test]
+    fn test_check_validation() {
+        let (private_key, public_key) = generate_keypair_from_secret(b"validation");
+        let (cosigner_key, cosigner) = generate_keypair_from_secret(b"cosigner");
+        let validation = Validation {
+            cosigners: vec![cosigner],
+            threshold: 1,
+            spend_limit: Some(10),
+            predicate: None,
+        };
+        let mut tx =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 8, 0, &private_key)
+                .unwrap();
+        let missing = Err(TxError::NotEnoughCosignatures {
+            threshold: 1,
+            signed: 0,
+        });
+        assert_eq!(validation.check(&tx, 0, 0), missing);
+        tx.cosign(&private_key).unwrap();
+        assert_eq!(
+            validation.check(&tx, 0, 0),
+            Err(TxError::InvalidCosignature)
+        );
+        tx.cosign(&cosigner_key).unwrap();
+        assert_eq!(
+            validation.check(&tx, 0, 0),
+            Err(TxError::TooManyCosignatures)
+        );
+        tx.clear_cosignatures();
+        tx.cosign(&cosigner_key).unwrap();
+        validation.check(&tx, 0, 0).unwrap();
+
+        tx.set_fee(3);
+        assert_eq!(
+            validation.check(&tx, 0, 0),
+            Err(TxError::InvalidCosignature)
+        );
+        tx.clear_cosignatures();
+        tx.cosign(&cosigner_key).unwrap();
+        assert_eq!(
+            validation.check(&tx, 0, 0),
+            Err(TxError::SpendLimitExceeded {
+                limit: 10,
+                spent: 11
+            })
+        );
+        assert!(Validation::default().is_empty());
+        assert_eq!(
+            Validation::default().check(&tx, 0, 0),
+            Err(TxError::TooManyCosignatures)
+        );
+    }
+
+    #[test]
+    fn test_multisig_threshold() {
+        let (private_key, public_key) = generate_keypair_from_secret(b"treasury");
+        let keys: Vec<_> = [b"a", b"b", b"c"]
+            .iter()
+            .map(|secret| generate_keypair_from_secret(&secret[..]))
+            .collect();
+        let validation = Validation {
+            cosigners: keys.iter().map(|(_, public_key)| *public_key).collect(),
+            threshold: 2,
+            spend_limit: None,
+            predicate: None,
+        };
+        assert!(validation.is_valid());
+        let mut tx =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 8, 0, &private_key)
+                .unwrap();
+        tx.cosign(&keys[2].0).unwrap();
+        assert_eq!(
+            validation.check(&tx, 0, 0),
+            Err(TxError::NotEnoughCosignatures {
+                threshold: 2,
+                signed: 1
+            })
+        );
+        tx.cosign(&keys[2].0).unwrap();
+        assert_eq!(
+            validation.check(&tx, 0, 0),
+            Err(TxError::InvalidCosignature)
+        );
+        tx.clear_cosignatures();
+        tx.cosign(&keys[2].0).unwrap();
+        tx.cosign(&keys[0].0).unwrap();
+        validation.check(&tx, 0, 0).unwrap();
+
+        // a third party appending a signature changes the id, rejected
+        let (junk_key, _) = generate_keypair_from_secret(b"junk");
+        let mut junk = tx.clone();
+        junk.cosign(&junk_key).unwrap();
+        assert_ne!(junk.id(), tx.id());
+        assert_eq!(
+            validation.check(&junk, 0, 0),
+            Err(TxError::InvalidCosignature)
+        );
+
+        let invalid = |cosigners: Vec<PublicKey>, threshold| {
+            !Validation {
+                cosigners,
+                threshold,
+                spend_limit: None,
+                predicate: None,
+            }
+            .is_valid()
+        };
+        assert!(invalid(vec![keys[0].1], 2));
+        assert!(invalid(vec![keys[0].1], 0));
+        assert!(invalid(vec![keys[0].1, keys[0].1], 1));
+        assert!(invalid(vec![keys[0].1; MAX_COSIGNERS + 1], 1));
+    }
+
+    #[test]
+    fn test_predicate() {
+        let (private_key, public_key) = generate_keypair_from_secret(b"validation");
+        let (recovery_key, recovery) = generate_keypair_from_secret(b"recovery");
+        // the recovery key alone, from block 100 on
+        let validation = Validation {
+            predicate: Some(vec![
+                Op::PushNumber(100),
+                Op::CheckLockTime,
+                Op::Push(recovery.as_ref().to_vec()),
+                Op::CheckSig,
+            ]),
+            ..Validation::default()
+        };
+        assert!(validation.is_valid());
+        let failed = |e| Err(TxError::PredicateFailed(e));
+        let mut tx =
+            Transaction::new_and_sign(&public_key, &public_key.address(), 8, 0, &private_key)
+                .unwrap();
+        let gas = DEFAULT_PREDICATE_GAS_LIMIT;
+        assert_eq!(
+            validation.check(&tx, 100, gas),
+            failed(ScriptError::StackUnderflow)
+        );
+        tx.push_witness_signature(&private_key).unwrap();
+        assert_eq!(
+            validation.check(&tx, 100, gas),
+            failed(ScriptError::Rejected)
+        );
+        tx.clear_witness();
+        tx.push_witness_signature(&recovery_key).unwrap();
+        assert_eq!(
+            validation.check(&tx, 99, gas),
+            failed(ScriptError::Locked(100))
+        );
+        assert_eq!(
+            validation.check(&tx, 100, 50),
+            failed(ScriptError::OutOfGas)
+        );
+        validation.check(&tx, 100, gas).unwrap();
+        // the witness is not signed, anyone can add to it so the predicate
+        // must use all of it
+        let mut padded = tx.clone();
+        padded.push_witness(vec![1]);
+        assert_eq!(
+            validation.check(&padded, 100, gas),
+            failed(ScriptError::Rejected)
+        );
+        padded.clear_witness();
+        padded.push_witness(vec![1]);
+        padded.push_witness_signature(&recovery_key).unwrap();
+        assert_eq!(
+            validation.check(&padded, 100, gas),
+            failed(ScriptError::UncleanStack(1))
+        );
+        let mut cosigned = tx.clone();
+        cosigned.cosign(&recovery_key).unwrap();
+        assert_eq!(
+            validation.check(&cosigned, 100, gas),
+            Err(TxError::TooManyCosignatures)
+        );
+
+        let with_cosigner = Validation {
+            cosigners: vec![recovery],
+            threshold: 1,
+            ..validation.clone()
+        };
+        assert!(!with_cosigner.is_valid());
+        let too_long = Validation {
+            predicate: Some(vec![Op::Dup; MAX_PREDICATE_OPS + 1]),
+            ..Validation::default()
+        };
+        assert!(!too_long.is_valid());
+    }
+}
@@ -0,0 +1,426 @@
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
+use bytes::Bytes;
+
+use crate::address::{Address, ADDRESS_LEN};
+use crate::crypto::{CryptoError, Hash, PublicKey, Signature};
+use crate::encoding;
+use crate::script::{self, Script};
+use crate::transaction::{DecodeError, Transaction, TxId, TRANSACTION_VERSION};
+
+const KEY_LEN: usize = 33;
+const HASH_LEN: usize = 32;
+const SIGNATURE_LEN: usize = 64;
+/// bincode variant indexes of `TransactionKind`
+const TRANSFER_TAG: u32 = 0;
+const BURN_TAG: u32 = 1;
+const COINBASE_TAG: u32 = 2;
+const GRANT_FEES_TAG: u32 = 3;
+const SET_VALIDATION_TAG: u32 = 4;
+/// bincode variant indexes of `Op` with a value, the others have none
+const PUSH_OP_TAG: u32 = 0;
+const PUSH_NUMBER_OP_TAG: u32 = 1;
+const LAST_OP_TAG: u32 = 14;
+/// offset of the kind payload, after the version, the sender and the tag
+const PAYLOAD_OFFSET: usize = 1 + KEY_LEN + 4;
+
+/// Transaction read in place from a received buffer. Only the layout is
+/// checked when parsing, keys and the signature are parsed when accessed,
+/// so relaying or indexing a transaction never copies its fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionView {
+    bytes: Bytes,
+    /// offset of the amount, right after the kind
+    amount_offset: usize,
+    /// offset of the reference, right after the fee granter
+    reference_offset: usize,
+    /// offset of the signature, right after the reference
+    signature_offset: usize,
+    /// offset of the witness, right after the cosignatures
+    witness_offset: usize,
+    tag: u32,
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut buffer = [0; 8];
+    buffer.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(buffer)
+}
+
+fn truncated() -> DecodeError {
+    DecodeError::Malformed("Truncated transaction".to_string())
+}
+
+fn read_tag(bytes: &[u8], offset: usize) -> Result<u32, DecodeError> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|tag| u32::from_le_bytes([tag[0], tag[1], tag[2], tag[3]]))
+        .ok_or_else(truncated)
+}
+
+/// length of the encoding of a sequence of `len` bytes values at `offset`
+fn vec_len(bytes: &[u8], offset: usize, len: usize) -> Result<usize, DecodeError> {
+    let count = bytes
+        .get(offset..offset + 8)
+        .map(|_| read_u64(bytes, offset))
+        .ok_or_else(truncated)?;
+    // bounded by the buffer so the size cannot overflow
+    if count > (bytes.len() / len.max(1)) as u64 {
+        return Err(truncated());
+    }
+    Ok(8 + count as usize * len)
+}
+
+/// length of the encoding of a sequence of byte strings at `offset`
+fn byte_strings_len(bytes: &[u8], offset: usize) -> Result<usize, DecodeError> {
+    // every value takes at least its length
+    let count = (vec_len(bytes, offset, 8)? - 8) / 8;
+    let mut end = offset + 8;
+    for _ in 0..count {
+        end += vec_len(bytes, end, 1)?;
+    }
+    Ok(end - offset)
+}
+
+/// length of the encoding of an optional script at `offset`
+fn predicate_len(bytes: &[u8], offset: usize) -> Result<usize, DecodeError> {
+    match bytes.get(offset) {
+        Some(0) => return Ok(1),
+        Some(1) => {}
+        Some(tag) => return Err(DecodeError::Malformed(format!("Invalid option {}", tag))),
+        None => return Err(truncated()),
+    }
+    // every operation takes at least its tag
+    let count = (vec_len(bytes, offset + 1, 4)? - 8) / 4;
+    let mut end = offset + 1 + 8;
+    for _ in 0..count {
+        let tag = read_tag(bytes, end)?;
+        end += 4;
+        end += match tag {
+            PUSH_OP_TAG => vec_len(bytes, end, 1)?,
+            PUSH_NUMBER_OP_TAG => 8,
+            tag if tag <= LAST_OP_TAG => 0,
+            tag => return Err(DecodeError::Malformed(format!("Unknown script op {}", tag))),
+        };
+    }
+    Ok(end - offset)
+}
+
+/// length of the encoding of an `Option` of a `len` bytes value at `offset`
+fn option_len(bytes: &[u8], offset: usize, len: usize) -> Result<usize, DecodeError> {
+    match bytes.get(offset) {
+        Some(0) => Ok(1),
+        Some(1) => Ok(1 + len),
+        Some(tag) => Err(DecodeError::Malformed(format!("Invalid option {}", tag))),
+        None => Err(truncated()),
+    }
+}
+
+impl TransactionView {
+    pub fn parse(bytes: Bytes) -> Result<TransactionView, DecodeError> {
+        let version = *bytes.first().ok_or(DecodeError::Empty)?;
+        if version != TRANSACTION_VERSION {
+            return Err(DecodeError::UnknownVersion(version));
+        }
+        let tag_offset = 1 + KEY_LEN;
+        let tag = read_tag(&bytes, tag_offset)?;
+        let payload_len = match tag {
+            TRANSFER_TAG => ADDRESS_LEN,
+            GRANT_FEES_TAG => KEY_LEN + 8,
+            SET_VALIDATION_TAG => {
+                let threshold_offset = PAYLOAD_OFFSET + vec_len(&bytes, PAYLOAD_OFFSET, KEY_LEN)?;
+                let limit_offset = threshold_offset + 4;
+                let predicate_offset = limit_offset + option_len(&bytes, limit_offset, 8)?;
+                predicate_offset - PAYLOAD_OFFSET + predicate_len(&bytes, predicate_offset)?
+            }
+            BURN_TAG | COINBASE_TAG => 0,
+            tag => return Err(DecodeError::Malformed(format!("Unknown kind {}", tag))),
+        };
+        let amount_offset = PAYLOAD_OFFSET + payload_len;
+        let granter_offset = amount_offset + 24;
+        let reference_offset = granter_offset + option_len(&bytes, granter_offset, KEY_LEN)?;
+        let signature_offset = reference_offset + option_len(&bytes, reference_offset, HASH_LEN)?;
+        let cosignatures_offset = signature_offset + SIGNATURE_LEN;
+        let witness_offset =
+            cosignatures_offset + vec_len(&bytes, cosignatures_offset, SIGNATURE_LEN)?;
+        let expected = witness_offset + byte_strings_len(&bytes, witness_offset)?;
+        if bytes.len() != expected {
+            return Err(DecodeError::Malformed(format!(
+                "Expected {} bytes, got {}",
+                expected,
+                bytes.len()
+            )));
+        }
+        Ok(TransactionView {
+            bytes,
+            amount_offset,
+            reference_offset,
+            signature_offset,
+            witness_offset,
+            tag,
+        })
+    }
+
+    fn sender_bytes(&self) -> &[u8] {
+        &self.bytes[1..1 + KEY_LEN]
+    }
+
+    /// recipient of a transfer, grantee of a grant
+    fn key_payload_bytes(&self) -> &[u8] {
+        match self.tag {
+            TRANSFER_TAG => &self.bytes[PAYLOAD_OFFSET..PAYLOAD_OFFSET + ADDRESS_LEN],
+            GRANT_FEES_TAG => &self.bytes[PAYLOAD_OFFSET..PAYLOAD_OFFSET + KEY_LEN],
+            _ => &[],
+        }
+    }
+
+    fn fee_granter_bytes(&self) -> &[u8] {
+        &self.bytes[self.amount_offset + 25..self.reference_offset]
+    }
+
+    fn reference_bytes(&self) -> &[u8] {
+        &self.bytes[self.reference_offset + 1..self.signature_offset]
+    }
+
+    /// the kind payload as covered by the signature, integers big endian
+    fn kind_signing_bytes(&self) -> Vec<u8> {
+        match self.tag {
+            GRANT_FEES_TAG => {
+                let expires_at = read_u64(&self.bytes, self.amount_offset - 8);
+                [self.key_payload_bytes(), &expires_at.to_be_bytes()].concat()
+            }
+            SET_VALIDATION_TAG => {
+                let mut payload = self.bytes[PAYLOAD_OFFSET..self.amount_offset].to_vec();
+                let count = read_u64(&payload, 0) as usize;
+                payload[..8].reverse();
+                let threshold_offset = 8 + count * KEY_LEN;
+                payload[threshold_offset..threshold_offset + 4].reverse();
+                let limit_offset = threshold_offset + 4;
+                let mut predicate_offset = limit_offset + 1;
+                if payload[limit_offset] == 1 {
+                    payload[predicate_offset..predicate_offset + 8].reverse();
+                    predicate_offset += 8;
+                }
+                payload.truncate(predicate_offset);
+                // cannot fail, the layout of the predicate is checked by `parse`
+                let predicate = encoding::decode::<Option<Script>>(
+                    &self.bytes[PAYLOAD_OFFSET + predicate_offset..self.amount_offset],
+                );
+                match predicate {
+                    Ok(Some(predicate)) => {
+                        payload.push(1);
+                        payload.extend(script::signing_bytes(&predicate));
+                    }
+                    _ => payload.push(0),
+                }
+                payload
+            }
+            _ => self.key_payload_bytes().to_vec(),
+        }
+    }
+
+    pub fn from(&self) -> Result<PublicKey, CryptoError> {
+        PublicKey::try_from(self.sender_bytes())
+    }
+
+    pub fn to(&self) -> Result<Option<Address>, CryptoError> {
+        if self.tag != TRANSFER_TAG {
+            return Ok(None);
+        }
+        Address::from_slice(self.key_payload_bytes()).map(Some)
+    }
+
+    pub fn fee_granter(&self) -> Result<Option<PublicKey>, CryptoError> {
+        match self.fee_granter_bytes() {
+            [] => Ok(None),
+            bytes => PublicKey::try_from(bytes).map(Some),
+        }
+    }
+
+    pub fn reference(&self) -> Result<Option<Hash>, CryptoError> {
+        match self.reference_bytes() {
+            [] => Ok(None),
+            bytes => Hash::from_slice(bytes).map(Some),
+        }
+    }
+
+    pub fn is_burn(&self) -> bool {
+        self.tag == BURN_TAG
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        self.tag == COINBASE_TAG
+    }
+
+    pub fn amount(&self) -> u64 {
+        read_u64(&self.bytes, self.amount_offset)
+    }
+
+    pub fn nonce(&self) -> u64 {
+        read_u64(&self.bytes, self.amount_offset + 8)
+    }
+
+    pub fn fee(&self) -> u64 {
+        read_u64(&self.bytes, self.amount_offset + 16)
+    }
+
+    pub fn signature(&self) -> Result<Signature, CryptoError> {
+        Signature::try_from(
+            &self.bytes[self.signature_offset..self.signature_offset + SIGNATURE_LEN],
+        )
+    }
+
+    pub fn cosignatures(&self) -> Result<Vec<Signature>, CryptoError> {
+        self.bytes[self.signature_offset + SIGNATURE_LEN + 8..self.witness_offset]
+            .chunks(SIGNATURE_LEN)
+            .map(Signature::try_from)
+            .collect()
+    }
+
+    /// values of the witness, in place in the buffer
+    pub fn witness(&self) -> Vec<&[u8]> {
+        let mut values = vec![];
+        let mut offset = self.witness_offset + 8;
+        while offset < self.bytes.len() {
+            let len = read_u64(&self.bytes, offset) as usize;
+            values.push(&self.bytes[offset + 8..offset + 8 + len]);
+            offset += 8 + len;
+        }
+        values
+    }
+
+    /// same value as `Transaction::signing_hash`, computed from the buffer
+    pub fn signing_hash(&self) -> Hash {
+        // the signing tag of a kind is its variant index
+        Hash::from_parts(&[
+            self.sender_bytes(),
+            &[self.tag as u8],
+            &self.kind_signing_bytes(),
+            &self.amount().to_be_bytes(),
+            &self.nonce().to_be_bytes(),
+            &self.fee().to_be_bytes(),
+            self.fee_granter_bytes(),
+            self.reference_bytes(),
+        ])
+    }
+
+    /// same value as `Transaction::id`, the encoding is canonical
+    pub fn id(&self) -> TxId {
+        TxId::from_hash(Hash::from(self.bytes.as_ref()))
+    }
+
+    pub fn as_bytes(&self) -> &Bytes {
+        &self.bytes
+    }
+
+    /// full decode, validating every field
+    pub fn to_transaction(&self) -> Result<Transaction, DecodeError> {
+        Transaction::from_bytes(&self.bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_keypair_from_secret;
+    use crate::script::Op;
+    use crate::transaction::TransactionKind;
+    use crate::validation::Validation;
+
+    #[test]
+    fn test_view_matches_decoded_transaction() {
+        let (private_key, public_key) = generate_keypair_from_secret(b"view");
+        let (_, other) = generate_keypair_from_secret(b"other");
+        let transfer =
+            Transaction::new_and_sign(&public_key, &other.address(), 10, 3, &private_key).unwrap();
+        let mut burn = Transaction::new_burn(&public_key, 7, 4);
+        burn.set_fee(2);
+        burn.sign(&private_key).unwrap();
+        let coinbase = Transaction::new_coinbase(&public_key, 50, 9);
+        let grant = TransactionKind::GrantFees {
+            grantee: other,
+            expires_at: 20,
+        };
+        let mut grant = Transaction::with_kind(&public_key, grant, 100, 5);
+        grant.sign(&private_key).unwrap();
+        let mut granted = Transaction::new(&other, &public_key.address(), 1, 0);
+        granted.set_fee(3);
+        granted.set_fee_granter(Some(public_key));
+        granted.set_reference(Some(Hash::from(&b"invoice 7"[..])));
+        let validation = Validation {
+            cosigners: vec![public_key, other],
+            threshold: 2,
+            spend_limit: Some(1000),
+            predicate: None,
+        };
+        let mut validated = Transaction::with_kind(
+            &public_key,
+            TransactionKind::SetValidation(validation),
+            0,
+            6,
+        );
+        validated.sign(&private_key).unwrap();
+        validated.cosign(&private_key).unwrap();
+        validated.cosign(&private_key).unwrap();
+        let predicate = Validation {
+            predicate: Some(vec![
+                Op::Push(other.as_ref().to_vec()),
+                Op::PushNumber(3),
+                Op::PushSpent,
+                Op::LessOrEqual,
+                Op::Drop,
+                Op::CheckSig,
+            ]),
+            ..Validation::default()
+        };
+        let mut scripted =
+            Transaction::with_kind(&public_key, TransactionKind::SetValidation(predicate), 0, 8);
+        scripted.sign(&private_key).unwrap();
+        scripted.push_witness(vec![]);
+        scripted.push_witness_signature(&private_key).unwrap();
+        let cleared = Transaction::with_kind(
+            &public_key,
+            TransactionKind::SetValidation(Validation::default()),
+            0,
+            7,
+        );
+
+        for tx in [
+            transfer, burn, coinbase, grant, granted, validated, scripted, cleared,
+        ] {
+            let view = TransactionView::parse(Bytes::from(tx.to_bytes())).unwrap();
+            assert_eq!(view.from().unwrap(), tx.from());
+            assert_eq!(view.to().unwrap(), tx.to());
+            assert_eq!(view.is_burn(), tx.is_burn());
+            assert_eq!(view.is_coinbase(), tx.is_coinbase());
+            assert_eq!(view.amount(), tx.amount());
+            assert_eq!(view.nonce(), tx.nonce());
+            assert_eq!(view.fee(), tx.fee());
+            assert_eq!(view.fee_granter().unwrap(), tx.fee_granter());
+            assert_eq!(view.reference().unwrap(), tx.reference());
+            assert_eq!(view.signature().unwrap(), tx.signature());
+            assert_eq!(view.cosignatures().unwrap(), tx.cosignatures());
+            assert!(view.witness().iter().eq(tx.witness()));
+            assert_eq!(view.signing_hash(), tx.signing_hash());
+            assert_eq!(view.id(), tx.id());
+            assert_eq!(view.to_transaction().unwrap(), tx);
+        }
+    }
+
+    #[test]
+    fn test_view_checks_layout_and_parses_lazily() {
+        let (private_key, public_key) = generate_keypair_from_secret(b"view");
+        let tx = Transaction::new_and_sign(&public_key, &public_key.address(), 10, 0, &private_key)
+            .unwrap();
+        let bytes = tx.to_bytes();
+        assert!(TransactionView::parse(Bytes::from(bytes[..bytes.len() - 1].to_vec())).is_err());
+        assert!(TransactionView::parse(Bytes::new()).is_err());
+
+        let mut invalid_key = bytes.clone();
+        invalid_key[1] = 0x05;
+        let view = TransactionView::parse(Bytes::from(invalid_key)).unwrap();
+        assert_eq!(view.amount(), 10);
+        assert!(view.from().is_err());
+        assert!(view.to_transaction().is_err());
+    }
+}
@@ -0,0 +1,738 @@
+use std::fmt;
+
+use crate::address::Address;
+use crate::blockchain::Blockchain;
+use crate::consensus::Network;
+use crate::crypto::{
+    generate_keypair, generate_keypair_from_secret, CryptoError, Hash, PrivateKey, PublicKey,
+};
+use crate::error::TxError;
+use crate::policy::FeePolicy;
+use crate::signer::{LocalSigner, Signer, SignerError};
+use crate::transaction::{DecodeError, Transaction, TxId};
+
+const AIRDROP_MAX_ATTEMPTS: usize = 3;
+
+/// scheme of payment request URIs, `coreledger:<address>?amount=..&memo=..`
+pub const PAYMENT_URI_SCHEME: &str = "coreledger";
+/// first bytes of a payment reference holding a destination tag, the index
+/// follows big endian
+const DESTINATION_TAG_PREFIX: &[u8; 24] = b"core-ledger/dest-tag/v1\0";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalletError {
+    Crypto(CryptoError),
+    Decode(DecodeError),
+    Signer(SignerError),
+    /// refused by the chain
+    Transaction(TxError),
+    WatchOnly,
+    /// the transaction is from another account than the one of the wallet
+    NotFromWallet,
+    /// the payment request leaves the amount to the payer
+    MissingAmount,
+}
+
+impl fmt::Display for WalletError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WalletError::Crypto(e) => write!(f, "{}", e),
+            WalletError::Decode(e) => write!(f, "{}", e),
+            WalletError::Signer(e) => write!(f, "{}", e),
+            WalletError::Transaction(e) => write!(f, "{}", e),
+            WalletError::WatchOnly => write!(f, "Watch-only wallet cannot sign"),
+            WalletError::NotFromWallet => write!(f, "Transaction is not from this wallet"),
+            WalletError::MissingAmount => write!(f, "Payment request has no amount"),
+        }
+    }
+}
+
+impl std::error::Error for WalletError {}
+
+impl From<CryptoError> for WalletError {
+    fn from(e: CryptoError) -> Self {
+        WalletError::Crypto(e)
+    }
+}
+
+impl From<DecodeError> for WalletError {
+    fn from(e: DecodeError) -> Self {
+        WalletError::Decode(e)
+    }
+}
+
+impl From<SignerError> for WalletError {
+    fn from(e: SignerError) -> Self {
+        WalletError::Signer(e)
+    }
+}
+
+impl From<TxError> for WalletError {
+    fn from(e: TxError) -> Self {
+        WalletError::Transaction(e)
+    }
+}
+
+/// Why a payment request URI does not parse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaymentUriError {
+    MissingScheme,
+    UnsupportedScheme(String),
+    InvalidAddress,
+    InvalidAmount,
+    InvalidPercentEncoding,
+    InvalidMemo,
+    InvalidReference,
+    InvalidDestinationTag,
+    DuplicateParameter(String),
+    /// a `req-` parameter this parser does not know
+    UnsupportedRequiredParameter(String),
+}
+
+impl fmt::Display for PaymentUriError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaymentUriError::MissingScheme => write!(f, "Missing URI scheme"),
+            PaymentUriError::UnsupportedScheme(scheme) => {
+                write!(f, "Unsupported URI scheme: {}", scheme)
+            }
+            PaymentUriError::InvalidAddress => write!(f, "Invalid address"),
+            PaymentUriError::InvalidAmount => write!(f, "Invalid amount"),
+            PaymentUriError::InvalidPercentEncoding => write!(f, "Invalid percent encoding"),
+            PaymentUriError::InvalidMemo => write!(f, "Memo is not valid UTF-8"),
+            PaymentUriError::InvalidReference => write!(f, "Invalid payment reference"),
+            PaymentUriError::InvalidDestinationTag => write!(f, "Invalid destination tag"),
+            PaymentUriError::DuplicateParameter(key) => {
+                write!(f, "Duplicate parameter: {}", key)
+            }
+            PaymentUriError::UnsupportedRequiredParameter(key) => {
+                write!(f, "Unsupported required parameter: {}", key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PaymentUriError {}
+
+/// A wallet built from a public key only is watch-only: it tracks the
+/// account and builds unsigned transactions to be signed offline.
+pub struct Wallet {
+    signer: Option<Box<dyn Signer>>,
+    public_key: PublicKey,
+    nonce: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AirdropStatus {
+    pub recipient: Address,
+    pub amount: u64,
+    pub attempts: usize,
+    /// id of the accepted transaction or the last error
+    pub result: Result<TxId, WalletError>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepStatus {
+    pub from: PublicKey,
+    /// balance left once the fee is paid
+    pub amount: u64,
+    pub fee: u64,
+    /// id of the sweeping transaction, only built and signed in a dry run
+    pub result: Result<TxId, WalletError>,
+}
+
+/// Move the whole balance of every signer, less the fee chosen by
+/// `fee_policy`, to `to`, e.g. when retiring cold storage. Empty accounts and
+/// `to` itself are skipped, nothing is submitted in a dry run.
+pub fn sweep(
+    blockchain: &mut Blockchain,
+    signers: Vec<Box<dyn Signer>>,
+    to: &Address,
+    fee_policy: &FeePolicy,
+    dry_run: bool,
+) -> Vec<SweepStatus> {
+    signers
+        .into_iter()
+        .filter_map(|signer| {
+            let mut wallet = Wallet::from_boxed_signer(signer);
+            wallet.sync(blockchain);
+            let balance = wallet.balance(blockchain);
+            if balance == 0 || wallet.address() == *to {
+                return None;
+            }
+            let fee = fee_policy.fee(blockchain);
+            let amount = balance.saturating_sub(fee);
+            let result = if amount == 0 {
+                Err(WalletError::Transaction(TxError::InsufficientFunds {
+                    balance,
+                    required: fee.saturating_add(1),
+                }))
+            } else {
+                let mut tx = wallet.build_transfer(to, amount);
+                tx.set_fee(fee);
+                match wallet.sign(&mut tx) {
+                    Ok(()) if dry_run => Ok(tx.id()),
+                    Ok(()) => wallet.submit(blockchain, tx),
+                    Err(e) => Err(e),
+                }
+            };
+            Some(SweepStatus {
+                from: wallet.public_key(),
+                amount,
+                fee,
+                result,
+            })
+        })
+        .collect()
+}
+
+/// Index an exchange gives each of its users so they can all deposit to
+/// one hot address, carried on chain as the payment reference of the
+/// deposit. Unlike an invoice reference, a hash, it can be read back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DestinationTag(pub u64);
+
+impl DestinationTag {
+    pub fn reference(&self) -> Hash {
+        let mut bytes = [0; 32];
+        bytes[..24].copy_from_slice(DESTINATION_TAG_PREFIX);
+        bytes[24..].copy_from_slice(&self.0.to_be_bytes());
+        // cannot fail, the slice is 32 bytes long
+        Hash::from_slice(&bytes).unwrap_or_default()
+    }
+
+    /// the tag `reference` holds, `None` for references of another kind
+    pub fn from_reference(reference: &Hash) -> Option<DestinationTag> {
+        let (prefix, index) = reference.as_ref().split_at(24);
+        if prefix != DESTINATION_TAG_PREFIX {
+            return None;
+        }
+        let index = index.try_into().ok()?;
+        Some(DestinationTag(u64::from_be_bytes(index)))
+    }
+
+    /// tag of a deposit, to credit the user it was given to
+    pub fn of_transaction(tx: &Transaction) -> Option<DestinationTag> {
+        DestinationTag::from_reference(&tx.reference()?)
+    }
+}
+
+impl fmt::Display for DestinationTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Payment request as found in a URI or QR code, the address is the bech32
+/// address of the recipient and the amount is in base units
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentRequest {
+    pub recipient: Address,
+    pub amount: Option<u64>,
+    pub memo: Option<String>,
+    /// set on the paying transaction, in hex in the URI or as `tag` when
+    /// it holds a destination tag
+    pub reference: Option<Hash>,
+}
+
+impl PaymentRequest {
+    pub fn new(recipient: &Address) -> PaymentRequest {
+        PaymentRequest {
+            recipient: *recipient,
+            amount: None,
+            memo: None,
+            reference: None,
+        }
+    }
+
+    /// request for a deposit of the user given `tag`
+    pub fn with_destination_tag(recipient: &Address, tag: DestinationTag) -> PaymentRequest {
+        PaymentRequest {
+            reference: Some(tag.reference()),
+            ..PaymentRequest::new(recipient)
+        }
+    }
+
+    pub fn destination_tag(&self) -> Option<DestinationTag> {
+        DestinationTag::from_reference(self.reference.as_ref()?)
+    }
+
+    /// URI with the address of the recipient on `network`
+    pub fn to_uri(&self, network: Network) -> String {
+        let mut uri = format!("{}:{}", PAYMENT_URI_SCHEME, self.recipient.encode(network));
+        let mut params = vec![];
+        if let Some(amount) = self.amount {
+            params.push(format!("amount={}", amount));
+        }
+        if let Some(memo) = &self.memo {
+            params.push(format!("memo={}", percent_encode(memo)));
+        }
+        match (self.destination_tag(), &self.reference) {
+            (Some(tag), _) => params.push(format!("tag={}", tag)),
+            (None, Some(reference)) => params.push(format!("reference={}", hex::encode(reference))),
+            (None, None) => {}
+        }
+        if !params.is_empty() {
+            uri.push('?');
+            uri.push_str(&params.join("&"));
+        }
+        uri
+    }
+
+    /// Unknown parameters are ignored unless prefixed with `req-`, which
+    /// marks them as required to understand the request. An address of
+    /// another network than `network` is invalid.
+    pub fn parse(uri: &str, network: Network) -> Result<PaymentRequest, PaymentUriError> {
+        let (scheme, rest) = uri
+            .trim()
+            .split_once(':')
+            .ok_or(PaymentUriError::MissingScheme)?;
+        if !scheme.eq_ignore_ascii_case(PAYMENT_URI_SCHEME) {
+            return Err(PaymentUriError::UnsupportedScheme(scheme.to_string()));
+        }
+        let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let recipient =
+            Address::decode(address, network).map_err(|_| PaymentUriError::InvalidAddress)?;
+
+        let mut request = PaymentRequest::new(&recipient);
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            match key {
+                "amount" if request.amount.is_none() => {
+                    request.amount =
+                        Some(value.parse().map_err(|_| PaymentUriError::InvalidAmount)?);
+                }
+                "memo" if request.memo.is_none() => {
+                    request.memo = Some(percent_decode(value)?);
+                }
+                "reference" if request.reference.is_none() => {
+                    let bytes =
+                        hex::decode(value).map_err(|_| PaymentUriError::InvalidReference)?;
+                    request.reference = Some(
+                        Hash::from_slice(&bytes).map_err(|_| PaymentUriError::InvalidReference)?,
+                    );
+                }
+                "tag" if request.reference.is_none() => {
+                    let index = value
+                        .parse()
+                        .map_err(|_| PaymentUriError::InvalidDestinationTag)?;
+                    request.reference = Some(DestinationTag(index).reference());
+                }
+                "amount" | "memo" | "reference" | "tag" => {
+                    return Err(PaymentUriError::DuplicateParameter(key.to_string()))
+                }
+                _ if key.starts_with("req-") => {
+                    return Err(PaymentUriError::UnsupportedRequiredParameter(
+                        key.to_string(),
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+}
+
+/// everything outside the unreserved set of RFC 3986 is escaped
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> Result<String, PaymentUriError> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+    while let Some(byte) = input.next() {
+        match byte {
+            b'%' => {
+                let escaped = [input.next(), input.next()]
+                    .into_iter()
+                    .collect::<Option<Vec<u8>>>()
+                    .ok_or(PaymentUriError::InvalidPercentEncoding)?;
+                let decoded =
+                    hex::decode(escaped).map_err(|_| PaymentUriError::InvalidPercentEncoding)?;
+                bytes.extend(decoded);
+            }
+            b'+' => bytes.push(b' '),
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| PaymentUriError::InvalidMemo)
+}
+
+impl Wallet {
+    pub fn new(private_key: &PrivateKey) -> Result<Wallet, WalletError> {
+        let signer = LocalSigner::new(private_key)?;
+        Ok(Wallet::with_signer(signer))
+    }
+
+    /// wallet signing through an external signer, e.g. a `HardwareSigner`
+    pub fn with_signer(signer: impl Signer + 'static) -> Wallet {
+        Wallet::from_boxed_signer(Box::new(signer))
+    }
+
+    fn from_boxed_signer(signer: Box<dyn Signer>) -> Wallet {
+        Wallet {
+            public_key: signer.public_key(),
+            signer: Some(signer),
+            nonce: 0,
+        }
+    }
+
+    pub fn watch_only(public_key: &PublicKey) -> Wallet {
+        Wallet {
+            signer: None,
+            public_key: *public_key,
+            nonce: 0,
+        }
+    }
+
+    pub fn generate() -> Wallet {
+        Wallet::with_signer(LocalSigner::from_keypair(generate_keypair()))
+    }
+
+    pub fn from_secret(secret: &[u8]) -> Wallet {
+        Wallet::with_signer(LocalSigner::from_keypair(generate_keypair_from_secret(
+            secret,
+        )))
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.public_key
+    }
+
+    pub fn address(&self) -> Address {
+        self.public_key.address()
+    }
+
+    pub fn is_watch_only(&self) -> bool {
+        self.signer.is_none()
+    }
+
+    /// nonce that will be used by the next transaction
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    pub fn balance(&self, blockchain: &Blockchain) -> u64 {
+        blockchain
+            .get_account(&self.address())
+            .map(|account| account.balance())
+            .unwrap_or(0)
+    }
+
+    /// reload the nonce from the chain state
+    pub fn sync(&mut self, blockchain: &Blockchain) {
+        self.nonce = blockchain
+            .get_account(&self.address())
+            .map(|account| account.nonce())
+            .unwrap_or(0);
+    }
+
+    /// unsigned transfer using the current nonce
+    pub fn build_transfer(&self, to: &Address, amount: u64) -> Transaction {
+        Transaction::new(&self.public_key, to, amount, self.nonce)
+    }
+
+    /// unsigned transfer prefilled from a payment request
+    pub fn build_payment(&self, request: &PaymentRequest) -> Result<Transaction, WalletError> {
+        let amount = request.amount.ok_or(WalletError::MissingAmount)?;
+        let mut tx = self.build_transfer(&request.recipient, amount);
+        tx.set_reference(request.reference);
+        Ok(tx)
+    }
+
+    pub fn sign(&self, tx: &mut Transaction) -> Result<(), WalletError> {
+        let signer = self.signer.as_ref().ok_or(WalletError::WatchOnly)?;
+        if tx.from() != self.public_key {
+            return Err(WalletError::NotFromWallet);
+        }
+        Ok(signer.sign_transaction(tx)?)
+    }
+
+    pub fn sign_transfer(&self, to: &Address, amount: u64) -> Result<Transaction, WalletError> {
+        let mut tx = self.build_transfer(to, amount);
+        self.sign(&mut tx)?;
+        Ok(tx)
+    }
+
+    /// sign a transaction exported with `Transaction::to_bytes`, returning the
+    /// signed encoding
+    pub fn sign_offline(&self, unsigned: &[u8]) -> Result<Vec<u8>, WalletError> {
+        let mut tx = Transaction::from_bytes(unsigned)?;
+        self.sign(&mut tx)?;
+        Ok(tx.to_bytes())
+    }
+
+    /// submit a transaction signed by this wallet, possibly offline
+    pub fn submit(
+        &mut self,
+        blockchain: &mut Blockchain,
+        tx: Transaction,
+    ) -> Result<TxId, WalletError> {
+        if tx.from() != self.public_key {
+            return Err(WalletError::NotFromWallet);
+        }
+        let id = tx.id();
+        let nonce = tx.nonce();
+        blockchain.add_transaction(tx)?;
+        self.nonce = nonce + 1;
+        Ok(id)
+    }
+
+    pub fn transfer(
+        &mut self,
+        blockchain: &mut Blockchain,
+        to: &Address,
+        amount: u64,
+    ) -> Result<TxId, WalletError> {
+        let tx = self.sign_transfer(to, amount)?;
+        self.submit(blockchain, tx)
+    }
+
+    /// Pay every recipient its amount as sequential single transfers: one
+    /// signed transaction and nonce per recipient, submitted in order.
+    /// This is not a batch, the airdrop is not atomic and recipients before a
+    /// failure stay paid. A failed transfer is retried when resyncing the nonce
+    /// from the chain changes it, the other recipients are processed regardless
+    /// of the outcome.
+    pub fn airdrop(
+        &mut self,
+        blockchain: &mut Blockchain,
+        recipients: &[(Address, u64)],
+    ) -> Vec<AirdropStatus> {
+        self.sync(blockchain);
+        recipients
+            .iter()
+            .map(|(recipient, amount)| {
+                let mut attempts = 0;
+                let result = loop {
+                    attempts += 1;
+                    match self.transfer(blockchain, recipient, *amount) {
+                        Ok(id) => break Ok(id),
+                        Err(e) if attempts >= AIRDROP_MAX_ATTEMPTS => break Err(e),
+                        Err(e) => {
+                            let nonce = self.nonce;
+                            self.sync(blockchain);
+                            if self.nonce == nonce {
+                                break Err(e);
+                            }
+                        }
+                    }
+                };
+                AirdropStatus {
+                    recipient: *recipient,
+                    amount: *amount,
+                    attempts,
+                    result,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watch_only_offline_signing() {
+        let signer = Wallet::from_secret(b"cold");
+        let mut watcher = Wallet::watch_only(&signer.public_key());
+        let genesis = signer.sign_transfer(&signer.address(), 100).unwrap();
+        let mut blockchain = Blockchain::new(genesis, 0).unwrap();
+        watcher.sync(&blockchain);
+        assert_eq!(watcher.balance(&blockchain), 100);
+
+        let to = Wallet::generate().address();
+        let unsigned = watcher.build_transfer(&to, 40);
+        assert!(watcher.sign(&mut unsigned.clone()).is_err());
+
+        let signed = signer.sign_offline(&unsigned.to_bytes()).unwrap();
+        let tx = Transaction::from_bytes(&signed).unwrap();
+        watcher.submit(&mut blockchain, tx).unwrap();
+        assert_eq!(watcher.nonce(), 1);
+        assert_eq!(watcher.balance(&blockchain), 60);
+    }
+
+    #[test]
+    fn test_sweep() {
+        let (cold_key, cold) = generate_keypair();
+        let (empty_key, _) = generate_keypair();
+        let target = generate_keypair().1.address();
+        let genesis = Transaction::new_and_sign(&cold, &cold.address(), 100, 0, &cold_key).unwrap();
+        let mut blockchain = Blockchain::new(genesis, 0).unwrap();
+        let signers = || -> Vec<Box<dyn Signer>> {
+            [cold_key, empty_key]
+                .iter()
+                .map(|key| Box::new(LocalSigner::new(key).unwrap()) as Box<dyn Signer>)
+                .collect()
+        };
+        let fee_policy = FeePolicy::Fixed(3);
+
+        let dry_run = sweep(&mut blockchain, signers(), &target, &fee_policy, true);
+        assert_eq!(dry_run.len(), 1);
+        assert_eq!((dry_run[0].amount, dry_run[0].fee), (97, 3));
+        assert!(blockchain.get_account(&target).is_none());
+
+        let swept = sweep(&mut blockchain, signers(), &target, &fee_policy, false);
+        assert_eq!(swept, dry_run);
+        assert_eq!(blockchain.get_account(&target).unwrap().balance(), 97);
+        assert_eq!(
+            blockchain.get_account(&cold.address()).unwrap().balance(),
+            0
+        );
+        assert!(sweep(&mut blockchain, signers(), &target, &fee_policy, false).is_empty());
+    }
+
+    #[test]
+    fn test_payment_uri_round_trip() {
+        let recipient = Wallet::generate().address();
+        let mut request = PaymentRequest::new(&recipient);
+        assert_eq!(
+            PaymentRequest::parse(&request.to_uri(Network::Mainnet), Network::Mainnet),
+            Ok(request.clone())
+        );
+
+        request.amount = Some(250);
+        request.memo = Some("order #42 & co".to_string());
+        request.reference = Some(Hash::from(&b"order #42"[..]));
+        let uri = request.to_uri(Network::Mainnet);
+        assert!(uri.contains("?amount=250&memo=order%20%2342%20%26%20co&reference="));
+        assert_eq!(
+            PaymentRequest::parse(&uri, Network::Mainnet),
+            Ok(request.clone())
+        );
+        let extra = format!("{}&label=shop", uri);
+        assert_eq!(
+            PaymentRequest::parse(&extra, Network::Mainnet),
+            Ok(request.clone())
+        );
+        assert_eq!(
+            PaymentRequest::parse(&uri, Network::Testnet),
+            Err(PaymentUriError::InvalidAddress)
+        );
+
+        let payer = Wallet::generate();
+        let tx = payer.build_payment(&request).unwrap();
+        assert_eq!(tx.to(), Some(recipient));
+        assert_eq!(tx.amount(), 250);
+        assert_eq!(tx.reference(), request.reference);
+
+        let address = recipient.encode(Network::Mainnet);
+        for invalid in [
+            format!("bitcoin:{}", address),
+            format!("coreledger:{}", &address[..address.len() - 1]),
+            format!("coreledger:{}?amount=-1", address),
+            format!("coreledger:{}?amount=1&amount=2", address),
+            format!("coreledger:{}?memo=%zz", address),
+            format!("coreledger:{}?reference=00", address),
+            format!("coreledger:{}?req-fee=1", address),
+        ] {
+            assert!(
+                PaymentRequest::parse(&invalid, Network::Mainnet).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_destination_tags() {
+        let exchange = Wallet::generate().address();
+        let request = PaymentRequest::with_destination_tag(&exchange, DestinationTag(42));
+        let uri = request.to_uri(Network::Mainnet);
+        assert!(uri.ends_with("?tag=42"));
+        let parsed = PaymentRequest::parse(&uri, Network::Mainnet).unwrap();
+        assert_eq!(parsed, request);
+        assert_eq!(parsed.destination_tag(), Some(DestinationTag(42)));
+
+        let user = Wallet::generate();
+        let mut deposit = user
+            .build_payment(&PaymentRequest {
+                amount: Some(10),
+                ..parsed
+            })
+            .unwrap();
+        assert_eq!(
+            DestinationTag::of_transaction(&deposit),
+            Some(DestinationTag(42))
+        );
+        deposit.set_reference(Some(Hash::from(&b"invoice"[..])));
+        assert_eq!(DestinationTag::of_transaction(&deposit), None);
+
+        let address = exchange.encode(Network::Mainnet);
+        for invalid in [
+            format!("coreledger:{}?tag=-1", address),
+            format!("coreledger:{}?tag=1&tag=2", address),
+            format!("coreledger:{}?reference={}&tag=1", address, "00".repeat(32)),
+        ] {
+            assert!(
+                PaymentRequest::parse(&invalid, Network::Mainnet).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_airdrop() {
+        let mut wallet = Wallet::from_secret(b"airdrop");
+        let genesis = wallet.sign_transfer(&wallet.address(), 100).unwrap();
+        let mut blockchain = Blockchain::new(genesis, 0).unwrap();
+        let recipients: Vec<(Address, u64)> = [10, 500, 20, 30]
+            .into_iter()
+            .map(|amount| (Wallet::generate().address(), amount))
+            .collect();
+
+        let statuses = wallet.airdrop(&mut blockchain, &recipients);
+        assert_eq!(
+            statuses
+                .iter()
+                .map(|status| (status.recipient, status.amount, status.attempts))
+                .collect::<Vec<_>>(),
+            recipients
+                .iter()
+                .map(|(recipient, amount)| (*recipient, *amount, 1))
+                .collect::<Vec<_>>()
+        );
+        // the failed recipient does not stop the others nor skip a nonce
+        assert_eq!(
+            statuses[1].result,
+            Err(WalletError::Transaction(TxError::InsufficientFunds {
+                balance: 90,
+                required: 500
+            }))
+        );
+        let pending = blockchain.pending_block().transactions();
+        assert_eq!(
+            pending
+                .iter()
+                .map(|tx| (tx.nonce(), tx.to().unwrap(), tx.amount()))
+                .collect::<Vec<_>>(),
+            [0, 2, 3]
+                .iter()
+                .enumerate()
+                .map(|(nonce, i)| (nonce as u64, recipients[*i].0, recipients[*i].1))
+                .collect::<Vec<_>>()
+        );
+        for (i, tx) in [0, 2, 3].iter().zip(pending) {
+            assert_eq!(statuses[*i].result, Ok(tx.id()));
+        }
+        assert_eq!(wallet.nonce(), 3);
+        assert_eq!(wallet.balance(&blockchain), 40);
+
+        // the next distribution continues from the confirmed nonce
+        blockchain.finalize_and_mint_pending_block().unwrap();
+        let statuses = wallet.airdrop(&mut blockchain, &recipients[..1]);
+        assert!(statuses[0].result.is_ok());
+        assert_eq!(blockchain.pending_block().transactions()[0].nonce(), 3);
+    }
+}
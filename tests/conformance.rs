@@ -0,0 +1,300 @@
+//! Golden consensus vectors, stored as JSON under `tests/vectors` so other
+//! implementations can run them too. Binary values are hex strings, blocks
+//! use their serde representation. Run with `UPDATE_VECTORS=1` to rewrite
+//! the files after an intended consensus change.
+
+use std::fs;
+use std::path::PathBuf;
+
+use core_ledger::{
+    generate_keypair_from_secret, sign_hash, verify_signature, Block, BlockBuilder, Blockchain,
+    ConsensusConfig, Hash, Mmr, PrivateKey, PublicKey, Signature, Transaction,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct HashVector {
+    input: String,
+    hash: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SignatureVector {
+    private_key: String,
+    public_key: String,
+    hash: String,
+    signature: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TransactionVector {
+    encoded: String,
+    signing_hash: String,
+    id: String,
+    valid: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BlockVector {
+    name: String,
+    block: Block,
+    valid: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AccountState {
+    address: String,
+    balance: u64,
+    nonce: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PostState {
+    state_root: String,
+    total_supply: u64,
+    accounts: Vec<AccountState>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StateVector {
+    name: String,
+    blocks: Vec<Block>,
+    /// `None` when replaying the blocks must fail
+    post_state: Option<PostState>,
+}
+
+fn load<T: Serialize + DeserializeOwned>(name: &str, generate: fn() -> Vec<T>) -> Vec<T> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/vectors")
+        .join(format!("{}.json", name));
+    if std::env::var_os("UPDATE_VECTORS").is_some() {
+        let json = serde_json::to_string_pretty(&generate()).unwrap();
+        fs::write(&path, json + "\n").unwrap();
+    }
+    serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap()
+}
+
+fn keypair(secret: &str) -> (PrivateKey, PublicKey) {
+    generate_keypair_from_secret(secret.as_bytes())
+}
+
+fn post_state(blockchain: &Blockchain) -> PostState {
+    let mut accounts: Vec<AccountState> = blockchain
+        .accounts()
+        .map(|account| AccountState {
+            address: hex::encode(account.address()),
+            balance: account.balance(),
+            nonce: account.nonce(),
+        })
+        .collect();
+    accounts.sort_by(|a, b| a.address.cmp(&b.address));
+    PostState {
+        state_root: hex::encode(blockchain.state_root()),
+        total_supply: blockchain.total_supply(),
+        accounts,
+    }
+}
+
+/// genesis funding `alice`, then one block per list of transactions
+fn build_chain(blocks: Vec<Vec<Transaction>>) -> Vec<Block> {
+    let (alice_key, alice) = keypair("alice");
+    let genesis_tx =
+        Transaction::new_and_sign(&alice, &alice.address(), 1_000, 0, &alice_key).unwrap();
+    let mut chain = vec![Block::new_genesis(vec![genesis_tx], 0).unwrap()];
+    let mut history = Mmr::new();
+    for (i, transactions) in blocks.into_iter().enumerate() {
+        let previous = chain.last().unwrap();
+        history.push(&previous.hash());
+        let mut builder = BlockBuilder::new(previous.index() + 1, &previous.hash());
+        builder.set_history_root(&history.root());
+        for tx in transactions {
+            builder.add_transaction(tx).unwrap();
+        }
+        chain.push(builder.build_at(10 * (i as u64 + 1)));
+    }
+    chain
+}
+
+fn generate_hash_vectors() -> Vec<HashVector> {
+    [&b""[..], b"abc", &[0u8; 64]]
+        .iter()
+        .map(|input| HashVector {
+            input: hex::encode(input),
+            hash: hex::encode(Hash::from(*input)),
+        })
+        .collect()
+}
+
+fn generate_signature_vectors() -> Vec<SignatureVector> {
+    ["alice", "bob"]
+        .iter()
+        .map(|secret| {
+            let (private_key, public_key) = keypair(secret);
+            let hash = Hash::from(secret.as_bytes());
+            SignatureVector {
+                private_key: hex::encode(private_key.expose_secret()),
+                public_key: hex::encode(public_key),
+                hash: hex::encode(hash),
+                signature: hex::encode(sign_hash(&hash, &private_key).unwrap()),
+            }
+        })
+        .collect()
+}
+
+fn generate_transaction_vectors() -> Vec<TransactionVector> {
+    let (alice_key, alice) = keypair("alice");
+    let (_, bob) = keypair("bob");
+    let transfer = Transaction::new_and_sign(&alice, &bob.address(), 10, 0, &alice_key).unwrap();
+    let mut burn = Transaction::new_burn(&alice, 5, 1);
+    burn.sign(&alice_key).unwrap();
+    let mut forged = Transaction::new(&alice, &bob.address(), 11, 0);
+    forged.set_signature(&transfer.signature());
+    [transfer, burn, forged]
+        .iter()
+        .map(|tx| TransactionVector {
+            encoded: hex::encode(tx.to_bytes()),
+            signing_hash: hex::encode(tx.signing_hash()),
+            id: hex::encode(tx.id()),
+            valid: tx.verify().is_ok(),
+        })
+        .collect()
+}
+
+fn generate_block_vectors() -> Vec<BlockVector> {
+    let (alice_key, alice) = keypair("alice");
+    let (_, bob) = keypair("bob");
+    let tx = Transaction::new_and_sign(&alice, &bob.address(), 10, 0, &alice_key).unwrap();
+    let chain = build_chain(vec![vec![tx]]);
+    let tamper = |field: &str, value: serde_json::Value| {
+        let mut block = serde_json::to_value(&chain[1]).unwrap();
+        block[field] = value;
+        serde_json::from_value(block).unwrap()
+    };
+    vec![
+        BlockVector {
+            name: "genesis".to_string(),
+            block: chain[0].clone(),
+            valid: true,
+        },
+        BlockVector {
+            name: "transfer".to_string(),
+            block: chain[1].clone(),
+            valid: true,
+        },
+        BlockVector {
+            name: "timestamp changed after hashing".to_string(),
+            block: tamper("timestamp", 11.into()),
+            valid: false,
+        },
+        BlockVector {
+            name: "transaction removed".to_string(),
+            block: tamper("transactions", serde_json::json!([])),
+            valid: false,
+        },
+        BlockVector {
+            name: "extra data too large".to_string(),
+            block: tamper("extra_data", serde_json::json!(vec![0u8; 33])),
+            valid: false,
+        },
+    ]
+}
+
+fn generate_state_vectors() -> Vec<StateVector> {
+    let (alice_key, alice) = keypair("alice");
+    let (_, bob) = keypair("bob");
+    let transfer = Transaction::new_and_sign(&alice, &bob.address(), 10, 0, &alice_key).unwrap();
+    let mut burn = Transaction::new_burn(&alice, 100, 1);
+    burn.sign(&alice_key).unwrap();
+    let replayed = Transaction::new_and_sign(&alice, &bob.address(), 10, 0, &alice_key).unwrap();
+    let overdraft =
+        Transaction::new_and_sign(&alice, &bob.address(), 5_000, 0, &alice_key).unwrap();
+
+    let vector = |name: &str, blocks: Vec<Vec<Transaction>>| {
+        let blocks = build_chain(blocks);
+        let post_state = Blockchain::from_blocks(blocks.clone(), ConsensusConfig::default())
+            .ok()
+            .map(|blockchain| post_state(&blockchain));
+        StateVector {
+            name: name.to_string(),
+            blocks,
+            post_state,
+        }
+    };
+    vec![
+        vector(
+            "transfer then burn",
+            vec![vec![transfer.clone()], vec![burn]],
+        ),
+        vector("nonce replay", vec![vec![transfer, replayed]]),
+        vector("overdraft", vec![vec![overdraft]]),
+    ]
+}
+
+#[test]
+fn test_hash_vectors() {
+    for vector in load("hash", generate_hash_vectors) {
+        let input = hex::decode(&vector.input).unwrap();
+        assert_eq!(hex::encode(Hash::from(input.as_ref())), vector.hash);
+    }
+}
+
+#[test]
+fn test_signature_vectors() {
+    for vector in load("signature", generate_signature_vectors) {
+        let private_key = hex::decode(&vector.private_key).unwrap();
+        let private_key = PrivateKey::try_from(private_key.as_slice()).unwrap();
+        let public_key = hex::decode(&vector.public_key).unwrap();
+        let public_key = PublicKey::try_from(public_key.as_slice()).unwrap();
+        let hash = Hash::from_slice(&hex::decode(&vector.hash).unwrap()).unwrap();
+        let signature = hex::decode(&vector.signature).unwrap();
+        let signature = Signature::try_from(signature.as_slice()).unwrap();
+        assert_eq!(PublicKey::try_from(&private_key).unwrap(), public_key);
+        assert_eq!(sign_hash(&hash, &private_key).unwrap(), signature);
+        verify_signature(&public_key, &hash, &signature).unwrap();
+    }
+}
+
+#[test]
+fn test_transaction_vectors() {
+    for vector in load("transaction", generate_transaction_vectors) {
+        let tx = Transaction::from_bytes(&hex::decode(&vector.encoded).unwrap()).unwrap();
+        assert_eq!(hex::encode(tx.signing_hash()), vector.signing_hash);
+        assert_eq!(hex::encode(tx.id()), vector.id);
+        assert_eq!(tx.verify().is_ok(), vector.valid);
+    }
+}
+
+#[test]
+fn test_block_vectors() {
+    for vector in load("block", generate_block_vectors) {
+        assert_eq!(
+            vector.block.verify().is_ok(),
+            vector.valid,
+            "{}",
+            vector.name
+        );
+    }
+}
+
+#[test]
+fn test_state_vectors() {
+    for vector in load("state", generate_state_vectors) {
+        let result = Blockchain::from_blocks(vector.blocks, ConsensusConfig::default());
+        match (result, vector.post_state) {
+            (Ok(blockchain), Some(expected)) => {
+                let state = post_state(&blockchain);
+                assert_eq!(state.state_root, expected.state_root, "{}", vector.name);
+                assert_eq!(state.total_supply, expected.total_supply, "{}", vector.name);
+                assert_eq!(
+                    serde_json::to_value(&state.accounts).unwrap(),
+                    serde_json::to_value(&expected.accounts).unwrap(),
+                    "{}",
+                    vector.name
+                );
+            }
+            (Err(_), None) => {}
+            (result, _) => panic!("{}: unexpected result {:?}", vector.name, result.err()),
+        }
+    }
+}